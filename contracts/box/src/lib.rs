@@ -38,6 +38,11 @@ impl Boxen {
     pub fn get(&self) -> Option<i16> {
         self.a.as_ref().map(|i| **i)
     }
+
+    /// Return the hash of the contract's memory
+    pub fn state_hash(&self) -> [u8; 32] {
+        uplink::self_state_hash()
+    }
 }
 
 /// Expose `Boxen::set()` to the host
@@ -51,3 +56,9 @@ unsafe fn set(arg_len: u32) -> u32 {
 unsafe fn get(arg_len: u32) -> u32 {
     uplink::wrap_call(arg_len, |_: ()| STATE.get())
 }
+
+/// Expose `Boxen::state_hash()` to the host
+#[no_mangle]
+unsafe fn state_hash(arg_len: u32) -> u32 {
+    uplink::wrap_call(arg_len, |_: ()| STATE.state_hash())
+}
@@ -80,6 +80,11 @@ impl Callcenter {
         uplink::caller()
     }
 
+    /// Return the call stack of this contract
+    pub fn return_caller_stack(&self) -> Vec<ContractId> {
+        uplink::caller_stack()
+    }
+
     /// Make sure that the caller of this contract is the contract itself
     pub fn call_self(&self) -> Result<bool, ContractError> {
         let self_id = uplink::self_id();
@@ -92,6 +97,20 @@ impl Callcenter {
         }
     }
 
+    /// Recursively call this contract `depth` times, returning the number of
+    /// calls made.
+    pub fn recurse(&self, depth: u32) -> u32 {
+        match depth {
+            0 => 0,
+            _ => {
+                let calls: u32 =
+                    uplink::call(uplink::self_id(), "recurse", &(depth - 1))
+                        .expect("calling self should succeed");
+                calls + 1
+            }
+        }
+    }
+
     /// Calls the `spend` function of the `contract` with no arguments, and the
     /// given `gas_limit`, assuming the called function returns `()`. It will
     /// then return the call's result itself.
@@ -135,6 +154,12 @@ unsafe fn call_self(arg_len: u32) -> u32 {
     wrap_call(arg_len, |_: ()| STATE.call_self())
 }
 
+/// Expose `Callcenter::recurse()` to the host
+#[no_mangle]
+unsafe fn recurse(arg_len: u32) -> u32 {
+    wrap_call(arg_len, |depth| STATE.recurse(depth))
+}
+
 /// Expose `Callcenter::call_spend_with_limit` to the host
 #[no_mangle]
 unsafe fn call_spend_with_limit(arg_len: u32) -> u32 {
@@ -155,6 +180,12 @@ unsafe fn return_caller(arg_len: u32) -> u32 {
     wrap_call(arg_len, |_: ()| STATE.return_caller())
 }
 
+/// Expose `Callcenter::return_caller_stack()` to the host
+#[no_mangle]
+unsafe fn return_caller_stack(arg_len: u32) -> u32 {
+    wrap_call(arg_len, |_: ()| STATE.return_caller_stack())
+}
+
 /// Expose `Callcenter::delegate_query()` to the host
 #[no_mangle]
 unsafe fn delegate_query(arg_len: u32) -> u32 {
@@ -24,6 +24,12 @@ impl Debug {
         uplink::debug!("What a string! {}", string);
     }
 
+    /// Print debug information with the given level
+    pub fn log(&self, level: u32, string: alloc::string::String) {
+        let level = uplink::DebugLevel::from_u32(level).unwrap();
+        uplink::log!(level, "{}", string);
+    }
+
     /// Panic execution
     pub fn panic(&self) {
         panic!("It's never too late to panic");
@@ -35,3 +41,9 @@ impl Debug {
 unsafe fn debug(arg_len: u32) -> u32 {
     uplink::wrap_call(arg_len, |s: alloc::string::String| STATE.debug(s))
 }
+
+/// Expose `Debug::log()` to the host
+#[no_mangle]
+unsafe fn log(arg_len: u32) -> u32 {
+    uplink::wrap_call(arg_len, |(level, s)| STATE.log(level, s))
+}
@@ -9,6 +9,7 @@
 #![no_std]
 
 use piecrust_uplink as uplink;
+use uplink::ContractId;
 
 /// Struct that describes the state of the eventer contract
 pub struct Eventer;
@@ -23,6 +24,18 @@ impl Eventer {
             uplink::emit("number", i);
         }
     }
+
+    /// Emits an event with the given number, tagged with the given version
+    pub fn emit_num_typed(&mut self, version: u16, num: u32) {
+        uplink::emit_typed("number", version, num);
+    }
+
+    /// Emits an event with the given number, and makes the given contract do
+    /// the same
+    pub fn emit_num_and_forward(&mut self, num: u32, target: ContractId) {
+        self.emit_num(num);
+        uplink::call::<_, ()>(target, "emit_events", &num).unwrap();
+    }
 }
 
 /// Expose `Eventer::emit_num()` to the host
@@ -30,3 +43,19 @@ impl Eventer {
 unsafe fn emit_events(arg_len: u32) -> u32 {
     uplink::wrap_call(arg_len, |num| STATE.emit_num(num))
 }
+
+/// Expose `Eventer::emit_num_and_forward()` to the host
+#[no_mangle]
+unsafe fn emit_events_and_forward(arg_len: u32) -> u32 {
+    uplink::wrap_call(arg_len, |(num, target)| {
+        STATE.emit_num_and_forward(num, target)
+    })
+}
+
+/// Expose `Eventer::emit_num_typed()` to the host
+#[no_mangle]
+unsafe fn emit_typed_event(arg_len: u32) -> u32 {
+    uplink::wrap_call(arg_len, |(version, num)| {
+        STATE.emit_num_typed(version, num)
+    })
+}
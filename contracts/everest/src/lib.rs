@@ -21,6 +21,16 @@ impl Height {
     pub fn get_height(&self) -> Option<u64> {
         uplink::meta_data::<u64>("height")
     }
+
+    /// Query the host for the current time
+    pub fn get_time(&self) -> u64 {
+        uplink::host_query("time", ())
+    }
+
+    /// Query the host for the global block height
+    pub fn get_global_height(&self) -> Option<u64> {
+        uplink::global_meta_data::<u64>("height")
+    }
 }
 
 /// Expose `Height::get_height()` to the host
@@ -28,3 +38,15 @@ impl Height {
 unsafe fn get_height(a: u32) -> u32 {
     uplink::wrap_call(a, |_: ()| STATE.get_height())
 }
+
+/// Expose `Height::get_global_height()` to the host
+#[no_mangle]
+unsafe fn get_global_height(a: u32) -> u32 {
+    uplink::wrap_call(a, |_: ()| STATE.get_global_height())
+}
+
+/// Expose `Height::get_time()` to the host
+#[no_mangle]
+unsafe fn get_time(a: u32) -> u32 {
+    uplink::wrap_call(a, |_: ()| STATE.get_time())
+}
@@ -32,6 +32,14 @@ impl FallibleCounter {
             panic!("Incremental panic");
         }
     }
+
+    /// Destruct the contract and panic if wanted
+    pub fn destruct(&mut self, panic: bool) {
+        uplink::self_destruct();
+        if panic {
+            panic!("Destructive panic");
+        }
+    }
 }
 
 /// Expose `FallibleCounter::read_value()` to the host
@@ -45,3 +53,9 @@ unsafe fn read_value(arg_len: u32) -> u32 {
 unsafe fn increment(arg_len: u32) -> u32 {
     uplink::wrap_call(arg_len, |panic: bool| STATE.increment(panic))
 }
+
+/// Expose `FallibleCounter::destruct()` to the host
+#[no_mangle]
+unsafe fn destruct(arg_len: u32) -> u32 {
+    uplink::wrap_call(arg_len, |panic: bool| STATE.destruct(panic))
+}
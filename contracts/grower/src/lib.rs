@@ -60,6 +60,63 @@ impl Grower {
         })
     }
 
+    /// Parses an offset from the argument buffer, and writes the bytes
+    /// following it onto the state vector at said offset, using the host to
+    /// perform the copy.
+    fn host_write_at(&mut self, arg_len: usize) {
+        let (offset, bytes) = with_arg_buf(|buf| {
+            if arg_len < 4 {
+                panic!("Bad arguments");
+            }
+
+            let mut offset_bytes = [0; 4];
+            offset_bytes.copy_from_slice(&buf[..4]);
+            let offset = u32::from_le_bytes(offset_bytes) as usize;
+
+            (offset, buf[4..arg_len].to_vec())
+        });
+
+        if offset + bytes.len() > self.0.len() {
+            panic!("Out of bounds");
+        }
+
+        let ptr = self.0.as_mut_ptr() as usize + offset;
+        uplink::host_write(ptr, &bytes);
+    }
+
+    /// Same as `view`, but using the host to read from the state vector.
+    fn host_view(&self, arg_len: usize) -> usize {
+        let (offset, len) = with_arg_buf(|buf| {
+            if arg_len != 8 {
+                panic!("Bad arguments");
+            }
+
+            let mut offset_bytes = [0; 4];
+            let mut len_bytes = [0; 4];
+
+            offset_bytes.copy_from_slice(&buf[..4]);
+            len_bytes.copy_from_slice(&buf[4..8]);
+
+            let offset = u32::from_le_bytes(offset_bytes) as usize;
+            let len = u32::from_le_bytes(len_bytes) as usize;
+
+            (offset, len)
+        });
+
+        if offset + len > self.0.len() {
+            panic!("Out of bounds");
+        }
+
+        let ptr = self.0.as_ptr() as usize + offset;
+        let bytes = uplink::host_read(ptr, len);
+
+        with_arg_buf(|buf| {
+            buf[..len].copy_from_slice(&bytes);
+        });
+
+        len
+    }
+
     /// Emplace the length of the state vector into the argument buffer.
     fn len(&self) -> usize {
         with_arg_buf(|buf| {
@@ -96,3 +153,16 @@ unsafe fn view(arg_len: u32) -> u32 {
 unsafe fn len(_arg_len: u32) -> u32 {
     STATE.len() as u32
 }
+
+/// Expose `Grower::host_write_at()` to the host
+#[no_mangle]
+unsafe fn host_write_at(arg_len: u32) -> u32 {
+    STATE.host_write_at(arg_len as usize);
+    0
+}
+
+/// Expose `Grower::host_view()` to the host
+#[no_mangle]
+unsafe fn host_view(arg_len: u32) -> u32 {
+    STATE.host_view(arg_len as usize) as u32
+}
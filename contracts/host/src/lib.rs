@@ -29,6 +29,22 @@ impl Hoster {
         uplink::host_query("hash", bytes)
     }
 
+    /// Call 'hash' function via the host `times` times, hashing the result
+    /// of the previous call each time
+    pub fn host_hash_repeat(&self, times: u32, bytes: Vec<u8>) -> [u8; 32] {
+        let mut hash = self.host_hash(bytes);
+        for _ in 1..times {
+            hash = self.host_hash(hash.to_vec());
+        }
+        hash
+    }
+
+    /// Call 'hash' function via the host on the concatenation of `items`
+    pub fn host_hash_all(&self, items: Vec<String>) -> [u8; 32] {
+        let bytes = items.concat().into_bytes();
+        self.host_hash(bytes)
+    }
+
     /// Call 'verify_proof' function via the host
     pub fn host_verify(
         &self,
@@ -53,6 +69,20 @@ unsafe fn host_hash(arg_len: u32) -> u32 {
     uplink::wrap_call(arg_len, |num| STATE.host_hash(num))
 }
 
+/// Expose `Hoster::host_hash_repeat()` to the host
+#[no_mangle]
+unsafe fn host_hash_repeat(arg_len: u32) -> u32 {
+    uplink::wrap_call(arg_len, |(times, bytes)| {
+        STATE.host_hash_repeat(times, bytes)
+    })
+}
+
+/// Expose `Hoster::host_hash_all()` to the host
+#[no_mangle]
+unsafe fn host_hash_all(arg_len: u32) -> u32 {
+    uplink::wrap_call(arg_len, |items| STATE.host_hash_all(items))
+}
+
 /// Expose `Hoster::host_verify()` to the host
 #[no_mangle]
 unsafe fn host_verify(arg_len: u32) -> u32 {
@@ -32,6 +32,11 @@ impl Metadata {
     pub fn read_owner_of(&self, id: ContractId) -> Option<[u8; 33]> {
         uplink::owner(id)
     }
+
+    /// Read the height and timestamp of the current block
+    pub fn read_block_context(&self) -> (u64, u64) {
+        (uplink::block_height(), uplink::block_timestamp())
+    }
 }
 
 /// Expose `Metadata::read_owner()` to the host
@@ -51,3 +56,9 @@ unsafe fn read_id(arg_len: u32) -> u32 {
 unsafe fn read_owner_of(arg_len: u32) -> u32 {
     uplink::wrap_call(arg_len, |id| STATE.read_owner_of(id))
 }
+
+/// Expose `Metadata::read_block_context()` to the host
+#[no_mangle]
+unsafe fn read_block_context(arg_len: u32) -> u32 {
+    uplink::wrap_call(arg_len, |_: ()| STATE.read_block_context())
+}
@@ -12,6 +12,11 @@
 #[allow(unused)]
 use piecrust_uplink;
 
+/// Descriptor of the contract's ABI, embedded in a custom section
+#[used]
+#[link_section = "piecrust-abi"]
+static ABI: [u8; 18] = *b"change(u32) -> u32";
+
 /// Struct representing the state of the change contract
 static mut A: u32 = 42;
 
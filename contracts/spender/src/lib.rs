@@ -50,6 +50,21 @@ impl Spender {
         }
     }
 
+    /// Loop until the remaining gas drops below the given `threshold`,
+    /// returning the number of iterations made.
+    pub fn spend_until(&self, threshold: u64) -> u64 {
+        let mut iterations = 0;
+        while uplink::remaining() >= threshold {
+            iterations += 1;
+        }
+        iterations
+    }
+
+    /// Request a refund of the given amount of gas.
+    pub fn refund(&self, gas: u64) {
+        uplink::refund(gas);
+    }
+
     /// Spend all gas that is given to the contract.
     pub fn spend(&self) {
         panic!("I like spending");
@@ -62,6 +77,18 @@ unsafe fn get_limit_and_spent(a: u32) -> u32 {
     uplink::wrap_call(a, |_: ()| STATE.get_limit_and_spent())
 }
 
+/// Expose `Spender::spend_until()` to the host
+#[no_mangle]
+unsafe fn spend_until(a: u32) -> u32 {
+    uplink::wrap_call(a, |threshold| STATE.spend_until(threshold))
+}
+
+/// Expose `Spender::refund()` to the host
+#[no_mangle]
+unsafe fn refund(a: u32) -> u32 {
+    uplink::wrap_call(a, |gas| STATE.refund(gas))
+}
+
 /// Expose `Spender::spend()` to the host
 #[no_mangle]
 unsafe fn spend(a: u32) -> u32 {
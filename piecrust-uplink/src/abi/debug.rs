@@ -6,6 +6,7 @@
 
 extern "C" {
     pub fn hdebug(arg_len: u32);
+    pub fn hdebug_level(level: u32, arg_len: u32);
 }
 
 /// Macro to format and send debug output to the host
@@ -21,3 +22,19 @@ macro_rules! debug {
         unsafe { $crate::hdebug(w.ofs() as u32) };
     };
 }
+
+/// Macro to format and send debug output to the host with the given
+/// [`DebugLevel`](crate::DebugLevel)
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($tt:tt)*) => {
+        #[allow(unused)]
+        use core::fmt::Write as _;
+
+        let mut w = $crate::ArgbufWriter::default();
+        write!(&mut w, $($tt)*).unwrap();
+
+        let level: $crate::DebugLevel = $level;
+        unsafe { $crate::hdebug_level(level as u32, w.ofs() as u32) };
+    };
+}
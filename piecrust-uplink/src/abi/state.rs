@@ -46,6 +46,7 @@ mod ext {
     extern "C" {
         pub fn hq(name: *const u8, name_len: u32, arg_len: u32) -> u32;
         pub fn hd(name: *const u8, name_len: u32) -> u32;
+        pub fn hgd(name: *const u8, name_len: u32) -> u32;
 
         pub fn c(
             contract_id: *const u8,
@@ -56,13 +57,26 @@ mod ext {
         ) -> i32;
 
         pub fn emit(topic: *const u8, topic_len: u32, arg_len: u32);
+        pub fn emit_typed(topic: *const u8, topic_len: u32, arg_len: u32);
         pub fn feed(arg_len: u32);
 
         pub fn caller();
+        pub fn caller_stack() -> u32;
         pub fn limit() -> u64;
         pub fn spent() -> u64;
+        pub fn remaining() -> u64;
         pub fn owner(contract_id: *const u8) -> i32;
         pub fn self_id();
+        pub fn self_state_hash();
+
+        pub fn mem_read(offset: usize, len: u32);
+        pub fn mem_write(offset: usize, len: u32);
+
+        pub fn block_height() -> u64;
+        pub fn block_timestamp() -> u64;
+
+        pub fn self_destruct();
+        pub fn refund(gas: u64);
     }
 }
 
@@ -242,6 +256,33 @@ where
     }
 }
 
+/// Returns global data made available by the host under the given name. The
+/// type `D` must be correctly specified, otherwise undefined behavior will
+/// occur.
+///
+/// Global data is readable by all contracts executing in a session, and is
+/// kept separately from the data returned by [`meta_data`].
+pub fn global_meta_data<D>(name: &str) -> Option<D>
+where
+    D: Archive,
+    D::Archived: Deserialize<D, Infallible>,
+{
+    let name_slice = name.as_bytes();
+
+    let name = name_slice.as_ptr();
+    let name_len = name_slice.len() as u32;
+
+    unsafe {
+        match ext::hgd(name, name_len) as usize {
+            0 => None,
+            arg_pos => Some(with_arg_buf(|buf| {
+                let ret = archived_root::<D>(&buf[..arg_pos]);
+                ret.deserialize(&mut Infallible).expect("Infallible")
+            })),
+        }
+    }
+}
+
 /// Return the given contract's owner, if the contract exists.
 pub fn owner<const N: usize>(contract: ContractId) -> Option<[u8; N]> {
     let contract_id_ptr = contract.as_bytes().as_ptr();
@@ -278,6 +319,22 @@ pub fn self_id() -> ContractId {
     id
 }
 
+/// Return the blake3 hash of the current contract's memory.
+///
+/// The hash is computed over the live memory of the contract, including any
+/// changes made during the current call, which are not yet committed. It is
+/// therefore not the hash of the contract's committed state.
+///
+/// Hashing costs one unit of gas for every 64 bytes of memory.
+pub fn self_state_hash() -> [u8; 32] {
+    unsafe { ext::self_state_hash() };
+    with_arg_buf(|buf| {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&buf[..32]);
+        hash
+    })
+}
+
 /// Return the ID of the calling contract. The returned id will be
 /// uninitialized if there is no caller - meaning this is the first contract
 /// to be called.
@@ -293,6 +350,23 @@ pub fn caller() -> ContractId {
     })
 }
 
+/// Return the IDs of all the contracts in the call stack, starting with the
+/// calling contract and ending with the first contract to be called. The
+/// returned vector will be empty if there is no caller.
+pub fn caller_stack() -> Vec<ContractId> {
+    let n_callers = unsafe { ext::caller_stack() } as usize;
+    with_arg_buf(|buf| {
+        buf[..n_callers * CONTRACT_ID_BYTES]
+            .chunks_exact(CONTRACT_ID_BYTES)
+            .map(|bytes| {
+                let mut id = [0u8; CONTRACT_ID_BYTES];
+                id.copy_from_slice(bytes);
+                ContractId::from_bytes(id)
+            })
+            .collect()
+    })
+}
+
 /// Returns the gas limit with which the contact was called.
 pub fn limit() -> u64 {
     unsafe { ext::limit() }
@@ -303,6 +377,66 @@ pub fn spent() -> u64 {
     unsafe { ext::spent() }
 }
 
+/// Returns the amount of gas the contract has left to spend.
+pub fn remaining() -> u64 {
+    unsafe { ext::remaining() }
+}
+
+/// Reads `len` bytes of the contract's memory at the given `offset`, using
+/// the host to perform the copy.
+///
+/// # Panics
+/// If the range is out of the bounds of the memory, or `len` is larger than
+/// the argument buffer.
+pub fn host_read(offset: usize, len: usize) -> Vec<u8> {
+    unsafe { ext::mem_read(offset, len as u32) };
+    with_arg_buf(|buf| buf[..len].to_vec())
+}
+
+/// Writes the given `bytes` to the contract's memory at the given `offset`,
+/// using the host to perform the copy.
+///
+/// # Panics
+/// If the range is out of the bounds of the memory, or there are more bytes
+/// than fit in the argument buffer.
+pub fn host_write(offset: usize, bytes: &[u8]) {
+    with_arg_buf(|buf| buf[..bytes.len()].copy_from_slice(bytes));
+    unsafe { ext::mem_write(offset, bytes.len() as u32) };
+}
+
+/// Returns the height of the block in whose context the contract is being
+/// executed, as set by the host.
+pub fn block_height() -> u64 {
+    unsafe { ext::block_height() }
+}
+
+/// Returns the timestamp of the block in whose context the contract is being
+/// executed, as set by the host.
+pub fn block_timestamp() -> u64 {
+    unsafe { ext::block_timestamp() }
+}
+
+/// Marks the current contract for destruction.
+///
+/// If the call succeeds, the contract is removed from the state once the call
+/// finishes, and is not carried forward into subsequent commits. Calls to it
+/// afterwards fail, as if it was never deployed. If the call fails, the
+/// contract is left untouched.
+pub fn self_destruct() {
+    unsafe { ext::self_destruct() }
+}
+
+/// Requests a refund of the given amount of `gas` to the top-level call, for
+/// instance when freeing state.
+///
+/// Refunds are subtracted from the gas spent by the call once it finishes,
+/// capped at a fraction of it set by the host. If the call - or the
+/// inter-contract call requesting the refund - fails, the refund is
+/// discarded.
+pub fn refund(gas: u64) {
+    unsafe { ext::refund(gas) }
+}
+
 /// Emits an event with the given data.
 pub fn emit<D>(topic: &'static str, data: D)
 where
@@ -325,6 +459,36 @@ where
     });
 }
 
+/// Emits an event with the given data, tagged with the `version` of its
+/// schema.
+///
+/// The version allows consumers of the event to tell apart different
+/// encodings of the data, allowing for the data emitted under a topic to
+/// evolve over time.
+pub fn emit_typed<D>(topic: &'static str, version: u16, data: D)
+where
+    for<'a> D: Serialize<StandardBufSerializer<'a>>,
+{
+    with_arg_buf(|buf| {
+        const VERSION_LEN: usize = core::mem::size_of::<u16>();
+        buf[..VERSION_LEN].copy_from_slice(&version.to_le_bytes());
+
+        let mut sbuf = [0u8; SCRATCH_BUF_BYTES];
+        let scratch = BufferScratch::new(&mut sbuf);
+        let ser = BufferSerializer::new(&mut buf[VERSION_LEN..]);
+        let mut composite =
+            CompositeSerializer::new(ser, scratch, rkyv::Infallible);
+
+        composite.serialize_value(&data).unwrap();
+        let arg_len = (VERSION_LEN + composite.pos()) as u32;
+
+        let topic_ptr = topic.as_ptr();
+        let topic_len = topic.len() as u32;
+
+        unsafe { ext::emit_typed(topic_ptr, topic_len, arg_len) }
+    });
+}
+
 /// Feeds the host with data.
 ///
 /// This is only allowed to be called in the context of a `feed_call`, and
@@ -12,7 +12,6 @@ use rkyv::{Archive, Deserialize, Serialize};
 use alloc::string::String;
 
 use core::fmt::{Display, Formatter};
-use core::str;
 
 /// The error possibly returned on an inter-contract-call.
 //
@@ -22,51 +21,72 @@ use core::str;
 //
 // The contract writer, however, is free to pass it around and react to it if it
 // wishes.
-#[derive(Debug, Clone, Archive, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Archive, Serialize, Deserialize)]
 #[archive_attr(derive(CheckBytes))]
 pub enum ContractError {
     Panic(String),
     OutOfGas,
+    Initialization,
+    Validation,
+    Trap,
     Unknown,
 }
 
 impl ContractError {
     /// Returns a contract error from a return `code` and the data in the
     /// `slice`.
-    #[cfg(feature = "abi")]
-    pub(crate) fn from_parts(code: i32, slice: &[u8]) -> Self {
+    ///
+    /// Codes that are not returned by [`as_status_code`] - for instance ones
+    /// introduced by a newer host - are decoded as [`Unknown`]. A panic message
+    /// longer than the `slice` is truncated to it.
+    ///
+    /// [`as_status_code`]: ContractError::as_status_code
+    /// [`Unknown`]: ContractError::Unknown
+    pub fn from_parts(code: i32, slice: &[u8]) -> Self {
         fn get_msg(slice: &[u8]) -> String {
-            let msg_len = {
-                let mut msg_len_bytes = [0u8; 4];
-                msg_len_bytes.copy_from_slice(&slice[..4]);
-                u32::from_le_bytes(msg_len_bytes)
-            } as usize;
-
-            // SAFETY: the host guarantees that the message is valid UTF-8,
-            // so this is safe.
-            let msg = unsafe {
-                use alloc::string::ToString;
-                let msg_bytes = &slice[4..4 + msg_len];
-                let msg_str = str::from_utf8_unchecked(msg_bytes);
-                msg_str.to_string()
+            let msg_len = match slice.get(..4) {
+                Some(msg_len_bytes) => {
+                    let mut bytes = [0u8; 4];
+                    bytes.copy_from_slice(msg_len_bytes);
+                    u32::from_le_bytes(bytes) as usize
+                }
+                None => return String::new(),
             };
 
-            msg
+            let msg_bytes = &slice[4..];
+            let msg_bytes = &msg_bytes[..msg_len.min(msg_bytes.len())];
+            String::from_utf8_lossy(msg_bytes).into_owned()
         }
 
         match code {
             -1 => Self::Panic(get_msg(slice)),
             -2 => Self::OutOfGas,
-            i32::MIN => Self::Unknown,
-            _ => unreachable!("The host must guarantee that the code is valid"),
+            -3 => Self::Initialization,
+            -4 => Self::Validation,
+            -5 => Self::Trap,
+            _ => Self::Unknown,
         }
     }
 
     /// Write the appropriate data the `arg_buf` and return the error code.
+    ///
+    /// A panic message that doesn't fit in the `slice` is truncated, and one
+    /// is not written at all if the `slice` can't even hold its length.
     pub fn to_parts(&self, slice: &mut [u8]) -> i32 {
         fn put_msg(msg: &str, slice: &mut [u8]) {
-            let msg_bytes = msg.as_bytes();
-            let msg_len = msg_bytes.len();
+            let available = match slice.len().checked_sub(4) {
+                Some(available) => available,
+                None => return,
+            };
+
+            // Truncate the message to fit after its length, making sure to not
+            // split a character.
+            let mut msg_len = msg.len().min(available);
+            while !msg.is_char_boundary(msg_len) {
+                msg_len -= 1;
+            }
+
+            let msg_bytes = &msg.as_bytes()[..msg_len];
 
             let mut msg_len_bytes = [0u8; 4];
             msg_len_bytes.copy_from_slice(&(msg_len as u32).to_le_bytes());
@@ -75,12 +95,24 @@ impl ContractError {
             slice[4..4 + msg_len].copy_from_slice(msg_bytes);
         }
 
+        if let Self::Panic(msg) = self {
+            put_msg(msg, slice);
+        }
+
+        self.as_status_code()
+    }
+
+    /// Returns the status code signalling the error to a calling contract.
+    ///
+    /// Status codes are always negative, with each kind of error having its
+    /// own reserved code.
+    pub fn as_status_code(&self) -> i32 {
         match self {
-            Self::Panic(msg) => {
-                put_msg(msg, slice);
-                -1
-            }
+            Self::Panic(_) => -1,
             Self::OutOfGas => -2,
+            Self::Initialization => -3,
+            Self::Validation => -4,
+            Self::Trap => -5,
             Self::Unknown => i32::MIN,
         }
     }
@@ -88,11 +120,7 @@ impl ContractError {
 
 impl From<ContractError> for i32 {
     fn from(err: ContractError) -> Self {
-        match err {
-            ContractError::Panic(_) => -1,
-            ContractError::OutOfGas => -2,
-            ContractError::Unknown => i32::MIN,
-        }
+        err.as_status_code()
     }
 }
 
@@ -101,6 +129,9 @@ impl Display for ContractError {
         match self {
             ContractError::Panic(msg) => write!(f, "Panic: {msg}"),
             ContractError::OutOfGas => write!(f, "OutOfGas"),
+            ContractError::Initialization => write!(f, "Initialization"),
+            ContractError::Validation => write!(f, "Validation"),
+            ContractError::Trap => write!(f, "Trap"),
             ContractError::Unknown => write!(f, "Unknown"),
         }
     }
@@ -53,7 +53,7 @@
 //! - `abi` for writing contracts
 //! - `dlmalloc` to using the builtin allocator
 //! - `debug` for writing contracts with debug capabilities such as the
-//!   [`debug!`] macro, and logging panics to stdout
+//!   [`debug!`] and [`log!`] macros, and logging panics to stdout
 //!
 //! [WASM memory]: https://wasmbyexample.dev/examples/webassembly-linear-memory/webassembly-linear-memory.rust.en-us.html
 //! [contracts/]: https://github.com/dusk-network/piecrust/tree/main/contracts
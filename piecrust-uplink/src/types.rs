@@ -32,9 +32,35 @@ use crate::SCRATCH_BUF_BYTES;
 pub struct Event {
     pub source: ContractId,
     pub topic: String,
+    /// The schema version of the data, if the event was emitted using
+    /// `emit_typed`.
+    pub version: Option<u16>,
     pub data: Vec<u8>,
 }
 
+/// The severity of a debug message sent by a contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u32)]
+pub enum DebugLevel {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+}
+
+impl DebugLevel {
+    /// Returns the level with the given numeric value, if any.
+    pub const fn from_u32(level: u32) -> Option<Self> {
+        match level {
+            0 => Some(Self::Debug),
+            1 => Some(Self::Info),
+            2 => Some(Self::Warn),
+            3 => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
 /// Type with `rkyv` serialization capabilities for specific types.
 pub type StandardBufSerializer<'a> = CompositeSerializer<
     BufferSerializer<&'a mut [u8]>,
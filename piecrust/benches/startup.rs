@@ -0,0 +1,79 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use piecrust::{
+    contract_bytecode, CommitLayout, ContractData, SessionData, VM,
+};
+
+const OWNER: [u8; 32] = [0u8; 32];
+const LIMIT: u64 = 1_000_000;
+
+const N_COMMITS: usize = 256;
+
+/// Creates a directory containing `N_COMMITS` commits.
+fn commits_dir() -> (VM, PathBuf) {
+    let vm = VM::ephemeral().expect("Ephemeral VM should succeed");
+    let root_dir = vm.root_dir().to_path_buf();
+
+    let mut session = vm
+        .session(SessionData::builder())
+        .expect("Spawning a session should succeed");
+    let id = session
+        .deploy(
+            contract_bytecode!("counter"),
+            ContractData::builder().owner(OWNER),
+            LIMIT,
+        )
+        .expect("Deployment should succeed");
+    let mut commit = session.commit().expect("Committing should succeed");
+
+    for _ in 1..N_COMMITS {
+        let mut session = vm
+            .session(SessionData::builder().base(commit))
+            .expect("Spawning a session should succeed");
+        session
+            .call::<_, ()>(id, "increment", &(), LIMIT)
+            .expect("Incrementing should succeed");
+        commit = session.commit().expect("Committing should succeed");
+    }
+
+    (vm, root_dir)
+}
+
+fn startup(c: &mut Criterion) {
+    let (_vm, root_dir) = commits_dir();
+
+    let mut group = c.benchmark_group("startup");
+
+    for parallelism in [1, 2, 4, 8] {
+        let n_threads = NonZeroUsize::new(parallelism)
+            .expect("Parallelism should be non-zero");
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(parallelism),
+            &n_threads,
+            |b, n_threads| {
+                b.iter(|| {
+                    VM::with_load_parallelism(
+                        &root_dir,
+                        CommitLayout::Flat,
+                        *n_threads,
+                    )
+                    .expect("Loading the VM should succeed")
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, startup);
+criterion_main!(benches);
@@ -101,6 +101,20 @@ impl CallTree {
         current.map(|inner| unsafe { (*inner).elem })
     }
 
+    /// Returns the number of elements from the current node to the root,
+    /// inclusive.
+    pub(crate) fn depth(&self) -> usize {
+        let mut current = self.0;
+
+        let mut depth = 0;
+        while let Some(inner) = current {
+            current = unsafe { (*inner).parent };
+            depth += 1;
+        }
+
+        depth
+    }
+
     /// Clears the call tree of all elements.
     pub(crate) fn clear(&mut self) {
         unsafe {
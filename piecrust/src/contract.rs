@@ -10,6 +10,7 @@ use bytecheck::CheckBytes;
 use dusk_wasmtime::{Engine, Module};
 use piecrust_uplink::ContractId;
 use rkyv::{Archive, Deserialize, Serialize};
+use wasmparser::{DataKind, Operator, Parser, Payload, TypeRef};
 
 use crate::error::Error;
 
@@ -78,6 +79,119 @@ impl<'a, A> ContractDataBuilder<'a, A> {
     }
 }
 
+/// The name of the custom WASM section in which a contract may embed a
+/// descriptor of its ABI.
+///
+/// The contents of the section are opaque to the VM, and may be retrieved
+/// using [`Session::contract_abi`]. A Rust contract may embed it using:
+///
+/// ```ignore
+/// #[link_section = "piecrust-abi"]
+/// static ABI: [u8; 18] = *b"change(u32) -> u32";
+/// ```
+///
+/// Tools stripping custom sections from the bytecode - such as
+/// `wasm-tools strip --all` - remove it, and must be told to keep it.
+///
+/// [`Session::contract_abi`]: crate::Session::contract_abi
+pub const ABI_SECTION: &str = "piecrust-abi";
+
+/// Returns the contents of the custom section with the given `name` in the
+/// `bytecode`, if it exists.
+pub(crate) fn custom_section(
+    bytecode: &[u8],
+    name: &str,
+) -> Result<Option<Vec<u8>>, Error> {
+    for payload in Parser::new(0).parse_all(bytecode) {
+        if let Payload::CustomSection(reader) = payload? {
+            if reader.name() == name {
+                return Ok(Some(reader.data().to_vec()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the names of the functions imported by the `bytecode`, in the order
+/// they are imported.
+pub(crate) fn imported_functions(
+    bytecode: &[u8],
+) -> Result<Vec<String>, Error> {
+    let mut functions = Vec::new();
+
+    for payload in Parser::new(0).parse_all(bytecode) {
+        if let Payload::ImportSection(reader) = payload? {
+            for import in reader {
+                let import = import?;
+                if let TypeRef::Func(_) = import.ty {
+                    functions.push(import.name.to_string());
+                }
+            }
+        }
+    }
+
+    Ok(functions)
+}
+
+/// The size of a WASM memory page.
+const WASM_PAGE_SIZE: u64 = 0x10000;
+
+/// Checks that the active data segments of the `bytecode` fit in the initial
+/// size of its memory, so they can all be copied into it when the contract is
+/// instantiated.
+///
+/// Segments whose offset is not a constant are only checked on instantiation.
+pub(crate) fn check_data_segments(bytecode: &[u8]) -> Result<(), Error> {
+    let mut mem_len = None;
+
+    for payload in Parser::new(0).parse_all(bytecode) {
+        match payload? {
+            Payload::MemorySection(reader) => {
+                if let Some(memory) = reader.into_iter().next() {
+                    let memory = memory?;
+                    mem_len =
+                        Some(memory.initial.saturating_mul(WASM_PAGE_SIZE));
+                }
+            }
+            Payload::DataSection(reader) => {
+                for data in reader {
+                    let data = data?;
+
+                    let offset_expr = match data.kind {
+                        DataKind::Active {
+                            memory_index: 0,
+                            offset_expr,
+                        } => offset_expr,
+                        _ => continue,
+                    };
+
+                    let offset =
+                        match offset_expr.get_operators_reader().read()? {
+                            Operator::I32Const { value } => value as u32 as u64,
+                            Operator::I64Const { value } => value as u64,
+                            _ => continue,
+                        };
+                    let len = data.data.len() as u64;
+
+                    let mem_len = mem_len.unwrap_or(0);
+                    if offset.checked_add(len).map_or(true, |end| end > mem_len)
+                    {
+                        return Err(Error::MemoryAccessOutOfBounds {
+                            offset: offset as usize,
+                            len: len as usize,
+                            mem_len: mem_len as usize,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Archive, Serialize, Deserialize, Debug, Clone)]
 #[archive_attr(derive(CheckBytes))]
 pub struct ContractMetadata {
@@ -0,0 +1,97 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use wasmparser::{ExternalKind, Operator, Parser, Payload, TypeRef};
+
+use crate::Error;
+
+/// Returns the gas charged by the runtime for executing the given operator.
+///
+/// This mirrors the fuel cost model of the runtime.
+fn operator_cost(op: &Operator) -> u64 {
+    match op {
+        Operator::Nop | Operator::Drop => 0,
+        Operator::Block { .. }
+        | Operator::Loop { .. }
+        | Operator::Unreachable
+        | Operator::Return
+        | Operator::Else
+        | Operator::End => 0,
+        _ => 1,
+    }
+}
+
+/// Statically estimates the gas cost of the function exported by the given
+/// contract `bytecode` under the name `method`.
+///
+/// The estimate is the sum of the costs of all the operators in the body of
+/// the function, using the same cost model as the runtime. It is a best-effort
+/// estimate, with the following limitations:
+///
+/// - Every operator is counted exactly once. Loops are therefore counted as if
+///   executing a single iteration, and the operators of all branches are
+///   counted, even though only one is taken at runtime.
+/// - Only the body of the exported function is considered. Calls to other
+///   functions - including host functions and other contracts - are counted as
+///   a single operator.
+///
+/// For a function without loops, branches, or calls the estimate will match
+/// the gas spent at runtime by the function itself.
+///
+/// # Errors
+/// If the bytecode is not valid WASM, or doesn't export a function named
+/// `method`.
+pub fn estimate_opcode_cost(
+    bytecode: &[u8],
+    method: &str,
+) -> Result<u64, Error> {
+    let mut imported_funcs = 0;
+    let mut func_index = None;
+    let mut code_index = 0;
+
+    for payload in Parser::new(0).parse_all(bytecode) {
+        match payload? {
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    let import = import?;
+                    if let TypeRef::Func(_) = import.ty {
+                        imported_funcs += 1;
+                    }
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    if export.kind == ExternalKind::Func
+                        && export.name == method
+                    {
+                        func_index = Some(export.index);
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                let index = imported_funcs + code_index;
+                code_index += 1;
+
+                if Some(index) != func_index {
+                    continue;
+                }
+
+                let mut cost = 0;
+                let mut reader = body.get_operators_reader()?;
+                while !reader.eof() {
+                    let op = reader.read()?;
+                    cost += operator_cost(&op);
+                }
+
+                return Ok(cost);
+            }
+            _ => {}
+        }
+    }
+
+    Err(Error::InvalidFunction(method.into()))
+}
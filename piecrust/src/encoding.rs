@@ -0,0 +1,125 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Compact and stable binary encodings for maps of contract IDs to payloads,
+//! and for events.
+//!
+//! Each entry of a map is framed as the 32 bytes of the contract ID, followed
+//! by the length of the payload as a little-endian `u32`, followed by the
+//! payload itself. Entries are simply concatenated.
+//!
+//! Unlike `rkyv`, the encodings do not depend on the version of any crate,
+//! making them suitable for transporting data over the network, or for
+//! hashing.
+
+use std::borrow::Cow;
+
+use piecrust_uplink::{ContractId, Event, CONTRACT_ID_BYTES};
+
+use crate::store::Hasher;
+use crate::Error::{self, DecodingError};
+
+const LEN_BYTES: usize = 4;
+
+/// Encodes the given `entries` into a byte vector.
+///
+/// # Panics
+/// If the length of any payload doesn't fit in a `u32`.
+pub fn encode_contract_map<'a, I, P>(entries: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = &'a (ContractId, P)>,
+    P: 'a + AsRef<[u8]>,
+{
+    let mut bytes = Vec::new();
+
+    for (contract, payload) in entries {
+        let payload = payload.as_ref();
+        let len = u32::try_from(payload.len())
+            .expect("Payload length should fit in a u32");
+
+        bytes.extend_from_slice(contract.as_bytes());
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(payload);
+    }
+
+    bytes
+}
+
+/// Decodes entries previously encoded using [`encode_contract_map`].
+///
+/// # Errors
+/// If the given `bytes` are not a sequence of well-formed entries.
+pub fn decode_contract_map(
+    mut bytes: &[u8],
+) -> Result<Vec<(ContractId, Vec<u8>)>, Error> {
+    let mut entries = Vec::new();
+
+    while !bytes.is_empty() {
+        if bytes.len() < CONTRACT_ID_BYTES + LEN_BYTES {
+            return Err(DecodingError(Cow::Borrowed("Truncated entry header")));
+        }
+
+        let mut contract_bytes = [0u8; CONTRACT_ID_BYTES];
+        contract_bytes.copy_from_slice(&bytes[..CONTRACT_ID_BYTES]);
+        bytes = &bytes[CONTRACT_ID_BYTES..];
+
+        let mut len_bytes = [0u8; LEN_BYTES];
+        len_bytes.copy_from_slice(&bytes[..LEN_BYTES]);
+        bytes = &bytes[LEN_BYTES..];
+
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        if bytes.len() < len {
+            return Err(DecodingError(Cow::Owned(format!(
+                "Truncated payload: {} < {len}",
+                bytes.len()
+            ))));
+        }
+
+        let payload = bytes[..len].to_vec();
+        bytes = &bytes[len..];
+
+        entries.push((ContractId::from_bytes(contract_bytes), payload));
+    }
+
+    Ok(entries)
+}
+
+/// Hashes the given `events`, in order, producing a digest suitable for
+/// committing to the events produced by the execution of a block.
+///
+/// Each event is canonically encoded as the 32 bytes of its source contract
+/// ID, followed by the length of its topic as a little-endian `u32` and the
+/// topic, followed by a byte signalling the presence of a version and the
+/// version as a little-endian `u16` if present, followed by the length of its
+/// data as a little-endian `u32` and the data.
+///
+/// # Panics
+/// If the length of the topic or data of any event doesn't fit in a `u32`.
+pub fn hash_events<'a, I>(events: I) -> [u8; 32]
+where
+    I: IntoIterator<Item = &'a Event>,
+{
+    let mut hasher = Hasher::new();
+
+    for event in events {
+        let topic_len = u32::try_from(event.topic.len())
+            .expect("Topic length should fit in a u32");
+        let data_len = u32::try_from(event.data.len())
+            .expect("Data length should fit in a u32");
+
+        hasher.update(event.source.as_bytes());
+        hasher.update(&topic_len.to_le_bytes());
+        hasher.update(event.topic.as_bytes());
+        match event.version {
+            None => hasher.update(&[0]),
+            Some(version) => hasher.update(&[1]).update(&version.to_le_bytes()),
+        };
+        hasher.update(&data_len.to_le_bytes());
+        hasher.update(&event.data);
+    }
+
+    hasher.finalize().into()
+}
@@ -24,6 +24,14 @@ pub type Compo = CompositeSerializerError<
 pub enum Error {
     #[error("Argument buffer overflow: {len} > {max_len}")]
     ArgumentBufferOverflow { len: usize, max_len: usize },
+    #[error("Block limit exceeded: {spent} spent + {limit} > {block_limit}")]
+    BlockLimitExceeded {
+        block_limit: u64,
+        spent: u64,
+        limit: u64,
+    },
+    #[error("Call stack overflow: depth exceeds {0}")]
+    CallStackOverflow(usize),
     #[error("Commit error: {0}")]
     CommitError(Cow<'static, str>),
     #[error(transparent)]
@@ -32,8 +40,12 @@ pub enum Error {
     ContractCacheError(Arc<std::io::Error>),
     #[error("Contract does not exist: {0}")]
     ContractDoesNotExist(ContractId),
+    #[error("Decoding error: {0}")]
+    DecodingError(Cow<'static, str>),
     #[error(transparent)]
     FeedPulled(mpsc::SendError<Vec<u8>>),
+    #[error("Host query limit exceeded: more than {0} queries")]
+    HostQueryLimitExceeded(usize),
     #[error(transparent)]
     Infallible(std::convert::Infallible),
     #[error("InitalizationError: {0}")]
@@ -44,12 +56,16 @@ pub enum Error {
     InvalidFunction(String),
     #[error("Invalid memory")]
     InvalidMemory,
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(usize),
     #[error("Memory access out of bounds: offset {offset}, length {len}, memory length {mem_len}")]
     MemoryAccessOutOfBounds {
         offset: usize,
         len: usize,
         mem_len: usize,
     },
+    #[error("Memory limit exceeded by contract: {0}")]
+    MemoryLimitExceeded(ContractId),
     #[error("Snapshot failure: {reason:?} {io}")]
     MemorySnapshotFailure {
         reason: Option<Arc<Self>>,
@@ -67,6 +83,8 @@ pub enum Error {
     Panic(String),
     #[error(transparent)]
     PersistenceError(Arc<std::io::Error>),
+    #[error("Session is read-only")]
+    ReadOnlySession,
     #[error(transparent)]
     RestoreError(Arc<std::io::Error>),
     #[error(transparent)]
@@ -91,6 +109,23 @@ impl Error {
             err => err,
         }
     }
+
+    /// Returns the underlying runtime error, if this is a
+    /// [`RuntimeError`](Error::RuntimeError).
+    pub fn as_runtime_error(&self) -> Option<&dusk_wasmtime::Error> {
+        match self {
+            Self::RuntimeError(rerr) => Some(rerr),
+            _ => None,
+        }
+    }
+
+    /// Returns the trap that caused this error, if it was caused by one.
+    ///
+    /// This allows for distinguishing between kinds of traps, such as a stack
+    /// overflow and a contract reaching an `unreachable` instruction.
+    pub fn as_trap(&self) -> Option<&dusk_wasmtime::Trap> {
+        self.as_runtime_error()?.downcast_ref()
+    }
 }
 
 impl From<std::convert::Infallible> for Error {
@@ -111,6 +146,12 @@ impl From<dusk_wasmtime::Error> for Error {
     }
 }
 
+impl From<wasmparser::BinaryReaderError> for Error {
+    fn from(e: wasmparser::BinaryReaderError) -> Self {
+        Error::DecodingError(e.to_string().into())
+    }
+}
+
 impl From<Compo> for Error {
     fn from(e: Compo) -> Self {
         Error::CompositeSerializerError(Arc::from(e))
@@ -128,6 +169,9 @@ impl From<Error> for ContractError {
         match err {
             Error::OutOfGas => Self::OutOfGas,
             Error::Panic(msg) => Self::Panic(msg),
+            Error::InitalizationError(_) => Self::Initialization,
+            Error::ValidationError => Self::Validation,
+            Error::RuntimeError(_) => Self::Trap,
             _ => Self::Unknown,
         }
     }
@@ -0,0 +1,102 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! A deterministic execution harness for differential fuzzing.
+//!
+//! A sequence of [`Op`]s is executed against a session using [`run_ops`],
+//! producing an [`ExecutionSummary`] that can be compared across builds of
+//! the VM.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use piecrust_uplink::ContractId;
+
+use crate::encoding::hash_events;
+use crate::session::Session;
+
+/// A raw call to be made to a contract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Op {
+    /// The contract to call.
+    pub contract: ContractId,
+    /// The name of the function to call.
+    pub fn_name: String,
+    /// The serialized argument to the function.
+    pub fn_arg: Vec<u8>,
+    /// The gas limit of the call.
+    pub gas_limit: u64,
+}
+
+/// A summary of the execution of a sequence of [`Op`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionSummary {
+    /// All operations were executed, some of which may have failed.
+    Completed {
+        /// The state root of the session after all operations.
+        root: [u8; 32],
+        /// The hash of the events emitted by all operations, in order.
+        events_hash: [u8; 32],
+        /// The total gas spent by the successful operations.
+        spent: u64,
+        /// The indices of the operations that returned an error.
+        failed: Vec<usize>,
+    },
+    /// The VM panicked while executing an operation, and the remaining ones
+    /// were not executed.
+    Panicked {
+        /// The index of the operation during which the VM panicked.
+        op_index: usize,
+        /// The panic message, if it was a string.
+        message: Option<String>,
+    },
+}
+
+/// Executes the given `ops` in order against the `session`, summarizing the
+/// result.
+///
+/// Errors returned by operations are recorded in the summary, and execution
+/// continues with the next operation. Panics are caught and reported as
+/// [`ExecutionSummary::Panicked`], in which case the session should be
+/// discarded.
+pub fn run_ops(session: &mut Session, ops: &[Op]) -> ExecutionSummary {
+    let mut events = Vec::new();
+    let mut spent = 0;
+    let mut failed = Vec::new();
+
+    for (op_index, op) in ops.iter().enumerate() {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            session.call_raw(
+                op.contract,
+                &op.fn_name,
+                op.fn_arg.clone(),
+                op.gas_limit,
+            )
+        }));
+
+        match result {
+            Ok(Ok(receipt)) => {
+                spent += receipt.gas_spent;
+                events.extend(receipt.events);
+            }
+            Ok(Err(_)) => failed.push(op_index),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|msg| msg.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned());
+
+                return ExecutionSummary::Panicked { op_index, message };
+            }
+        }
+    }
+
+    ExecutionSummary::Completed {
+        root: session.root(),
+        events_hash: hash_events(&events),
+        spent,
+        failed,
+    }
+}
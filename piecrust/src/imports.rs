@@ -12,6 +12,8 @@ use std::sync::Arc;
 use dusk_wasmtime::{
     Caller, Extern, Func, Module, Result as WasmtimeResult, Store,
 };
+#[cfg(feature = "debug")]
+use piecrust_uplink::DebugLevel;
 use piecrust_uplink::{
     ContractError, ContractId, ARGBUF_LEN, CONTRACT_ID_BYTES,
 };
@@ -19,6 +21,8 @@ use piecrust_uplink::{
 use crate::instance::{Env, WrappedInstance};
 use crate::Error;
 
+/// The default percentage of the caller's remaining gas forwarded to an
+/// inter-contract call that doesn't specify a limit.
 pub const GAS_PASS_PCT: u64 = 93;
 
 pub(crate) struct Imports;
@@ -30,7 +34,7 @@ impl Imports {
         module: &Module,
         is_64: bool,
     ) -> Result<Vec<Extern>, Error> {
-        let max_imports = 12;
+        let max_imports = 13;
         let mut imports = Vec::with_capacity(max_imports);
 
         for import in module.imports() {
@@ -52,6 +56,7 @@ impl Imports {
     fn import(store: &mut Store<Env>, name: &str, is_64: bool) -> Option<Func> {
         Some(match name {
             "caller" => Func::wrap(store, caller),
+            "caller_stack" => Func::wrap(store, caller_stack),
             "c" => match is_64 {
                 false => Func::wrap(store, wasm32::c),
                 true => Func::wrap(store, wasm64::c),
@@ -64,21 +69,45 @@ impl Imports {
                 false => Func::wrap(store, wasm32::hd),
                 true => Func::wrap(store, wasm64::hd),
             },
+            "hgd" => match is_64 {
+                false => Func::wrap(store, wasm32::hgd),
+                true => Func::wrap(store, wasm64::hgd),
+            },
             "emit" => match is_64 {
                 false => Func::wrap(store, wasm32::emit),
                 true => Func::wrap(store, wasm64::emit),
             },
+            "emit_typed" => match is_64 {
+                false => Func::wrap(store, wasm32::emit_typed),
+                true => Func::wrap(store, wasm64::emit_typed),
+            },
             "feed" => Func::wrap(store, feed),
+            "mem_read" => match is_64 {
+                false => Func::wrap(store, wasm32::mem_read),
+                true => Func::wrap(store, wasm64::mem_read),
+            },
+            "mem_write" => match is_64 {
+                false => Func::wrap(store, wasm32::mem_write),
+                true => Func::wrap(store, wasm64::mem_write),
+            },
             "limit" => Func::wrap(store, limit),
             "spent" => Func::wrap(store, spent),
+            "remaining" => Func::wrap(store, remaining),
             "panic" => Func::wrap(store, panic),
             "owner" => match is_64 {
                 false => Func::wrap(store, wasm32::owner),
                 true => Func::wrap(store, wasm64::owner),
             },
             "self_id" => Func::wrap(store, self_id),
+            "self_state_hash" => Func::wrap(store, self_state_hash),
+            "block_height" => Func::wrap(store, block_height),
+            "block_timestamp" => Func::wrap(store, block_timestamp),
+            "self_destruct" => Func::wrap(store, self_destruct),
+            "refund" => Func::wrap(store, refund),
             #[cfg(feature = "debug")]
             "hdebug" => Func::wrap(store, hdebug),
+            #[cfg(feature = "debug")]
+            "hdebug_level" => Func::wrap(store, hdebug_level),
             _ => return None,
         })
     }
@@ -147,6 +176,8 @@ pub(crate) fn hq(
 ) -> WasmtimeResult<u32> {
     let env = fenv.data_mut();
 
+    env.record_host_query()?;
+
     let instance = env.self_instance();
 
     let name_len = name_len as usize;
@@ -160,16 +191,42 @@ pub(crate) fn hq(
             .map(ToOwned::to_owned)
     })?;
 
-    Ok(instance
+    let ret_len = instance
         .with_arg_buf_mut(|buf| env.host_query(&name, buf, arg_len))
-        .ok_or(Error::MissingHostQuery(name))?)
+        .ok_or(Error::MissingHostQuery(name))??;
+
+    Ok(ret_len)
 }
 
 pub(crate) fn hd(
-    mut fenv: Caller<Env>,
+    fenv: Caller<Env>,
+    name_ofs: usize,
+    name_len: u32,
+) -> WasmtimeResult<u32> {
+    host_data(fenv, name_ofs, name_len, |env, name| env.meta(name))
+}
+
+pub(crate) fn hgd(
+    fenv: Caller<Env>,
     name_ofs: usize,
     name_len: u32,
 ) -> WasmtimeResult<u32> {
+    host_data(fenv, name_ofs, name_len, |env, name| env.global_meta(name))
+}
+
+/// Writes the data returned by `get_data` for the name in the contract's
+/// memory to the argument buffer, returning its length.
+///
+/// Data that doesn't fit in the argument buffer fails the call.
+fn host_data<F>(
+    mut fenv: Caller<Env>,
+    name_ofs: usize,
+    name_len: u32,
+    get_data: F,
+) -> WasmtimeResult<u32>
+where
+    F: FnOnce(&Env, &str) -> Option<Vec<u8>>,
+{
     let env = fenv.data_mut();
 
     let instance = env.self_instance();
@@ -184,7 +241,17 @@ pub(crate) fn hd(
             .map(ToOwned::to_owned)
     })?;
 
-    let data = env.meta(&name).unwrap_or_default();
+    let data = get_data(env, &name).unwrap_or_default();
+
+    if data.len() > ARGBUF_LEN {
+        let mem_len = instance.with_memory(|mem| mem.len());
+        return Err(Error::MemoryAccessOutOfBounds {
+            offset: instance.arg_buffer_offset(),
+            len: data.len(),
+            mem_len,
+        }
+        .into());
+    }
 
     instance.with_arg_buf_mut(|buf| {
         buf[..data.len()].copy_from_slice(&data);
@@ -218,22 +285,32 @@ pub(crate) fn c(
     let callee_limit = if gas_limit > 0 && gas_limit < caller_remaining {
         gas_limit
     } else {
-        let div = caller_remaining / 100 * GAS_PASS_PCT;
-        let rem = caller_remaining % 100 * GAS_PASS_PCT / 100;
-        div + rem
+        let (num, den) = env.gas_pass_fraction();
+        (caller_remaining as u128 * num as u128 / den as u128) as u64
     };
 
-    let with_memory = |memory: &mut [u8]| -> Result<_, Error> {
-        let arg_buf = &memory[argbuf_ofs..][..ARGBUF_LEN];
+    // Refunds and self-destructs requested by the callee are discarded if it
+    // fails.
+    let caller_refund = env.refund();
+    let caller_destructed = env.destructed();
 
-        let mut mod_id = ContractId::uninitialized();
+    let mut mod_id = ContractId::uninitialized();
+    instance.with_memory(|memory| {
         mod_id.as_bytes_mut().copy_from_slice(
             &memory[mod_id_ofs..][..std::mem::size_of::<ContractId>()],
         );
+    });
+
+    // Exceeding the maximum call depth fails the entire call, so the error is
+    // propagated as a trap instead of being returned to the caller.
+    let callee_stack_element = match env.push_callstack(mod_id, callee_limit) {
+        Err(err @ Error::CallStackOverflow(_)) => return Err(err.into()),
+        res => res.expect("pushing to the callstack should succeed"),
+    };
+
+    let with_memory = |memory: &mut [u8]| -> Result<_, Error> {
+        let arg_buf = &memory[argbuf_ofs..][..ARGBUF_LEN];
 
-        let callee_stack_element = env
-            .push_callstack(mod_id, callee_limit)
-            .expect("pushing to the callstack should succeed");
         let callee = env
             .instance(&callee_stack_element.contract_id)
             .expect("callee instance should exist");
@@ -276,8 +353,14 @@ pub(crate) fn c(
                 };
             }
             env.move_up_prune_call_tree();
+            env.set_refund(caller_refund);
+            env.set_destructed(caller_destructed);
             instance.set_remaining_gas(caller_remaining - callee_limit);
 
+            if let Error::CallStackOverflow(_) = err {
+                return Err(err.into());
+            }
+
             let c_err = ContractError::from(err);
             instance.with_arg_buf_mut(|buf| {
                 c_err.to_parts(buf);
@@ -290,11 +373,34 @@ pub(crate) fn c(
 }
 
 pub(crate) fn emit(
+    fenv: Caller<Env>,
+    topic_ofs: usize,
+    topic_len: u32,
+    arg_len: u32,
+) -> WasmtimeResult<()> {
+    emit_inner(fenv, topic_ofs, topic_len, arg_len, false)
+}
+
+pub(crate) fn emit_typed(
+    fenv: Caller<Env>,
+    topic_ofs: usize,
+    topic_len: u32,
+    arg_len: u32,
+) -> WasmtimeResult<()> {
+    emit_inner(fenv, topic_ofs, topic_len, arg_len, true)
+}
+
+/// Emits an event, with the argument buffer optionally starting with the
+/// little-endian `u16` version of the data.
+fn emit_inner(
     mut fenv: Caller<Env>,
     topic_ofs: usize,
     topic_len: u32,
     arg_len: u32,
+    versioned: bool,
 ) -> WasmtimeResult<()> {
+    const VERSION_LEN: usize = std::mem::size_of::<u16>();
+
     let env = fenv.data_mut();
     let instance = env.self_instance();
 
@@ -303,9 +409,21 @@ pub(crate) fn emit(
     check_ptr(instance, topic_ofs, topic_len)?;
     check_arg(instance, arg_len)?;
 
-    let data = instance.with_arg_buf(|buf| {
-        let arg_len = arg_len as usize;
-        Vec::from(&buf[..arg_len])
+    let arg_len = arg_len as usize;
+    if versioned && arg_len < VERSION_LEN {
+        return Err(Error::InvalidArgumentBuffer.into());
+    }
+
+    let (version, data) = instance.with_arg_buf(|buf| {
+        let buf = &buf[..arg_len];
+        match versioned {
+            false => (None, Vec::from(buf)),
+            true => {
+                let (version, data) = buf.split_at(VERSION_LEN);
+                let version = u16::from_le_bytes([version[0], version[1]]);
+                (Some(version), Vec::from(data))
+            }
+        }
     });
 
     let topic = instance.with_memory(|buf| {
@@ -314,7 +432,7 @@ pub(crate) fn emit(
             .map(ToOwned::to_owned)
     })?;
 
-    env.emit(topic, data);
+    env.emit(topic, version, data);
 
     Ok(())
 }
@@ -332,6 +450,25 @@ fn caller(env: Caller<Env>) {
     })
 }
 
+fn caller_stack(env: Caller<Env>) -> u32 {
+    let env = env.data();
+
+    let max_callers = ARGBUF_LEN / CONTRACT_ID_BYTES;
+    let callers: Vec<ContractId> = (1..=max_callers)
+        .map_while(|n| env.nth_from_top(n))
+        .map(|elem| elem.contract_id)
+        .collect();
+
+    env.self_instance().with_arg_buf_mut(|arg| {
+        for (i, caller) in callers.iter().enumerate() {
+            arg[i * CONTRACT_ID_BYTES..][..CONTRACT_ID_BYTES]
+                .copy_from_slice(caller.as_bytes());
+        }
+    });
+
+    callers.len() as u32
+}
+
 fn feed(mut fenv: Caller<Env>, arg_len: u32) -> WasmtimeResult<()> {
     let env = fenv.data_mut();
     let instance = env.self_instance();
@@ -347,8 +484,30 @@ fn feed(mut fenv: Caller<Env>, arg_len: u32) -> WasmtimeResult<()> {
 }
 
 #[cfg(feature = "debug")]
-fn hdebug(mut fenv: Caller<Env>, msg_len: u32) -> WasmtimeResult<()> {
+fn hdebug(fenv: Caller<Env>, msg_len: u32) -> WasmtimeResult<()> {
+    register_debug(fenv, DebugLevel::Debug, msg_len)
+}
+
+#[cfg(feature = "debug")]
+fn hdebug_level(
+    fenv: Caller<Env>,
+    level: u32,
+    msg_len: u32,
+) -> WasmtimeResult<()> {
+    let level = DebugLevel::from_u32(level).ok_or_else(|| {
+        Error::DecodingError(format!("Invalid debug level: {level}").into())
+    })?;
+    register_debug(fenv, level, msg_len)
+}
+
+#[cfg(feature = "debug")]
+fn register_debug(
+    mut fenv: Caller<Env>,
+    level: DebugLevel,
+    msg_len: u32,
+) -> WasmtimeResult<()> {
     let env = fenv.data_mut();
+    let contract = *env.self_contract_id();
     let instance = env.self_instance();
 
     check_arg(instance, msg_len)?;
@@ -361,7 +520,7 @@ fn hdebug(mut fenv: Caller<Env>, msg_len: u32) -> WasmtimeResult<()> {
             Err(err) => return Err(Error::Utf8(err)),
         };
 
-        env.register_debug(msg);
+        env.register_debug(contract, level, msg);
         println!("CONTRACT DEBUG {msg}");
 
         Ok(())
@@ -382,6 +541,104 @@ fn spent(fenv: Caller<Env>) -> u64 {
     limit - remaining
 }
 
+fn remaining(fenv: Caller<Env>) -> u64 {
+    fenv.data().self_instance().get_remaining_gas()
+}
+
+/// Copies `len` bytes of the contract's memory at the given offset to its
+/// argument buffer.
+pub(crate) fn mem_read(
+    mut fenv: Caller<Env>,
+    ofs: usize,
+    len: u32,
+) -> WasmtimeResult<()> {
+    let env = fenv.data_mut();
+    let instance = env.self_instance();
+
+    check_ptr(instance, ofs, len as usize)?;
+    check_arg(instance, len)?;
+
+    let arg_ofs = instance.arg_buffer_offset();
+    let len = len as usize;
+
+    instance.with_memory_mut(|memory| {
+        memory.copy_within(ofs..ofs + len, arg_ofs);
+    });
+
+    Ok(())
+}
+
+/// Copies `len` bytes of the contract's argument buffer to its memory at the
+/// given offset.
+pub(crate) fn mem_write(
+    mut fenv: Caller<Env>,
+    ofs: usize,
+    len: u32,
+) -> WasmtimeResult<()> {
+    let env = fenv.data_mut();
+    let instance = env.self_instance();
+
+    check_ptr(instance, ofs, len as usize)?;
+    check_arg(instance, len)?;
+
+    let arg_ofs = instance.arg_buffer_offset();
+    let len = len as usize;
+
+    instance.with_memory_mut(|memory| {
+        memory.copy_within(arg_ofs..arg_ofs + len, ofs);
+    });
+
+    Ok(())
+}
+
+fn block_height(fenv: Caller<Env>) -> u64 {
+    fenv.data().block_height()
+}
+
+fn block_timestamp(fenv: Caller<Env>) -> u64 {
+    fenv.data().block_timestamp()
+}
+
+/// The number of bytes of memory hashed by [`self_state_hash`] per unit of
+/// gas - the size of a blake3 block.
+const STATE_HASH_BYTES_PER_GAS: u64 = 64;
+
+fn self_state_hash(fenv: Caller<Env>) -> WasmtimeResult<()> {
+    let env = fenv.data();
+    let instance = env.self_instance();
+
+    // Hashing is charged by the size of the whole memory, since that is what
+    // is hashed, regardless of how much of it is in use.
+    let mem_len = instance.with_memory(|mem| mem.len()) as u64;
+    let cost = mem_len.div_ceil(STATE_HASH_BYTES_PER_GAS);
+
+    let remaining = instance.get_remaining_gas();
+    if remaining < cost {
+        instance.set_remaining_gas(0);
+        return Err(Error::OutOfGas.into());
+    }
+    instance.set_remaining_gas(remaining - cost);
+
+    let hash = instance.with_memory(|mem| blake3::hash(mem));
+    let slice = hash.as_bytes();
+    let len = slice.len();
+    instance.with_arg_buf_mut(|arg| arg[..len].copy_from_slice(slice));
+
+    Ok(())
+}
+
+fn self_destruct(mut fenv: Caller<Env>) {
+    let env = fenv.data_mut();
+    let self_id = *env.self_contract_id();
+    env.destruct(self_id);
+}
+
+fn refund(mut fenv: Caller<Env>, gas: u64) {
+    let env = fenv.data_mut();
+    let refund = env.refund().saturating_add(gas);
+    env.set_refund(refund);
+}
+
 fn panic(fenv: Caller<Env>, arg_len: u32) -> WasmtimeResult<()> {
     let env = fenv.data();
     let instance = env.self_instance();
@@ -26,6 +26,14 @@ pub(crate) fn hd(
     imports::hd(fenv, name_ofs as usize, name_len)
 }
 
+pub(crate) fn hgd(
+    fenv: Caller<Env>,
+    name_ofs: u64,
+    name_len: u32,
+) -> WasmtimeResult<u32> {
+    imports::hgd(fenv, name_ofs as usize, name_len)
+}
+
 pub(crate) fn c(
     fenv: Caller<Env>,
     mod_id_ofs: u64,
@@ -53,6 +61,31 @@ pub(crate) fn emit(
     imports::emit(fenv, topic_ofs as usize, topic_len, arg_len)
 }
 
+pub(crate) fn emit_typed(
+    fenv: Caller<Env>,
+    topic_ofs: u64,
+    topic_len: u32,
+    arg_len: u32,
+) -> WasmtimeResult<()> {
+    imports::emit_typed(fenv, topic_ofs as usize, topic_len, arg_len)
+}
+
+pub(crate) fn mem_read(
+    fenv: Caller<Env>,
+    ofs: u64,
+    len: u32,
+) -> WasmtimeResult<()> {
+    imports::mem_read(fenv, ofs as usize, len)
+}
+
+pub(crate) fn mem_write(
+    fenv: Caller<Env>,
+    ofs: u64,
+    len: u32,
+) -> WasmtimeResult<()> {
+    imports::mem_write(fenv, ofs as usize, len)
+}
+
 pub(crate) fn owner(fenv: Caller<Env>, mod_id_ofs: u64) -> WasmtimeResult<i32> {
     imports::owner(fenv, mod_id_ofs as usize)
 }
@@ -10,7 +10,6 @@ use std::ops::{Deref, DerefMut};
 use dusk_wasmtime::{Instance, Module, Mutability, Store, ValType};
 use piecrust_uplink::{ContractId, Event, ARGBUF_LEN};
 
-use crate::contract::WrappedContract;
 use crate::imports::Imports;
 use crate::session::Session;
 use crate::store::Memory;
@@ -23,7 +22,11 @@ pub struct WrappedInstance {
     memory: Memory,
 }
 
-pub(crate) struct Env {
+/// The data held by the store of a contract instance.
+///
+/// Exposed only to name the store handed out by
+/// [`Session::with_wasmtime_store`] and [`Session::with_wasmtime_store_mut`].
+pub struct Env {
     self_id: ContractId,
     session: Session,
 }
@@ -43,7 +46,7 @@ impl DerefMut for Env {
 }
 
 impl Env {
-    pub fn self_instance<'b>(&self) -> &'b mut WrappedInstance {
+    pub(crate) fn self_instance<'b>(&self) -> &'b mut WrappedInstance {
         let stack_element = self
             .session
             .nth_from_top(0)
@@ -52,31 +55,37 @@ impl Env {
             .expect("instance should exist")
     }
 
-    pub fn instance<'b>(
+    pub(crate) fn instance<'b>(
         &self,
         contract_id: &ContractId,
     ) -> Option<&'b mut WrappedInstance> {
         self.session.instance(contract_id)
     }
 
-    pub fn limit(&self) -> u64 {
+    pub(crate) fn limit(&self) -> u64 {
         self.session
             .nth_from_top(0)
             .expect("there should be at least one element in the call stack")
             .limit
     }
 
-    pub fn emit(&mut self, topic: String, data: Vec<u8>) {
+    pub(crate) fn emit(
+        &mut self,
+        topic: String,
+        version: Option<u16>,
+        data: Vec<u8>,
+    ) {
         let event = Event {
             source: self.self_id,
             topic,
+            version,
             data,
         };
 
         self.session.push_event(event);
     }
 
-    pub fn self_contract_id(&self) -> &ContractId {
+    pub(crate) fn self_contract_id(&self) -> &ContractId {
         &self.self_id
     }
 }
@@ -85,7 +94,7 @@ impl WrappedInstance {
     pub fn new(
         session: Session,
         contract_id: ContractId,
-        contract: &WrappedContract,
+        module: &Module,
         memory: Memory,
     ) -> Result<Self, Error> {
         let mut memory = memory;
@@ -96,8 +105,6 @@ impl WrappedInstance {
             session,
         };
 
-        let module =
-            unsafe { Module::deserialize(&engine, contract.as_bytes())? };
         let mut store = Store::new(&engine, env);
 
         // Ensure there is at most one memory exported, and that it is called
@@ -146,8 +153,8 @@ impl WrappedInstance {
             }
         }
 
-        let imports = Imports::for_module(&mut store, &module, is_64)?;
-        let instance = Instance::new(&mut store, &module, &imports)?;
+        let imports = Imports::for_module(&mut store, module, is_64)?;
+        let instance = Instance::new(&mut store, module, &imports)?;
 
         // Ensure there is a global exported named `A`, whose value is in the
         // memory.
@@ -235,12 +242,12 @@ impl WrappedInstance {
 
     /// Returns the current length of the memory.
     pub(crate) fn mem_len(&self) -> usize {
-        self.memory.current_len
+        self.memory.current_len()
     }
 
     /// Sets the length of the memory.
     pub(crate) fn set_len(&mut self, len: usize) {
-        self.memory.current_len = len;
+        self.memory.set_current_len(len);
     }
 
     pub(crate) fn with_arg_buf<F, R>(&self, f: F) -> R
@@ -299,6 +306,25 @@ impl WrappedInstance {
             .map_err(|e| map_call_err(self, e))
     }
 
+    /// Returns the underlying store of the instance.
+    ///
+    /// This is meant for diagnostics only. The store holds the gas remaining
+    /// for the running call, and the state of the instance, so relying on it
+    /// may break with any change to piecrust's internals.
+    #[cfg(feature = "debug-internals")]
+    pub fn wasmtime_store(&self) -> &Store<Env> {
+        &self.store
+    }
+
+    /// Returns the underlying store of the instance mutably.
+    ///
+    /// This is meant for diagnostics only. Mutating the store - such as by
+    /// setting its fuel - can corrupt metering and the state of the instance.
+    #[cfg(feature = "debug-internals")]
+    pub fn wasmtime_store_mut(&mut self) -> &mut Store<Env> {
+        &mut self.store
+    }
+
     pub fn set_remaining_gas(&mut self, limit: u64) {
         self.store.set_fuel(limit).expect("Fuel is enabled");
     }
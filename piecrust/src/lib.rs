@@ -22,6 +22,12 @@
 //! about the compiler middleware used to achieve this, please refer to the
 //! relevant [runtime docs].
 //!
+//! Gas is charged at one unit per executed WASM operator, with some control
+//! flow operators - such as `nop`, `block` and `end` - being free. This cost
+//! model is built into the code generated by the runtime, and is therefore not
+//! configurable per operator. The cost of a contract function may be estimated
+//! statically using [`estimate_opcode_cost`].
+//!
 //! # State Representation and Session/Commit Mechanism
 //!
 //! Smart Contracts are represented on disk by two separate files: their WASM
@@ -119,20 +125,41 @@
 mod bytecode_macro;
 mod call_tree;
 mod contract;
+mod cost;
+mod encoding;
 mod error;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
 mod imports;
 mod instance;
 mod session;
 mod store;
+#[cfg(feature = "test-utils")]
+mod test_utils;
 mod types;
 mod vm;
 
 pub use call_tree::{CallTree, CallTreeElem};
-pub use contract::{ContractData, ContractDataBuilder};
+pub use contract::{ContractData, ContractDataBuilder, ABI_SECTION};
+pub use cost::estimate_opcode_cost;
+pub use encoding::{decode_contract_map, encode_contract_map, hash_events};
 pub use error::Error;
-pub use session::{CallReceipt, Session, SessionData};
-pub use store::PageOpening;
-pub use vm::{HostQuery, VM};
+pub use imports::GAS_PASS_PCT;
+#[cfg(feature = "debug-internals")]
+pub use instance::Env;
+pub use session::{
+    CallReceipt, CallRecord, DebugRecord, FeedStream, Session, SessionData,
+    SnapshotId, DEFAULT_MAX_CALL_DEPTH, MAX_REFUND_PCT,
+};
+pub use store::{
+    CommitDelta, CommitInfo, CommitLayout, MemoryReport, ModuleCacheReport,
+    PageOpening, STORE_FORMAT_VERSION,
+};
+#[cfg(feature = "test-utils")]
+pub use test_utils::TestSessionBuilder;
+#[cfg(feature = "test-utils")]
+pub use vm::TIME_QUERY;
+pub use vm::{DeployPolicy, DeployRequest, FallibleHostQuery, HostQuery, VM};
 
 // re-export the contents of the `piecrust-uplink` crate wholesale, ensuring
 // this is the only crate we need to define and use a VM.
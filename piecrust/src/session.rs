@@ -5,16 +5,22 @@
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
 use std::mem;
 use std::sync::{mpsc, Arc};
 
 use bytecheck::CheckBytes;
+#[cfg(feature = "debug-internals")]
+use dusk_wasmtime::Store;
 use dusk_wasmtime::{Engine, LinearMemory, MemoryCreator, MemoryType};
-use piecrust_uplink::{ContractId, Event, ARGBUF_LEN, SCRATCH_BUF_BYTES};
+use piecrust_uplink::{
+    ContractId, DebugLevel, Event, ARGBUF_LEN, SCRATCH_BUF_BYTES,
+};
 use rkyv::ser::serializers::{
-    BufferScratch, BufferSerializer, CompositeSerializer,
+    BufferScratch, BufferSerializer, BufferSerializerError,
+    CompositeSerializer, CompositeSerializerError,
 };
 use rkyv::ser::Serializer;
 use rkyv::{
@@ -23,16 +29,36 @@ use rkyv::{
 };
 
 use crate::call_tree::{CallTree, CallTreeElem};
-use crate::contract::{ContractData, ContractMetadata, WrappedContract};
+use crate::contract::{
+    check_data_segments, custom_section, imported_functions, ContractData,
+    ContractMetadata, WrappedContract, ABI_SECTION,
+};
+use crate::encoding::hash_events;
 use crate::error::Error::{self, InitalizationError, PersistenceError};
+use crate::imports::GAS_PASS_PCT;
+#[cfg(feature = "debug-internals")]
+use crate::instance::Env;
 use crate::instance::WrappedInstance;
 use crate::store::{ContractSession, PageOpening, PAGE_SIZE};
 use crate::types::StandardBufSerializer;
-use crate::vm::HostQueries;
+use crate::vm::{DeployRequest, HostQueries, SharedDeployPolicy, VM};
 
 const MAX_META_SIZE: usize = ARGBUF_LEN;
 pub const INIT_METHOD: &str = "init";
 
+/// The maximum number of calls performed by [`Session::estimate_gas`] while
+/// searching for the minimum gas limit. Since the search space is at most
+/// `2^64` wide, this is enough to always find the exact minimum.
+const MAX_ESTIMATE_ITERATIONS: usize = 64;
+
+/// The default maximum depth of the call stack. See
+/// [`Session::set_max_call_depth`].
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+/// The maximum percentage of the gas spent by a call that may be refunded to
+/// it. See [`Session::gas_refund`].
+pub const MAX_REFUND_PCT: u64 = 20;
+
 unsafe impl Send for Session {}
 
 unsafe impl Sync for Session {}
@@ -87,11 +113,32 @@ struct SessionInner {
 
     call_tree: CallTree,
     instances: BTreeMap<ContractId, *mut WrappedInstance>,
-    debug: Vec<String>,
+    debug: Vec<DebugRecord>,
     data: SessionData,
+    call_data: BTreeMap<String, Vec<u8>>,
+    global_data: BTreeMap<Cow<'static, str>, Vec<u8>>,
+    gas_pass_fraction: (u64, u64),
+    touched: BTreeSet<ContractId>,
+    destructed: BTreeSet<ContractId>,
+    max_call_depth: usize,
+    host_query_limit: Option<usize>,
+    host_queries_made: usize,
+    refund: u64,
+    max_arg_len: usize,
+    scratch_size: usize,
+    max_memory_len: Option<usize>,
+    read_only: bool,
+    block_height: u64,
+    block_timestamp: u64,
+    block_limit: Option<u64>,
+    block_spent: u64,
+    call_records: Vec<CallRecord>,
+    snapshots: BTreeMap<usize, Snapshot>,
+    next_snapshot: usize,
 
     contract_session: ContractSession,
     host_queries: HostQueries,
+    deploy_policy: SharedDeployPolicy,
     buffer: Vec<u8>,
 
     feeder: Option<mpsc::Sender<Vec<u8>>>,
@@ -123,7 +170,7 @@ unsafe impl MemoryCreator for Session {
             .memory;
 
         if memory.is_new {
-            memory.current_len = minimum;
+            memory.set_current_len(minimum);
         }
 
         Ok(Box::new(memory))
@@ -135,6 +182,7 @@ impl Session {
         engine: Engine,
         contract_session: ContractSession,
         host_queries: HostQueries,
+        deploy_policy: SharedDeployPolicy,
         data: SessionData,
     ) -> Self {
         let inner = SessionInner {
@@ -143,8 +191,29 @@ impl Session {
             instances: BTreeMap::new(),
             debug: vec![],
             data,
+            call_data: BTreeMap::new(),
+            global_data: BTreeMap::new(),
+            gas_pass_fraction: (GAS_PASS_PCT, 100),
+            touched: BTreeSet::new(),
+            destructed: BTreeSet::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            host_query_limit: None,
+            host_queries_made: 0,
+            refund: 0,
+            max_arg_len: ARGBUF_LEN,
+            scratch_size: SCRATCH_BUF_BYTES,
+            max_memory_len: None,
+            read_only: false,
+            block_height: 0,
+            block_timestamp: 0,
+            block_limit: None,
+            block_spent: 0,
+            call_records: Vec::new(),
+            snapshots: BTreeMap::new(),
+            next_snapshot: 0,
             contract_session,
             host_queries,
+            deploy_policy,
             buffer: vec![0; PAGE_SIZE],
             feeder: None,
             events: vec![],
@@ -168,6 +237,140 @@ impl Session {
         session
     }
 
+    /// Makes the session read-only. See [`VM::read_only_session`].
+    pub(crate) fn set_read_only(&mut self) {
+        self.inner.read_only = true;
+    }
+
+    /// Forks the session, returning an independent session starting from the
+    /// same state.
+    ///
+    /// The fork shares the base commit of this session, and has a copy of the
+    /// memory of every contract loaded or deployed in it. Calls made in the
+    /// fork do not affect this session, and vice versa, allowing for
+    /// speculatively executing different calls from a common state.
+    ///
+    /// The fork inherits the session's metadata and block context, and the gas
+    /// spent against the block limit. Events, the contracts touched by the
+    /// last call, the records of previous calls, and snapshots are not
+    /// inherited.
+    ///
+    /// # Errors
+    /// If the base commit is already in use by the maximum number of sessions
+    /// set using [`VM::set_max_sessions_per_commit`].
+    ///
+    /// [`VM::set_max_sessions_per_commit`]: crate::VM::set_max_sessions_per_commit
+    pub fn fork(&self) -> Result<Session, Error> {
+        let contract_session = self
+            .inner
+            .contract_session
+            .fork()
+            .map_err(|err| PersistenceError(Arc::new(err)))?;
+
+        let mut fork = Session::new(
+            self.engine.clone(),
+            contract_session,
+            self.inner.host_queries.clone(),
+            self.inner.deploy_policy.clone(),
+            self.inner.data.clone(),
+        );
+
+        fork.inner.global_data = self.inner.global_data.clone();
+        fork.inner.max_call_depth = self.inner.max_call_depth;
+        fork.inner.host_query_limit = self.inner.host_query_limit;
+        fork.inner.max_arg_len = self.inner.max_arg_len;
+        fork.inner.scratch_size = self.inner.scratch_size;
+        fork.inner.max_memory_len = self.inner.max_memory_len;
+        fork.inner.gas_pass_fraction = self.inner.gas_pass_fraction;
+        fork.inner.read_only = self.inner.read_only;
+        fork.inner.block_height = self.inner.block_height;
+        fork.inner.block_timestamp = self.inner.block_timestamp;
+        fork.inner.block_limit = self.inner.block_limit;
+        fork.inner.block_spent = self.inner.block_spent;
+        fork.inner.next_snapshot = self.inner.next_snapshot;
+
+        Ok(fork)
+    }
+
+    /// Takes a snapshot of the current state of the session, which may later
+    /// be returned to using [`restore`].
+    ///
+    /// Like a [`fork`], the snapshot holds a copy of the memory of every
+    /// contract loaded or deployed in the session. It also records the number
+    /// of calls made and the gas spent against the block limit. It also holds
+    /// the base commit, and so fails in the same cases as a fork.
+    ///
+    /// [`restore`]: Session::restore
+    /// [`fork`]: Session::fork
+    pub fn snapshot(&mut self) -> Result<SnapshotId, Error> {
+        let contract_session = self
+            .inner
+            .contract_session
+            .fork()
+            .map_err(|err| PersistenceError(Arc::new(err)))?;
+
+        // Identifiers are never reused, so a snapshot that was discarded can't
+        // be confused with one taken later.
+        let id = SnapshotId(self.inner.next_snapshot);
+        self.inner.next_snapshot += 1;
+
+        self.inner.snapshots.insert(
+            id.0,
+            Snapshot {
+                contract_session,
+                call_records: self.inner.call_records.len(),
+                block_spent: self.inner.block_spent,
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Restores the session to the state it was in when the given
+    /// [`snapshot`] was taken.
+    ///
+    /// The records of the calls made since are discarded, and so are all
+    /// snapshots taken after the given one. The snapshot itself is kept, and
+    /// may be restored again.
+    ///
+    /// # Errors
+    /// If the snapshot doesn't exist, such as when it was discarded by
+    /// restoring to an earlier one, or by resetting the session.
+    ///
+    /// [`snapshot`]: Session::snapshot
+    pub fn restore(&mut self, id: SnapshotId) -> Result<(), Error> {
+        let snapshot = self
+            .inner
+            .snapshots
+            .get(&id.0)
+            .ok_or(Error::InvalidSnapshot(id.0))?;
+
+        let contract_session = snapshot
+            .contract_session
+            .fork()
+            .map_err(|err| PersistenceError(Arc::new(err)))?;
+        let call_records = snapshot.call_records;
+        let block_spent = snapshot.block_spent;
+
+        self.inner.contract_session = contract_session;
+        self.inner.call_records.truncate(call_records);
+        self.inner.block_spent = block_spent;
+        self.inner.touched.clear();
+        self.inner.destructed.clear();
+        self.inner.snapshots.retain(|snapshot, _| *snapshot <= id.0);
+
+        Ok(())
+    }
+
+    /// Returns whether the session is read-only.
+    ///
+    /// A read-only session can't deploy contracts or be committed, and the
+    /// changes made to the state by its calls are always discarded. See
+    /// [`VM::read_only_session`].
+    pub fn is_read_only(&self) -> bool {
+        self.inner.read_only
+    }
+
     /// Clone the given session. We explicitly **do not** implement the
     /// [`Clone`] trait here, since we don't want allow the user to clone a
     /// session.
@@ -235,8 +438,8 @@ impl Session {
 
         let mut constructor_arg = None;
         if let Some(arg) = deploy_data.constructor_arg {
-            let mut sbuf = [0u8; SCRATCH_BUF_BYTES];
-            let scratch = BufferScratch::new(&mut sbuf);
+            let mut sbuf = vec![0u8; self.inner.scratch_size];
+            let scratch = BufferScratch::new(&mut sbuf[..]);
             let ser = BufferSerializer::new(&mut self.inner.buffer[..]);
             let mut ser = CompositeSerializer::new(ser, scratch, Infallible);
 
@@ -260,6 +463,43 @@ impl Session {
         Ok(contract_id)
     }
 
+    /// Deploy a contract unless a contract with the same ID already exists in
+    /// the session, returning whether it was deployed.
+    ///
+    /// The ID is determined as in [`deploy`], and if the contract already
+    /// exists the session is left untouched. This makes it possible to re-run
+    /// scripts bootstrapping a state without failing on contracts they've
+    /// already deployed.
+    ///
+    /// # Errors
+    /// If the contract doesn't exist and deploying it fails.
+    ///
+    /// [`deploy`]: Session::deploy
+    pub fn deploy_if_absent<'a, A, D>(
+        &mut self,
+        bytecode: &[u8],
+        deploy_data: D,
+        gas_limit: u64,
+    ) -> Result<bool, Error>
+    where
+        A: 'a + for<'b> Serialize<StandardBufSerializer<'b>>,
+        D: Into<ContractData<'a, A>>,
+    {
+        let mut deploy_data = deploy_data.into();
+
+        let contract_id = deploy_data.contract_id.unwrap_or_else(|| {
+            ContractId::from_bytes(blake3::hash(bytecode).into())
+        });
+        deploy_data.contract_id = Some(contract_id);
+
+        if self.inner.contract_session.contract_deployed(contract_id) {
+            return Ok(false);
+        }
+
+        self.deploy(bytecode, deploy_data, gas_limit)?;
+        Ok(true)
+    }
+
     fn do_deploy(
         &mut self,
         contract_id: ContractId,
@@ -268,12 +508,24 @@ impl Session {
         owner: Vec<u8>,
         gas_limit: u64,
     ) -> Result<(), Error> {
+        if self.inner.read_only {
+            return Err(Error::ReadOnlySession);
+        }
+
+        self.inner.deploy_policy.check(&DeployRequest {
+            bytecode,
+            contract_id,
+            owner: &owner,
+        })?;
+
         if self.inner.contract_session.contract_deployed(contract_id) {
             return Err(InitalizationError(
                 "Deployed error already exists".into(),
             ));
         }
 
+        check_data_segments(bytecode)?;
+
         let wrapped_contract =
             WrappedContract::new(&self.engine, bytecode, None::<&[u8]>)?;
         let contract_metadata = ContractMetadata { contract_id, owner };
@@ -302,7 +554,13 @@ impl Session {
                 // contract has a constructor in the first place, which might
                 // not be the case, such as when ingesting untrusted bytecode.
                 let arg = arg.unwrap_or_default();
-                self.call_inner(contract_id, INIT_METHOD, arg, gas_limit)?;
+                self.call_inner(
+                    contract_id,
+                    INIT_METHOD,
+                    arg,
+                    gas_limit,
+                    true,
+                )?;
             }
 
             Ok(())
@@ -343,8 +601,8 @@ impl Session {
             return Err(InitalizationError("init call not allowed".into()));
         }
 
-        let mut sbuf = [0u8; SCRATCH_BUF_BYTES];
-        let scratch = BufferScratch::new(&mut sbuf);
+        let mut sbuf = vec![0u8; self.inner.scratch_size];
+        let scratch = BufferScratch::new(&mut sbuf[..]);
         let ser = BufferSerializer::new(&mut self.inner.buffer[..]);
         let mut ser = CompositeSerializer::new(ser, scratch, Infallible);
 
@@ -381,8 +639,16 @@ impl Session {
             return Err(InitalizationError("init call not allowed".into()));
         }
 
-        let (data, gas_spent, call_tree) =
-            self.call_inner(contract, fn_name, fn_arg.into(), gas_limit)?;
+        let spent_before = self.inner.block_spent;
+        let r =
+            self.call_inner(contract, fn_name, fn_arg.into(), gas_limit, true);
+        self.inner.call_records.push(CallRecord {
+            gas_limit,
+            gas_spent: self.inner.block_spent - spent_before,
+            success: r.is_ok(),
+        });
+
+        let (data, gas_spent, call_tree) = r?;
         let events = mem::take(&mut self.inner.events);
 
         Ok(CallReceipt {
@@ -394,6 +660,140 @@ impl Session {
         })
     }
 
+    /// Estimate the minimum gas limit with which a call succeeds on the current
+    /// state of this session.
+    ///
+    /// The call is first executed with the given `gas_limit`, after which a
+    /// binary search is performed between the gas spent and the limit, until
+    /// the smallest limit under which the call still succeeds is found. This
+    /// may be larger than the gas spent, since contracts calling other
+    /// contracts only pass on a portion of their remaining gas.
+    ///
+    /// None of the calls made during the estimation modify the state of the
+    /// session, and any events they emit are discarded.
+    ///
+    /// # Errors
+    /// If the call fails when given the full `gas_limit`, the error is
+    /// returned.
+    pub fn estimate_gas<A>(
+        &mut self,
+        contract: ContractId,
+        fn_name: &str,
+        fn_arg: &A,
+        gas_limit: u64,
+    ) -> Result<u64, Error>
+    where
+        A: for<'b> Serialize<StandardBufSerializer<'b>>,
+        A::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+    {
+        if fn_name == INIT_METHOD {
+            return Err(InitalizationError("init call not allowed".into()));
+        }
+
+        let mut sbuf = vec![0u8; self.inner.scratch_size];
+        let scratch = BufferScratch::new(&mut sbuf[..]);
+        let ser = BufferSerializer::new(&mut self.inner.buffer[..]);
+        let mut ser = CompositeSerializer::new(ser, scratch, Infallible);
+
+        ser.serialize_value(fn_arg)?;
+        let pos = ser.pos();
+
+        let fn_arg = self.inner.buffer[..pos].to_vec();
+
+        let spent =
+            self.discarded_call(contract, fn_name, fn_arg.clone(), gas_limit)?;
+
+        // The call is known to succeed with `high`, and assumed to fail with
+        // anything below `low`.
+        let mut low = spent;
+        let mut high = gas_limit;
+
+        let mut iterations = 0;
+        while low < high && iterations < MAX_ESTIMATE_ITERATIONS {
+            let mid = low + (high - low) / 2;
+
+            match self.discarded_call(contract, fn_name, fn_arg.clone(), mid) {
+                Ok(_) => high = mid,
+                Err(_) => low = mid + 1,
+            }
+
+            iterations += 1;
+        }
+
+        Ok(high)
+    }
+
+    /// Execute a call on the current state of this session, discarding any
+    /// changes it makes to the state.
+    ///
+    /// The call is executed as in [`call`], and its receipt - including the
+    /// return and any emitted events - is returned as normal. However, the
+    /// state of the session is left exactly as it was before the call, meaning
+    /// that its [`root`] is unchanged. The gas spent is also not counted
+    /// against the block limit, if one is set.
+    ///
+    /// This is useful for previewing the result of a call, and the gas it
+    /// will spend, before executing it for real.
+    ///
+    /// [`call`]: Session::call
+    /// [`root`]: Session::root
+    pub fn dry_run<A, R>(
+        &mut self,
+        contract: ContractId,
+        fn_name: &str,
+        fn_arg: &A,
+        gas_limit: u64,
+    ) -> Result<CallReceipt<R>, Error>
+    where
+        A: for<'b> Serialize<StandardBufSerializer<'b>>,
+        A::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+        R: Archive,
+        R::Archived: Deserialize<R, Infallible>
+            + for<'b> CheckBytes<DefaultValidator<'b>>,
+    {
+        if fn_name == INIT_METHOD {
+            return Err(InitalizationError("init call not allowed".into()));
+        }
+
+        let mut sbuf = vec![0u8; self.inner.scratch_size];
+        let scratch = BufferScratch::new(&mut sbuf[..]);
+        let ser = BufferSerializer::new(&mut self.inner.buffer[..]);
+        let mut ser = CompositeSerializer::new(ser, scratch, Infallible);
+
+        ser.serialize_value(fn_arg)?;
+        let pos = ser.pos();
+
+        let fn_arg = self.inner.buffer[..pos].to_vec();
+
+        let r = self.call_inner(contract, fn_name, fn_arg, gas_limit, false);
+        let events = mem::take(&mut self.inner.events);
+        let (data, gas_spent, call_tree) = r?;
+
+        let receipt = CallReceipt {
+            gas_limit,
+            gas_spent,
+            events,
+            call_tree,
+            data,
+        };
+
+        receipt.deserialize()
+    }
+
+    /// Performs a call whose state changes and events are discarded, returning
+    /// the gas spent.
+    fn discarded_call(
+        &mut self,
+        contract: ContractId,
+        fn_name: &str,
+        fn_arg: Vec<u8>,
+        gas_limit: u64,
+    ) -> Result<u64, Error> {
+        let r = self.call_inner(contract, fn_name, fn_arg, gas_limit, false);
+        self.inner.events.clear();
+        r.map(|(_, spent, _)| spent)
+    }
+
     /// Migrates a `contract` to a new `bytecode`, performing modifications to
     /// its state as specified by the closure.
     ///
@@ -459,6 +859,73 @@ impl Session {
         Ok(self)
     }
 
+    /// Execute a call on the current state of this session, with the given
+    /// `meta`data available to contracts only for the duration of the call.
+    ///
+    /// The metadata is given as pairs of names and serialized values - see
+    /// [`serialize_data`] - and takes precedence over the session's metadata
+    /// under the same names. Once the call is finished, the session's metadata
+    /// is visible again.
+    ///
+    /// For more information about calls see [`call`].
+    ///
+    /// [`serialize_data`]: Session::serialize_data
+    /// [`call`]: Session::call
+    pub fn call_with_meta<A, R>(
+        &mut self,
+        contract: ContractId,
+        fn_name: &str,
+        fn_arg: &A,
+        meta: &[(String, Vec<u8>)],
+        gas_limit: u64,
+    ) -> Result<CallReceipt<R>, Error>
+    where
+        A: for<'b> Serialize<StandardBufSerializer<'b>>,
+        A::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+        R: Archive,
+        R::Archived: Deserialize<R, Infallible>
+            + for<'b> CheckBytes<DefaultValidator<'b>>,
+    {
+        self.inner.call_data = meta.iter().cloned().collect();
+        let r = self.call(contract, fn_name, fn_arg, gas_limit);
+        self.inner.call_data.clear();
+        r
+    }
+
+    /// Execute a call on the current state of this session, with inter-contract
+    /// calls forwarding the given percentage of the caller's remaining gas
+    /// when they don't specify a limit, instead of the fraction set using
+    /// [`set_icc_gas_fraction`] - by default [`GAS_PASS_PCT`].
+    ///
+    /// The percentage is capped at 100, and applies only for the duration of
+    /// the call.
+    ///
+    /// For more information about calls see [`call`].
+    ///
+    /// [`call`]: Session::call
+    /// [`set_icc_gas_fraction`]: Session::set_icc_gas_fraction
+    pub fn call_with_gas_pass_pct<A, R>(
+        &mut self,
+        contract: ContractId,
+        fn_name: &str,
+        fn_arg: &A,
+        gas_pass_pct: u64,
+        gas_limit: u64,
+    ) -> Result<CallReceipt<R>, Error>
+    where
+        A: for<'b> Serialize<StandardBufSerializer<'b>>,
+        A::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+        R: Archive,
+        R::Archived: Deserialize<R, Infallible>
+            + for<'b> CheckBytes<DefaultValidator<'b>>,
+    {
+        let gas_pass_fraction = self.inner.gas_pass_fraction;
+        self.inner.gas_pass_fraction = (gas_pass_pct.min(100), 100);
+        let r = self.call(contract, fn_name, fn_arg, gas_limit);
+        self.inner.gas_pass_fraction = gas_pass_fraction;
+        r
+    }
+
     /// Execute a *feeder* call on the current state of this session.
     ///
     /// Feeder calls are used to have the contract be able to report larger
@@ -487,6 +954,48 @@ impl Session {
         r
     }
 
+    /// Execute a *feeder* call on the current state of this session,
+    /// collecting the data fed by the contract.
+    ///
+    /// Works as [`feeder_call`], except that the channel is managed by the
+    /// session. The returned stream yields every piece of data fed during the
+    /// call, in order, deserializing each as it is reached.
+    ///
+    /// [`feeder_call`]: Session::feeder_call
+    pub fn feed_call<A, T>(
+        &mut self,
+        contract: ContractId,
+        fn_name: &str,
+        fn_arg: &A,
+        gas_limit: u64,
+    ) -> Result<FeedStream<T>, Error>
+    where
+        A: for<'b> Serialize<StandardBufSerializer<'b>>,
+        A::Archived: for<'b> CheckBytes<DefaultValidator<'b>>,
+    {
+        let mut sbuf = vec![0u8; self.inner.scratch_size];
+        let scratch = BufferScratch::new(&mut sbuf[..]);
+        let ser = BufferSerializer::new(&mut self.inner.buffer[..]);
+        let mut ser = CompositeSerializer::new(ser, scratch, Infallible);
+
+        ser.serialize_value(fn_arg)?;
+        let pos = ser.pos();
+
+        let (sender, receiver) = mpsc::channel();
+        self.feeder_call_raw(
+            contract,
+            fn_name,
+            self.inner.buffer[..pos].to_vec(),
+            gas_limit,
+            sender,
+        )?;
+
+        Ok(FeedStream {
+            fed: receiver.into_iter(),
+            _marker: PhantomData,
+        })
+    }
+
     /// Execute a raw *feeder* call on the current state of this session.
     ///
     /// See [`feeder_call`] and [`call_raw`] for more information of this type
@@ -520,7 +1029,7 @@ impl Session {
             .contract_session
             .contract(contract_id)
             .map_err(|err| Error::PersistenceError(Arc::new(err)))?
-            .map(|data| data.memory.current_len))
+            .map(|data| data.memory.current_len()))
     }
 
     pub(crate) fn instance<'a>(
@@ -555,6 +1064,43 @@ impl Session {
         self.inner.contract_session.root().into()
     }
 
+    /// Returns the IDs of all contracts deployed in the current state of the
+    /// session, in ascending order.
+    ///
+    /// These are the contracts in the base commit, together with the ones
+    /// deployed in the session, minus any that have destructed themselves.
+    pub fn deployed_contracts(&self) -> Vec<ContractId> {
+        self.inner.contract_session.deployed_contracts()
+    }
+
+    /// Instantiates every contract deployed in the current state of the
+    /// session, without calling any of their functions, returning the result
+    /// for each contract in ascending order of ID.
+    ///
+    /// This loads each contract's compiled code and memory, and checks that it
+    /// can be instantiated - a stronger check than [`VM::verify_commit`],
+    /// which only checks memory against the state root.
+    pub fn instantiate_all(&mut self) -> Vec<(ContractId, Result<(), Error>)> {
+        self.deployed_contracts()
+            .into_iter()
+            .map(|contract| {
+                let result = self.new_instance(contract).map(|_| ());
+                (contract, result)
+            })
+            .collect()
+    }
+
+    /// Returns an estimate of the number of bytes committing the session would
+    /// add to disk.
+    ///
+    /// This counts the modified memory pages of all contracts, and the
+    /// bytecode and compiled code of newly deployed contracts, but not the
+    /// files shared with the base commit nor the commit's index. It is useful
+    /// for charging for storage before committing.
+    pub fn estimated_commit_bytes(&self) -> u64 {
+        self.inner.contract_session.estimated_commit_bytes()
+    }
+
     /// Returns an iterator over the pages (and their indices) of a contract's
     /// memory, together with a proof of their inclusion in the state.
     ///
@@ -575,6 +1121,26 @@ impl Session {
         self.inner.events.push(event);
     }
 
+    pub(crate) fn destruct(&mut self, contract: ContractId) {
+        self.inner.destructed.insert(contract);
+    }
+
+    pub(crate) fn destructed(&self) -> BTreeSet<ContractId> {
+        self.inner.destructed.clone()
+    }
+
+    pub(crate) fn set_destructed(&mut self, destructed: BTreeSet<ContractId>) {
+        self.inner.destructed = destructed;
+    }
+
+    pub(crate) fn refund(&self) -> u64 {
+        self.inner.refund
+    }
+
+    pub(crate) fn set_refund(&mut self, refund: u64) {
+        self.inner.refund = refund;
+    }
+
     pub(crate) fn push_feed(&mut self, data: Vec<u8>) -> Result<(), Error> {
         let feed = self.inner.feeder.as_ref().ok_or(Error::MissingFeed)?;
         feed.send(data).map_err(Error::FeedPulled)
@@ -591,18 +1157,14 @@ impl Session {
             .map_err(|err| PersistenceError(Arc::new(err)))?
             .ok_or(Error::ContractDoesNotExist(contract_id))?;
 
-        let contract = WrappedContract::new(
-            &self.engine,
-            store_data.bytecode,
-            Some(store_data.module.serialize()),
-        )?;
-
         self.inner.current = contract_id;
 
+        // The module is the one deserialized by the store, shared through its
+        // cache, so instantiating doesn't deserialize it again.
         let instance = WrappedInstance::new(
             self.clone(),
             contract_id,
-            &contract,
+            &store_data.module,
             store_data.memory,
         )?;
 
@@ -614,10 +1176,22 @@ impl Session {
         name: &str,
         buf: &mut [u8],
         arg_len: u32,
-    ) -> Option<u32> {
+    ) -> Option<Result<u32, Error>> {
         self.inner.host_queries.call(name, buf, arg_len)
     }
 
+    /// Records a host query being made, erroring if the limit on the number
+    /// of host queries per call is exceeded.
+    pub(crate) fn record_host_query(&mut self) -> Result<(), Error> {
+        self.inner.host_queries_made += 1;
+        match self.inner.host_query_limit {
+            Some(limit) if self.inner.host_queries_made > limit => {
+                Err(Error::HostQueryLimitExceeded(limit))
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub(crate) fn nth_from_top(&self, n: usize) -> Option<CallTreeElem> {
         self.inner.call_tree.nth_parent(n)
     }
@@ -642,11 +1216,28 @@ impl Session {
         Ok(mem_len)
     }
 
+    /// Pushes the given contract to the call tree, instantiating it if it is
+    /// not yet instantiated.
+    ///
+    /// Instances are created lazily, the first time a contract is pushed
+    /// during a call, and are all dropped at the end of each call. This means
+    /// the instantiation order is the order in which contracts are first
+    /// called, which is fully determined by the call's inputs and the state
+    /// it executes on. Since no contract can be instantiated twice during a
+    /// call, executing the same sequence of calls on the same state always
+    /// results in the same memories.
     pub(crate) fn push_callstack(
         &mut self,
         contract_id: ContractId,
         limit: u64,
     ) -> Result<CallTreeElem, Error> {
+        let max_depth = self.inner.max_call_depth;
+        if self.call_stack_depth() >= max_depth {
+            return Err(Error::CallStackOverflow(max_depth));
+        }
+
+        self.inner.touched.insert(contract_id);
+
         let instance = self.instance(&contract_id);
 
         match instance {
@@ -696,9 +1287,62 @@ impl Session {
         Ok(())
     }
 
+    /// Resets the session to an empty state with no base commit, as if it had
+    /// just been spawned with no base.
+    ///
+    /// All modifications made in the session are discarded, together with its
+    /// events, debug output, call records, snapshots, and the gas spent in it.
+    /// This allows for reusing the session to build a new genesis state,
+    /// without spawning a new one. Commits already in the VM - including the
+    /// session's previous base - are left untouched.
+    pub fn reset_to_genesis(&mut self) {
+        self.clear_stack_and_instances();
+        self.inner.contract_session.reset();
+
+        self.inner.data.base = None;
+        self.inner.call_data.clear();
+        self.inner.touched.clear();
+        self.inner.destructed.clear();
+        self.inner.debug.clear();
+        self.inner.events.clear();
+        self.inner.call_records.clear();
+        self.inner.snapshots.clear();
+        self.inner.block_spent = 0;
+    }
+
+    /// Rolls the session back to the state of its base commit, as if it had
+    /// just been spawned from it.
+    ///
+    /// All modifications made in the session are discarded, together with its
+    /// events, debug output, call records, snapshots, and the gas spent in
+    /// it. This is cheaper than spawning a new session, for instance when
+    /// retrying a set of calls.
+    pub fn rollback(&mut self) {
+        self.clear_stack_and_instances();
+        self.inner.contract_session.rollback();
+
+        self.inner.call_data.clear();
+        self.inner.touched.clear();
+        self.inner.destructed.clear();
+        self.inner.debug.clear();
+        self.inner.events.clear();
+        self.inner.call_records.clear();
+        self.inner.snapshots.clear();
+        self.inner.block_spent = 0;
+    }
+
     /// Commits the given session to disk, consuming the session and returning
     /// its state root.
+    ///
+    /// Commits are content-addressed by their root, so committing a state that
+    /// already exists in the VM returns the root of the existing commit
+    /// instead of writing a copy of it.
     pub fn commit(self) -> Result<[u8; 32], Error> {
+        if self.inner.read_only {
+            return Err(Error::ReadOnlySession);
+        }
+        self.check_memory_lens()?;
+
         self.inner
             .contract_session
             .commit()
@@ -706,40 +1350,424 @@ impl Session {
             .map_err(|err| PersistenceError(Arc::new(err)))
     }
 
+    /// Commits the given session to the directory of another `vm`, consuming
+    /// the session and returning its state root.
+    ///
+    /// The resulting commit will be available in the given `vm`, but not in
+    /// the one this session was spawned from. If the base commit of the
+    /// session exists in the given `vm`, unchanged files are hard linked from
+    /// it. Otherwise the commit is written in full, hard linking unchanged
+    /// files from the original base commit when both are on the same
+    /// filesystem, and copying them otherwise.
+    pub fn commit_to(self, vm: &VM) -> Result<[u8; 32], Error> {
+        if self.inner.read_only {
+            return Err(Error::ReadOnlySession);
+        }
+        self.check_memory_lens()?;
+
+        self.inner
+            .contract_session
+            .commit_to(vm.store())
+            .map(Into::into)
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
     #[cfg(feature = "debug")]
-    pub(crate) fn register_debug<M: Into<String>>(&mut self, msg: M) {
-        self.inner.debug.push(msg.into());
+    pub(crate) fn register_debug<M: Into<String>>(
+        &mut self,
+        contract: ContractId,
+        level: DebugLevel,
+        msg: M,
+    ) {
+        self.inner.debug.push(DebugRecord {
+            contract,
+            level,
+            message: msg.into(),
+        });
+    }
+
+    /// Instantiates the given `contract`, and calls the closure with the
+    /// underlying store of the instance.
+    ///
+    /// This is meant for diagnostics only, such as inspecting the engine. The
+    /// instance is dropped once the closure returns.
+    #[cfg(feature = "debug-internals")]
+    pub fn with_wasmtime_store<F, R>(
+        &mut self,
+        contract: ContractId,
+        f: F,
+    ) -> Result<R, Error>
+    where
+        F: FnOnce(&Store<Env>) -> R,
+    {
+        self.create_instance(contract)?;
+        let instance = self.instance(&contract).expect("instance should exist");
+
+        let r = f(instance.wasmtime_store());
+        self.clear_stack_and_instances();
+
+        Ok(r)
     }
 
+    /// Instantiates the given `contract`, and calls the closure with the
+    /// underlying store of the instance, mutably.
+    ///
+    /// This is meant for diagnostics only. Mutating the store can corrupt
+    /// metering and the state of the contract, so modifications to the
+    /// contract's memory made by the closure are discarded.
+    #[cfg(feature = "debug-internals")]
+    pub fn with_wasmtime_store_mut<F, R>(
+        &mut self,
+        contract: ContractId,
+        f: F,
+    ) -> Result<R, Error>
+    where
+        F: FnOnce(&mut Store<Env>) -> R,
+    {
+        self.create_instance(contract)?;
+        let instance = self.instance(&contract).expect("instance should exist");
+
+        instance
+            .snap()
+            .map_err(|err| Error::MemorySnapshotFailure {
+                reason: None,
+                io: Arc::new(err),
+            })?;
+        let mem_len = instance.mem_len();
+
+        let r = f(instance.wasmtime_store_mut());
+
+        let revert_result = instance.revert();
+        instance.set_len(mem_len);
+        self.clear_stack_and_instances();
+
+        revert_result.map_err(|err| Error::MemorySnapshotFailure {
+            reason: None,
+            io: Arc::new(err),
+        })?;
+
+        Ok(r)
+    }
+
+    /// Calls the closure with the debug output of the contracts executed in
+    /// the session, in the order it was sent.
     pub fn with_debug<C, R>(&self, c: C) -> R
     where
-        C: FnOnce(&[String]) -> R,
+        C: FnOnce(&[DebugRecord]) -> R,
     {
         c(&self.inner.debug)
     }
 
+    /// Calls the closure with the debug output of the contracts executed in
+    /// the session, mutably.
+    ///
+    /// This allows for filtering or draining the records, for instance to
+    /// only keep the ones above a given [`DebugLevel`].
+    pub fn with_debug_mut<C, R>(&mut self, c: C) -> R
+    where
+        C: FnOnce(&mut Vec<DebugRecord>) -> R,
+    {
+        c(&mut self.inner.debug)
+    }
+
+    /// Returns the messages of the debug output of the contracts executed in
+    /// the session, in the order they were sent.
+    pub fn debug_strings(&self) -> Vec<String> {
+        self.inner
+            .debug
+            .iter()
+            .map(|record| record.message.clone())
+            .collect()
+    }
+
+    /// Returns the amount of gas refunded to the most recent call.
+    ///
+    /// Contracts request refunds using the `refund` function of
+    /// `piecrust-uplink`, for instance when freeing state. The refunds
+    /// requested during a call are accumulated, and subtracted from the gas it
+    /// spent once it finishes - but only up to [`MAX_REFUND_PCT`] percent of
+    /// it. Refunds requested by inter-contract calls that fail, or by a call
+    /// that fails as a whole, are discarded.
+    pub fn gas_refund(&self) -> u64 {
+        self.inner.refund
+    }
+
+    /// Returns the set of contracts called during the most recent call,
+    /// including the contract called directly.
+    ///
+    /// This can be used to find whether a call made inter-contract calls, and
+    /// to which contracts. The set is also populated by a failed call, up to
+    /// the point where it failed.
+    pub fn last_call_touched(&self) -> BTreeSet<ContractId> {
+        self.inner.touched.clone()
+    }
+
+    /// Sets the maximum depth of the call stack, i.e. the maximum number of
+    /// nested inter-contract calls plus the top-level call.
+    ///
+    /// A call exceeding the depth fails as a whole with
+    /// [`Error::CallStackOverflow`]. Defaults to [`DEFAULT_MAX_CALL_DEPTH`].
+    pub fn set_max_call_depth(&mut self, max_depth: usize) {
+        self.inner.max_call_depth = max_depth;
+    }
+
+    /// Sets the maximum number of host queries a call may make, including the
+    /// ones made by the contracts it calls.
+    ///
+    /// A call exceeding the limit fails with [`Error::HostQueryLimitExceeded`].
+    /// By default the number of host queries is only bounded by the gas
+    /// limit.
+    pub fn set_host_query_limit(&mut self, limit: usize) {
+        self.inner.host_query_limit = Some(limit);
+    }
+
+    /// Sets the fraction of the caller's remaining gas forwarded to an
+    /// inter-contract call that doesn't specify a limit, as `num / den`.
+    ///
+    /// The fraction is capped at 1, and defaults to [`GAS_PASS_PCT`] percent.
+    /// Since it determines how much gas contracts have available, and
+    /// therefore the outcome of calls, changing it is consensus-breaking: all
+    /// nodes executing the same calls must use the same fraction.
+    ///
+    /// # Errors
+    /// If the denominator is zero.
+    pub fn set_icc_gas_fraction(
+        &mut self,
+        num: u64,
+        den: u64,
+    ) -> Result<(), Error> {
+        if den == 0 {
+            return Err(Error::SessionError(
+                "Gas fraction denominator must not be zero".into(),
+            ));
+        }
+        self.inner.gas_pass_fraction = (num.min(den), den);
+        Ok(())
+    }
+
+    /// Sets the maximum length of the serialized argument of a call made
+    /// from the host, capped at the length of the argument buffer.
+    ///
+    /// A call whose argument is longer fails with
+    /// [`Error::ArgumentBufferOverflow`] without being executed. Defaults to
+    /// the length of the argument buffer.
+    pub fn set_max_arg_len(&mut self, max_len: usize) {
+        self.inner.max_arg_len = max_len.min(ARGBUF_LEN);
+    }
+
+    /// Sets the size of the scratch space used to serialize the arguments of
+    /// calls and deployments, and the values of global metadata items.
+    ///
+    /// Serializing values containing collections - such as a `Vec<String>` -
+    /// requires scratch space proportional to the number of their elements,
+    /// and fails with [`Error::CompositeSerializerError`] when it runs out.
+    /// Defaults to [`SCRATCH_BUF_BYTES`].
+    ///
+    /// Note that this does not raise the bound on the length of the
+    /// serialized argument itself, which must still fit in the contract's
+    /// argument buffer of [`ARGBUF_LEN`] bytes.
+    pub fn set_scratch_size(&mut self, size: usize) {
+        self.inner.scratch_size = size;
+    }
+
+    /// Sets the maximum length of the memory of a contract for the session to
+    /// be committed.
+    ///
+    /// Committing a session in which any contract's memory is longer fails
+    /// with [`Error::MemoryLimitExceeded`], preventing a runaway contract from
+    /// persisting its full memory. By default the length of memories is only
+    /// bounded by the contracts themselves.
+    pub fn set_max_memory_len(&mut self, max_len: usize) {
+        self.inner.max_memory_len = Some(max_len);
+    }
+
+    fn check_memory_lens(&self) -> Result<(), Error> {
+        if let Some(max_len) = self.inner.max_memory_len {
+            for (contract, len) in self.inner.contract_session.memory_lens() {
+                if len > max_len {
+                    return Err(Error::MemoryLimitExceeded(contract));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the current depth of the call stack.
+    ///
+    /// This is zero unless a call is being executed.
+    pub fn call_stack_depth(&self) -> usize {
+        self.inner.call_tree.depth()
+    }
+
+    /// Sets the height and timestamp of the block in whose context contracts
+    /// are executed, made available to them through the `block_height` and
+    /// `block_timestamp` functions of `piecrust-uplink`.
+    ///
+    /// Both default to zero, and stay the same across calls until set again.
+    pub fn set_block_context(&mut self, height: u64, timestamp: u64) {
+        self.inner.block_height = height;
+        self.inner.block_timestamp = timestamp;
+    }
+
+    /// Sets a limit on the total gas spent by all the calls made in the
+    /// session, such as a block gas limit.
+    ///
+    /// A call whose gas limit, added to the gas already spent by previous
+    /// calls, exceeds the block limit is rejected with
+    /// [`Error::BlockLimitExceeded`] without being executed. Calls whose
+    /// changes are discarded, such as the ones made by [`estimate_gas`], are
+    /// not metered against the limit.
+    ///
+    /// [`estimate_gas`]: Session::estimate_gas
+    pub fn set_block_limit(&mut self, block_limit: u64) {
+        self.inner.block_limit = Some(block_limit);
+    }
+
+    /// Returns the total gas spent by the calls made in the session, as
+    /// metered against the limit set using [`set_block_limit`].
+    ///
+    /// [`set_block_limit`]: Session::set_block_limit
+    pub fn block_spent(&self) -> u64 {
+        self.inner.block_spent
+    }
+
+    /// Returns the record of the `index`-th call made in the session, if it
+    /// exists.
+    ///
+    /// A record is kept for every call made using [`call`], or any of its
+    /// variants, in the order they were made - whether they succeeded or not.
+    /// Calls whose changes are discarded, such as the ones made by
+    /// [`estimate_gas`], and the constructor calls made during deployment, are
+    /// not recorded.
+    ///
+    /// [`call`]: Session::call
+    /// [`estimate_gas`]: Session::estimate_gas
+    pub fn call_record(&self, index: usize) -> Option<CallRecord> {
+        self.inner.call_records.get(index).copied()
+    }
+
+    /// Returns the gas spent by each call made in the session, in the order
+    /// the calls were made.
+    ///
+    /// This includes the same calls as the ones recorded by [`call_record`].
+    ///
+    /// [`call_record`]: Session::call_record
+    pub fn spent_per_call(&self) -> Vec<u64> {
+        self.inner
+            .call_records
+            .iter()
+            .map(|record| record.gas_spent)
+            .collect()
+    }
+
+    pub(crate) fn block_height(&self) -> u64 {
+        self.inner.block_height
+    }
+
+    pub(crate) fn block_timestamp(&self) -> u64 {
+        self.inner.block_timestamp
+    }
+
+    /// Returns the fraction of the remaining gas forwarded to inter-contract
+    /// calls not specifying a limit, as a numerator and denominator.
+    pub(crate) fn gas_pass_fraction(&self) -> (u64, u64) {
+        self.inner.gas_pass_fraction
+    }
+
     /// Returns the value of a metadata item.
+    ///
+    /// During a call made using [`call_with_meta`], the metadata given to the
+    /// call takes precedence over the session's.
+    ///
+    /// [`call_with_meta`]: Session::call_with_meta
     pub fn meta(&self, name: &str) -> Option<Vec<u8>> {
-        self.inner.data.get(name)
+        match self.inner.call_data.get(name) {
+            Some(data) => Some(data.clone()),
+            None => self.inner.data.get(name),
+        }
     }
 
-    pub fn serialize_data<V>(value: &V) -> Result<Vec<u8>, Error>
+    /// Sets the value of a global metadata item, replacing any previous value
+    /// under the same `name`.
+    ///
+    /// Global metadata is readable by every contract executing in the session
+    /// using `global_meta_data`, and is kept in a namespace separate from the
+    /// session metadata passed on session creation, which contracts read
+    /// using `meta_data`. Unlike session metadata, global metadata may be
+    /// modified between calls.
+    pub fn set_global_meta<S, V>(
+        &mut self,
+        name: S,
+        value: V,
+    ) -> Result<(), Error>
     where
+        S: Into<Cow<'static, str>>,
         V: for<'a> Serialize<StandardBufSerializer<'a>>,
     {
-        let mut buf = [0u8; MAX_META_SIZE];
-        let mut sbuf = [0u8; SCRATCH_BUF_BYTES];
+        let data =
+            Self::serialize_data_with_scratch(&value, self.inner.scratch_size)?;
+        self.inner.global_data.insert(name.into(), data);
+        Ok(())
+    }
 
-        let ser = BufferSerializer::new(&mut buf[..]);
-        let scratch = BufferScratch::new(&mut sbuf);
+    /// Returns the value of a global metadata item.
+    pub fn global_meta(&self, name: &str) -> Option<Vec<u8>> {
+        self.inner.global_data.get(name).cloned()
+    }
 
-        let mut serializer =
-            StandardBufSerializer::new(ser, scratch, Infallible);
-        serializer.serialize_value(value)?;
+    /// Returns the names of all metadata items set on the session, in
+    /// ascending order.
+    pub fn meta_keys(&self) -> Vec<String> {
+        self.inner.data.keys()
+    }
 
-        let pos = serializer.pos();
+    /// Returns all metadata items set on the session together with their
+    /// names, in ascending order of name.
+    pub fn meta_entries(&self) -> Vec<(String, Vec<u8>)> {
+        self.inner.data.entries()
+    }
 
-        Ok(buf[..pos].to_vec())
+    pub fn serialize_data<V>(value: &V) -> Result<Vec<u8>, Error>
+    where
+        V: for<'a> Serialize<StandardBufSerializer<'a>>,
+    {
+        Self::serialize_data_with_scratch(value, SCRATCH_BUF_BYTES)
+    }
+
+    fn serialize_data_with_scratch<V>(
+        value: &V,
+        scratch_size: usize,
+    ) -> Result<Vec<u8>, Error>
+    where
+        V: for<'a> Serialize<StandardBufSerializer<'a>>,
+    {
+        // The buffer starts at the size of the argument buffer, and is grown
+        // until the value fits.
+        let mut buf = vec![0u8; MAX_META_SIZE];
+        let mut sbuf = vec![0u8; scratch_size];
+
+        loop {
+            let ser = BufferSerializer::new(&mut buf[..]);
+            let scratch = BufferScratch::new(&mut sbuf[..]);
+
+            let mut serializer =
+                StandardBufSerializer::new(ser, scratch, Infallible);
+
+            match serializer.serialize_value(value) {
+                Ok(_) => {
+                    let pos = serializer.pos();
+                    buf.truncate(pos);
+                    return Ok(buf);
+                }
+                Err(CompositeSerializerError::SerializerError(
+                    BufferSerializerError::Overflow { .. },
+                )) => {
+                    let len = 2 * buf.len();
+                    buf = vec![0u8; len];
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
     }
 
     fn call_inner(
@@ -748,7 +1776,39 @@ impl Session {
         fname: &str,
         fdata: Vec<u8>,
         limit: u64,
+        persist: bool,
     ) -> Result<(Vec<u8>, u64, CallTree), Error> {
+        // Calls whose changes are discarded are not metered against the block
+        // limit.
+        let metered = persist;
+        if metered {
+            if let Some(block_limit) = self.inner.block_limit {
+                let spent = self.inner.block_spent;
+                if spent.saturating_add(limit) > block_limit {
+                    return Err(Error::BlockLimitExceeded {
+                        block_limit,
+                        spent,
+                        limit,
+                    });
+                }
+            }
+        }
+
+        if fdata.len() > self.inner.max_arg_len {
+            return Err(Error::ArgumentBufferOverflow {
+                len: fdata.len(),
+                max_len: self.inner.max_arg_len,
+            });
+        }
+
+        // Read-only sessions never persist the changes made by a call.
+        let persist = persist && !self.inner.read_only;
+
+        self.inner.touched.clear();
+        self.inner.destructed.clear();
+        self.inner.host_queries_made = 0;
+        self.inner.refund = 0;
+
         let stack_element = self.push_callstack(contract, limit)?;
         let instance = self
             .instance(&stack_element.contract_id)
@@ -765,6 +1825,12 @@ impl Session {
         let ret_len = instance
             .call(fname, arg_len, limit)
             .map_err(|err| {
+                if metered {
+                    let spent = limit - instance.get_remaining_gas();
+                    self.inner.block_spent += spent;
+                }
+                // A failed call is not refunded.
+                self.inner.refund = 0;
                 if let Err(io_err) = self.revert_callstack() {
                     return Error::MemorySnapshotFailure {
                         reason: Some(Arc::new(err)),
@@ -778,21 +1844,51 @@ impl Session {
             .map_err(Error::normalize)?;
         let ret = instance.read_bytes_from_arg_buffer(ret_len as u32);
 
+        // The refund requested by contracts during the call is capped at a
+        // fraction of the gas spent, so a call never spends less than zero.
         let spent = limit - instance.get_remaining_gas();
+        let max_refund = (spent as u128 * MAX_REFUND_PCT as u128 / 100) as u64;
+        let refund = self.inner.refund.min(max_refund);
+        self.inner.refund = refund;
 
-        for elem in self.inner.call_tree.iter() {
-            let instance = self
-                .instance(&elem.contract_id)
-                .expect("instance should exist");
-            instance
-                .apply()
-                .map_err(|err| Error::MemorySnapshotFailure {
+        let spent = spent - refund;
+        if metered {
+            self.inner.block_spent += spent;
+        }
+
+        // If the changes are not to be persisted, the memories of all the
+        // contracts in the call tree are reverted as if the call had failed.
+        if persist {
+            for elem in self.inner.call_tree.iter() {
+                let instance = self
+                    .instance(&elem.contract_id)
+                    .expect("instance should exist");
+                instance.apply().map_err(|err| {
+                    Error::MemorySnapshotFailure {
+                        reason: None,
+                        io: Arc::new(err),
+                    }
+                })?;
+            }
+        } else {
+            self.revert_callstack().map_err(|err| {
+                Error::MemorySnapshotFailure {
                     reason: None,
                     io: Arc::new(err),
-                })?;
+                }
+            })?;
         }
         self.clear_stack_and_instances();
 
+        // Contracts that destructed themselves are only removed once the call
+        // succeeds, and only if its changes are persisted.
+        let destructed = mem::take(&mut self.inner.destructed);
+        if persist {
+            for contract in destructed {
+                self.inner.contract_session.destruct_contract(contract);
+            }
+        }
+
         let mut call_tree = CallTree::new();
         mem::swap(&mut self.inner.call_tree, &mut call_tree);
         call_tree.update_spent(spent);
@@ -806,6 +1902,118 @@ impl Session {
     ) -> Option<&ContractMetadata> {
         self.inner.contract_session.contract_metadata(contract_id)
     }
+
+    /// Returns the ABI descriptor embedded in the bytecode of the given
+    /// `contract`, or `None` if it doesn't embed one.
+    ///
+    /// The descriptor is the raw contents of the [`ABI_SECTION`] custom
+    /// section of the contract's bytecode.
+    ///
+    /// # Errors
+    /// If the contract doesn't exist, or its bytecode can't be parsed.
+    pub fn contract_abi(
+        &mut self,
+        contract: ContractId,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let contract_data = self
+            .inner
+            .contract_session
+            .contract(contract)
+            .map_err(|err| PersistenceError(Arc::new(err)))?
+            .ok_or(Error::ContractDoesNotExist(contract))?;
+
+        custom_section(contract_data.bytecode.as_ref(), ABI_SECTION)
+    }
+
+    /// Returns the names of the host functions imported by the given
+    /// `contract`, such as `hq` for performing host queries.
+    ///
+    /// This allows for checking whether a contract uses a given host function
+    /// before calling it. Note that the names of the host queries a contract
+    /// performs are passed at runtime, and can therefore not be known
+    /// upfront.
+    ///
+    /// # Errors
+    /// If the contract doesn't exist, or its bytecode can't be parsed.
+    pub fn host_imports(
+        &mut self,
+        contract: ContractId,
+    ) -> Result<Vec<String>, Error> {
+        let contract_data = self
+            .inner
+            .contract_session
+            .contract(contract)
+            .map_err(|err| PersistenceError(Arc::new(err)))?
+            .ok_or(Error::ContractDoesNotExist(contract))?;
+
+        imported_functions(contract_data.bytecode.as_ref())
+    }
+}
+
+/// The record of a call made in a session, as returned by
+/// [`Session::call_record`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallRecord {
+    /// The gas limit the call was made with.
+    pub gas_limit: u64,
+    /// The amount of gas spent by the call.
+    pub gas_spent: u64,
+    /// Whether the call succeeded.
+    pub success: bool,
+}
+
+/// A piece of debug output sent by a contract, as passed to
+/// [`Session::with_debug`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugRecord {
+    /// The contract that sent the output.
+    pub contract: ContractId,
+    /// The severity of the output.
+    pub level: DebugLevel,
+    /// The message sent.
+    pub message: String,
+}
+
+/// The data fed by a contract during a call made using
+/// [`Session::feed_call`].
+///
+/// Iterating yields each piece of data in the order it was fed, deserialized
+/// as a `T`.
+#[derive(Debug)]
+pub struct FeedStream<T> {
+    fed: mpsc::IntoIter<Vec<u8>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Iterator for FeedStream<T>
+where
+    T: Archive,
+    T::Archived:
+        Deserialize<T, Infallible> + for<'b> CheckBytes<DefaultValidator<'b>>,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.fed.next()?;
+
+        let deserialize = || -> Result<T, Error> {
+            let ta = check_archived_root::<T>(&data[..])?;
+            Ok(ta.deserialize(&mut Infallible)?)
+        };
+
+        Some(deserialize())
+    }
+}
+
+/// The identifier of a snapshot taken using [`Session::snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SnapshotId(usize);
+
+#[derive(Debug)]
+struct Snapshot {
+    contract_session: ContractSession,
+    call_records: usize,
+    block_spent: u64,
 }
 
 /// The receipt given for a call execution using one of either [`call`] or
@@ -851,7 +2059,37 @@ impl CallReceipt<Vec<u8>> {
     }
 }
 
-#[derive(Debug, Default)]
+impl<T> CallReceipt<T> {
+    /// Returns the hash of the events emitted during the execution of the
+    /// call, computed using [`hash_events`].
+    ///
+    /// [`hash_events`]: crate::hash_events
+    pub fn events_hash(&self) -> [u8; 32] {
+        hash_events(&self.events)
+    }
+
+    /// Returns the events emitted by the given `contract` during the
+    /// execution of the call, in the order they were emitted.
+    pub fn events_for(&self, contract: ContractId) -> Vec<&Event> {
+        self.events
+            .iter()
+            .filter(|event| event.source == contract)
+            .collect()
+    }
+
+    /// Removes and returns the events emitted by the given `contract` during
+    /// the execution of the call, leaving the events emitted by other
+    /// contracts in the receipt.
+    pub fn take_events_for(&mut self, contract: ContractId) -> Vec<Event> {
+        let (taken, kept) = mem::take(&mut self.events)
+            .into_iter()
+            .partition(|event| event.source == contract);
+        self.events = kept;
+        taken
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct SessionData {
     data: BTreeMap<Cow<'static, str>, Vec<u8>>,
     pub base: Option<[u8; 32]>,
@@ -868,6 +2106,17 @@ impl SessionData {
     fn get(&self, name: &str) -> Option<Vec<u8>> {
         self.data.get(name).cloned()
     }
+
+    fn keys(&self) -> Vec<String> {
+        self.data.keys().map(|name| name.to_string()).collect()
+    }
+
+    fn entries(&self) -> Vec<(String, Vec<u8>)> {
+        self.data
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
 }
 
 impl From<SessionDataBuilder> for SessionData {
@@ -892,6 +2141,20 @@ impl SessionDataBuilder {
         Ok(self)
     }
 
+    /// Inserts an already serialized metadata item, replacing any previous
+    /// value under the same `name`.
+    ///
+    /// The bytes are passed to contracts as is, and should be serialized in
+    /// the form they expect to deserialize.
+    pub fn insert_raw<S, V>(mut self, name: S, value: V) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+        V: Into<Vec<u8>>,
+    {
+        self.data.insert(name.into(), value.into());
+        self
+    }
+
     pub fn base(mut self, base: [u8; 32]) -> Self {
         self.base = Some(base);
         self
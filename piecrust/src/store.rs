@@ -6,6 +6,7 @@
 
 //! A library for dealing with memories in trees.
 
+mod archive;
 mod bytecode;
 mod memory;
 mod metadata;
@@ -13,31 +14,88 @@ mod module;
 mod session;
 mod tree;
 
+use std::any::Any;
 use std::collections::btree_map::Entry::*;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Debug, Formatter};
+use std::io::{Read, Write};
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, SystemTime};
 use std::{fs, io, thread};
 
 use dusk_wasmtime::Engine;
 use piecrust_uplink::ContractId;
 use session::ContractDataEntry;
+use tempfile::TempDir;
 use tree::{ContractIndex, Hash};
+use wasmparser::{Parser, Payload};
 
 pub use bytecode::Bytecode;
-pub use memory::{Memory, PAGE_SIZE};
+pub use memory::{Memory, MemoryReport, MemoryTracker, PAGE_SIZE};
 pub use metadata::Metadata;
-pub use module::Module;
+pub use module::{Module, ModuleCache, ModuleCacheReport};
 pub use session::ContractSession;
+pub use tree::Hasher;
 pub use tree::PageOpening;
 
+/// The version of the on-disk format of a store.
+///
+/// It is written to the root directory of a store on creation, and checked
+/// every time the store is opened.
+pub const STORE_FORMAT_VERSION: u32 = 1;
+
+const VERSION_FILE: &str = "version";
 const BYTECODE_DIR: &str = "bytecode";
 const MEMORY_DIR: &str = "memory";
 const INDEX_FILE: &str = "index";
+const TIMESTAMP_FILE: &str = "timestamp";
+const PARENT_FILE: &str = "parent";
+const LENGTHS_FILE: &str = "lengths";
+/// Files written alongside the index of a commit, describing it rather than
+/// holding its state.
+const SIDECAR_FILES: [&str; 3] = [TIMESTAMP_FILE, PARENT_FILE, LENGTHS_FILE];
+const IMPORT_DIR_PREFIX: &str = ".import-";
+const TMP_DIR_PREFIX: &str = ".tmp-";
 const OBJECTCODE_EXTENSION: &str = "a";
 const METADATA_EXTENSION: &str = "m";
 
+/// The layout of the commit directories in a store.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CommitLayout {
+    /// Each commit is stored in a directory named by the hex of its root,
+    /// directly under the store's directory.
+    #[default]
+    Flat,
+    /// Each commit is stored in a directory named by the hex of its root minus
+    /// its first two characters, under a directory named by those two
+    /// characters - e.g. `ab/cdef...`.
+    ///
+    /// This avoids having a large number of entries in a single directory,
+    /// which slows down lookups on some filesystems.
+    Fanout,
+}
+
+impl CommitLayout {
+    /// Returns the directory of the commit with the given `root`.
+    pub(crate) fn commit_dir<P: AsRef<Path>>(
+        &self,
+        root_dir: P,
+        root: Hash,
+    ) -> PathBuf {
+        let root_hex = hex::encode(root);
+        match self {
+            Self::Flat => root_dir.as_ref().join(root_hex),
+            Self::Fanout => {
+                let (fanout, rest) = root_hex.split_at(2);
+                root_dir.as_ref().join(fanout).join(rest)
+            }
+        }
+    }
+}
+
 /// A store for all contract commits.
 pub struct ContractStore {
     sync_loop: thread::JoinHandle<()>,
@@ -45,6 +103,14 @@ pub struct ContractStore {
 
     call: mpsc::Sender<Call>,
     root_dir: PathBuf,
+    layout: CommitLayout,
+    memory_tracker: Arc<MemoryTracker>,
+    module_cache: Arc<ModuleCache>,
+    zero_memories: bool,
+    max_sessions_per_commit: Option<usize>,
+    // Shared with every session spawned from the store, so a temporary
+    // directory is only removed once both the store and its sessions drop.
+    tmp_dir: Option<Arc<TempDir>>,
 }
 
 impl Debug for ContractStore {
@@ -53,12 +119,19 @@ impl Debug for ContractStore {
             .field("sync_loop", &self.sync_loop)
             .field("call", &self.call)
             .field("root_dir", &self.root_dir)
+            .field("layout", &self.layout)
+            .field("memory_tracker", &self.memory_tracker)
+            .field("module_cache", &self.module_cache)
+            .field("zero_memories", &self.zero_memories)
+            .field("max_sessions_per_commit", &self.max_sessions_per_commit)
             .finish()
     }
 }
 
 impl ContractStore {
-    /// Loads a new contract store from the given `dir`ectory.
+    /// Loads a new contract store from the given `dir`ectory, whose commit
+    /// directories follow the given `layout`. The existing commits are read
+    /// using up to `parallelism` threads.
     ///
     /// This also starts the synchronization loop, which is used to align
     /// [`commit`]s, [`delete`]s, and [`session spawning`] to avoid deleting
@@ -67,13 +140,19 @@ impl ContractStore {
     /// [`commit`]: ContractSession::commit
     /// [`delete`]: ContractStore::delete_commit
     /// [`session spawning`]: ContractStore::session
-    pub fn new<P: AsRef<Path>>(engine: Engine, dir: P) -> io::Result<Self> {
+    pub fn new<P: AsRef<Path>>(
+        engine: Engine,
+        dir: P,
+        layout: CommitLayout,
+        parallelism: NonZeroUsize,
+    ) -> io::Result<Self> {
         let root_dir = dir.as_ref();
 
-        fs::create_dir_all(root_dir)?;
+        fs::create_dir_all(root_dir).map_err(classify_io_error)?;
+        check_format_version(root_dir)?;
 
         let (call, calls) = mpsc::channel();
-        let commits = read_all_commits(&engine, root_dir)?;
+        let commits = read_all_commits(&engine, root_dir, layout, parallelism)?;
 
         let loop_root_dir = root_dir.to_path_buf();
 
@@ -81,28 +160,52 @@ impl ContractStore {
         // debugging.
         let sync_loop = thread::Builder::new()
             .name(String::from("PiecrustSync"))
-            .spawn(|| sync_loop(loop_root_dir, commits, calls))?;
+            .spawn(move || sync_loop(loop_root_dir, layout, commits, calls))?;
 
         Ok(Self {
             sync_loop,
             engine,
             call,
             root_dir: root_dir.into(),
+            layout,
+            memory_tracker: Arc::new(MemoryTracker::default()),
+            module_cache: Arc::new(ModuleCache::default()),
+            zero_memories: false,
+            max_sessions_per_commit: None,
+            tmp_dir: None,
         })
     }
 
+    /// Returns the format version of the store.
+    pub fn format_version(&self) -> u32 {
+        STORE_FORMAT_VERSION
+    }
+
     /// Create a new [`ContractSession`] with the given `base` commit.
     ///
-    /// Errors if the given base commit does not exist in the store.
+    /// Errors if the given base commit does not exist in the store, or if it
+    /// is already held by the maximum number of sessions set using
+    /// [`set_max_sessions_per_commit`].
+    ///
+    /// [`set_max_sessions_per_commit`]: ContractStore::set_max_sessions_per_commit
     pub fn session(&self, base: Hash) -> io::Result<ContractSession> {
-        let base_commit = self
-            .call_with_replier(|replier| Call::CommitHold { base, replier })
-            .ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("No such base commit: {}", hex::encode(base)),
-                )
-            })?;
+        let base_commit = match self.max_sessions_per_commit {
+            Some(max_sessions) => {
+                self.call_with_replier(|replier| Call::SessionHold {
+                    base,
+                    max_sessions,
+                    replier,
+                })?
+            }
+            None => self
+                .call_with_replier(|replier| Call::CommitHold { base, replier })
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("No such base commit: {}", hex::encode(base)),
+                    )
+                })?,
+        };
 
         Ok(self.session_with_base(Some(base_commit)))
     }
@@ -121,6 +224,20 @@ impl ContractStore {
         self.call_with_replier(|replier| Call::GetCommits { replier })
     }
 
+    /// Returns the roots of the commits that are currently in the store,
+    /// oldest first.
+    ///
+    /// Commits loaded from disk are ordered by the creation time recorded in
+    /// their timestamp file, falling back to the modification time of their
+    /// index file for commits written without one. Commits created afterwards
+    /// follow, in the order they were written or imported. Ties are broken by
+    /// root.
+    pub fn commits_chronological(&self) -> Vec<Hash> {
+        self.call_with_replier(|replier| Call::GetCommitsChronological {
+            replier,
+        })
+    }
+
     /// Deletes a given `commit` from the store.
     ///
     /// If a `ContractSession` is currently using the given commit as a base,
@@ -132,6 +249,418 @@ impl ContractStore {
         self.call_with_replier(|replier| Call::CommitDelete { commit, replier })
     }
 
+    /// Deletes all commits from the store, except for the ones to `keep`,
+    /// returning the roots of the deleted commits.
+    ///
+    /// Commits currently used by a `ContractSession` as a base are queued for
+    /// deletion once the last session using them has dropped, as with
+    /// [`delete_commit`], and are included in the returned roots.
+    ///
+    /// [`delete_commit`]: ContractStore::delete_commit
+    pub fn gc(&self, keep: &[Hash]) -> io::Result<Vec<Hash>> {
+        let keep = keep.iter().copied().collect();
+        self.call_with_replier(|replier| Call::CommitGc { keep, replier })
+    }
+
+    /// Returns information about the given `commit`, or `None` if it doesn't
+    /// exist in the store.
+    ///
+    /// The commit is held while its directory is walked, so any deletion of it
+    /// is queued until the information is gathered.
+    pub fn commit_info(&self, commit: Hash) -> io::Result<Option<CommitInfo>> {
+        let held = self.call_with_replier(|replier| Call::CommitHold {
+            base: commit,
+            replier,
+        });
+
+        let held = match held {
+            Some(held) => held,
+            None => return Ok(None),
+        };
+
+        let io_result = commit_info(&self.root_dir, self.layout, commit, &held);
+
+        let _ = self.call.send(Call::SessionDrop(commit));
+
+        io_result.map(Some)
+    }
+
+    /// Returns the time at which the given `commit` was written, or `None` if
+    /// it doesn't exist in the store or was written without a timestamp.
+    ///
+    /// Commits written by versions of the store predating timestamps don't
+    /// have one.
+    pub fn commit_timestamp(
+        &self,
+        commit: Hash,
+    ) -> io::Result<Option<SystemTime>> {
+        let commit_dir = self.layout.commit_dir(&self.root_dir, commit);
+        read_timestamp(commit_dir)
+    }
+
+    /// Returns the base commit the given `commit` was written on top of, or
+    /// `None` if it was written without a base, or doesn't exist in the store.
+    ///
+    /// Commits written by versions of the store predating recorded parents
+    /// don't have one.
+    pub fn commit_parents(&self, commit: Hash) -> io::Result<Option<Hash>> {
+        let commit_dir = self.layout.commit_dir(&self.root_dir, commit);
+        read_parent(commit_dir)
+    }
+
+    /// Returns the chain of commits leading to the given `commit`, oldest
+    /// first and ending with the `commit` itself.
+    ///
+    /// The chain starts at the first commit without a recorded parent, or
+    /// whose parent no longer exists in the store. It is empty if the
+    /// `commit` doesn't exist in the store.
+    ///
+    /// Parents may form a cycle - for instance when a deleted commit is
+    /// written again from a session based on one of its descendants - in
+    /// which case the chain starts at the commit whose parent is already in it.
+    pub fn commit_chain(&self, commit: Hash) -> io::Result<Vec<Hash>> {
+        let mut chain = Vec::new();
+        let mut visited = BTreeSet::new();
+        let mut next = Some(commit);
+
+        while let Some(commit) = next {
+            let commit_dir = self.layout.commit_dir(&self.root_dir, commit);
+            if !commit_dir.is_dir() || !visited.insert(commit) {
+                break;
+            }
+            chain.push(commit);
+            next = read_parent(commit_dir)?;
+        }
+
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// Computes the contract-level difference between the `from` and `to`
+    /// commits.
+    ///
+    /// Errors if either commit does not exist in the store.
+    pub fn diff_commits(
+        &self,
+        from: Hash,
+        to: Hash,
+    ) -> io::Result<CommitDelta> {
+        self.call_with_replier(|replier| Call::CommitDiff { from, to, replier })
+    }
+
+    /// Reads the bytecode and compiled code of the given `contract` in the
+    /// given `commit`, without loading its memory.
+    ///
+    /// Returns `None` if the contract doesn't exist in the commit. The commit
+    /// is held while the files are read, so any deletion of it is queued until
+    /// it is done.
+    pub fn contract_code(
+        &self,
+        commit: Hash,
+        contract: ContractId,
+    ) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let held = self
+            .call_with_replier(|replier| Call::CommitHold {
+                base: commit,
+                replier,
+            })
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("No such commit: {}", hex::encode(commit)),
+                )
+            })?;
+
+        let io_result = if held.index.contains_key(&contract) {
+            let bytecode_path = self
+                .layout
+                .commit_dir(&self.root_dir, commit)
+                .join(BYTECODE_DIR)
+                .join(hex::encode(contract));
+            let objectcode_path =
+                bytecode_path.with_extension(OBJECTCODE_EXTENSION);
+
+            fs::read(bytecode_path).and_then(|bytecode| {
+                let objectcode = fs::read(objectcode_path)?;
+                Ok(Some((bytecode, objectcode)))
+            })
+        } else {
+            Ok(None)
+        };
+
+        let _ = self.call.send(Call::SessionDrop(commit));
+
+        io_result
+    }
+
+    /// Verifies the files of the given `commit` against its root, returning
+    /// `false` if they are corrupted.
+    ///
+    /// The root is recomputed from the memory pages of all contracts on disk.
+    /// The commit is held for the duration of the verification, so any
+    /// deletion of it is queued until it is done.
+    pub fn verify_commit(&self, commit: Hash) -> io::Result<bool> {
+        self.call_with_replier(|replier| Call::CommitHold {
+            base: commit,
+            replier,
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("No such commit: {}", hex::encode(commit)),
+            )
+        })?;
+
+        let commit_dir = self.layout.commit_dir(&self.root_dir, commit);
+        let io_result = match index_from_files(commit_dir) {
+            Ok(index) => Ok(*index.root() == commit),
+            // Missing or malformed files mean the commit is corrupted.
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::InvalidData
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        };
+
+        let _ = self.call.send(Call::SessionDrop(commit));
+
+        io_result
+    }
+
+    /// Writes the given `commit` to `out` as a single self-contained archive,
+    /// which can be imported into another store using [`import_commit`].
+    ///
+    /// The commit is held for the duration of the export, so any deletion of
+    /// it is queued until the export is done.
+    ///
+    /// [`import_commit`]: ContractStore::import_commit
+    pub fn export_commit<W: Write>(
+        &self,
+        commit: Hash,
+        out: W,
+    ) -> io::Result<()> {
+        self.call_with_replier(|replier| Call::CommitHold {
+            base: commit,
+            replier,
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("No such commit: {}", hex::encode(commit)),
+            )
+        })?;
+
+        let commit_dir = self.layout.commit_dir(&self.root_dir, commit);
+        let io_result = archive::write_archive(commit_dir, out);
+
+        let _ = self.call.send(Call::SessionDrop(commit));
+
+        io_result
+    }
+
+    /// Reads a commit archive produced by [`export_commit`] from `data`,
+    /// adding the commit to the store and returning its root.
+    ///
+    /// The root of the commit is recomputed from its memory pages, and the
+    /// import fails if it doesn't match the root in the archive's index. The
+    /// objectcode in the archive is discarded, and compiled anew from the
+    /// bytecode of each contract.
+    ///
+    /// [`export_commit`]: ContractStore::export_commit
+    pub fn import_commit<R: Read>(&self, data: R) -> io::Result<Hash> {
+        let import_dir = self.root_dir.join(format!(
+            "{IMPORT_DIR_PREFIX}{}",
+            hex::encode(rand::random::<[u8; 16]>())
+        ));
+        fs::create_dir_all(&import_dir)?;
+
+        let commit = match read_imported_commit(&self.engine, &import_dir, data)
+        {
+            Ok(commit) => commit,
+            Err(err) => {
+                let _ = fs::remove_dir_all(&import_dir);
+                return Err(err);
+            }
+        };
+
+        self.call_with_replier(|replier| Call::CommitImport {
+            dir: import_dir,
+            commit,
+            replier,
+        })
+    }
+
+    /// Writes all commits in the store to `out` as a single store archive,
+    /// which can be imported into another store using [`import_all`].
+    ///
+    /// Commits are written oldest first, and files with the same contents are
+    /// only written once, no matter how many commits they appear in. Each
+    /// commit is held while it is written, and commits deleted before their
+    /// turn comes are skipped.
+    ///
+    /// [`import_all`]: ContractStore::import_all
+    pub fn export_all<W: Write>(&self, mut out: W) -> io::Result<()> {
+        archive::write_store_header(&mut out)?;
+
+        let mut seen = BTreeSet::new();
+
+        for commit in self.commits_chronological() {
+            let held = self.call_with_replier(|replier| Call::CommitHold {
+                base: commit,
+                replier,
+            });
+            if held.is_none() {
+                continue;
+            }
+
+            let commit_dir = self.layout.commit_dir(&self.root_dir, commit);
+            let io_result = archive::write_store_commit(
+                commit.into(),
+                commit_dir,
+                &mut seen,
+                &mut out,
+            );
+
+            let _ = self.call.send(Call::SessionDrop(commit));
+
+            io_result?;
+        }
+
+        out.flush()
+    }
+
+    /// Reads a store archive produced by [`export_all`] from `data`, adding
+    /// all its commits to the store and returning their roots.
+    ///
+    /// Files with the same contents are hard linked to each other, restoring
+    /// the sharing between commits of the exported store. Each commit is
+    /// verified, and its objectcode compiled anew, like in [`import_commit`].
+    /// Commits read before an error remain in the store.
+    ///
+    /// [`export_all`]: ContractStore::export_all
+    /// [`import_commit`]: ContractStore::import_commit
+    pub fn import_all<R: Read>(&self, mut data: R) -> io::Result<Vec<Hash>> {
+        archive::read_store_header(&mut data)?;
+
+        let mut seen = BTreeMap::<[u8; 32], PathBuf>::new();
+        let mut roots = Vec::new();
+
+        loop {
+            let import_dir = self.root_dir.join(format!(
+                "{IMPORT_DIR_PREFIX}{}",
+                hex::encode(rand::random::<[u8; 16]>())
+            ));
+            fs::create_dir_all(&import_dir)?;
+
+            let frame =
+                archive::read_store_commit(&import_dir, &mut data, |hash| {
+                    seen.get(hash).cloned()
+                })
+                .and_then(|frame| match frame {
+                    Some((root, files)) => {
+                        let commit =
+                            verified_commit(&self.engine, &import_dir)?;
+                        let commit_root = *commit.index.root();
+                        if commit_root != Hash::from(root) {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "Imported commit {} is not the expected {}",
+                                    hex::encode(commit_root),
+                                    hex::encode(root)
+                                ),
+                            ));
+                        }
+                        Ok(Some((commit, files)))
+                    }
+                    None => Ok(None),
+                });
+
+            let (commit, files) = match frame {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    let _ = fs::remove_dir_all(&import_dir);
+                    break;
+                }
+                Err(err) => {
+                    let _ = fs::remove_dir_all(&import_dir);
+                    return Err(err);
+                }
+            };
+
+            let root =
+                self.call_with_replier(|replier| Call::CommitImport {
+                    dir: import_dir,
+                    commit,
+                    replier,
+                })?;
+
+            let commit_dir = self.layout.commit_dir(&self.root_dir, root);
+            for (path, hash) in files {
+                seen.entry(hash).or_insert_with(|| commit_dir.join(path));
+            }
+
+            roots.push(root);
+        }
+
+        Ok(roots)
+    }
+
+    /// Returns a report on the contract memories currently loaded by the
+    /// sessions spawned from this store.
+    pub fn memory_report(&self) -> MemoryReport {
+        self.memory_tracker.report()
+    }
+
+    /// Returns a report on the use of the cache of deserialized modules shared
+    /// by the sessions spawned from this store.
+    pub fn module_cache_report(&self) -> ModuleCacheReport {
+        self.module_cache.report()
+    }
+
+    /// Set the maximum number of deserialized modules kept in the cache shared
+    /// by the sessions spawned from this store.
+    pub fn set_module_cache_size(&mut self, size: usize) {
+        self.module_cache.set_size(size);
+    }
+
+    /// Set whether sessions created *after* this call zero the modified pages
+    /// of the memories they loaded once they're dropped.
+    pub fn set_zero_memories(&mut self, enabled: bool) {
+        self.zero_memories = enabled;
+    }
+
+    /// Set the maximum number of holds a commit may have for a session to be
+    /// spawned from it using [`session`], or for a session based on it to be
+    /// [`fork`]ed.
+    ///
+    /// Only sessions spawned *after* this call are subject to the limit.
+    ///
+    /// [`session`]: ContractStore::session
+    /// [`fork`]: ContractSession::fork
+    pub fn set_max_sessions_per_commit(&mut self, max_sessions: usize) {
+        self.max_sessions_per_commit = Some(max_sessions);
+    }
+
+    /// Hand the ownership of the temporary directory the store is in over to
+    /// the store, removing it once the store and all its sessions drop.
+    pub(crate) fn set_tmp_dir(&mut self, tmp_dir: TempDir) {
+        self.tmp_dir = Some(Arc::new(tmp_dir));
+    }
+
+    /// Returns whether the thread running the store's synchronization loop is
+    /// still alive.
+    ///
+    /// Panics while handling individual calls are contained by the loop, so
+    /// the store only stops being healthy if the loop itself dies.
+    pub fn is_healthy(&self) -> bool {
+        !self.sync_loop.is_finished()
+    }
+
     /// Return the handle to the thread running the store's synchronization
     /// loop.
     pub fn sync_loop(&self) -> &thread::Thread {
@@ -161,9 +690,15 @@ impl ContractStore {
     fn session_with_base(&self, base: Option<Commit>) -> ContractSession {
         ContractSession::new(
             &self.root_dir,
+            self.layout,
             self.engine.clone(),
             base,
             self.call.clone(),
+            self.memory_tracker.clone(),
+            self.module_cache.clone(),
+            self.zero_memories,
+            self.max_sessions_per_commit,
+            self.tmp_dir.clone(),
         )
     }
 }
@@ -171,14 +706,41 @@ impl ContractStore {
 fn read_all_commits<P: AsRef<Path>>(
     engine: &Engine,
     root_dir: P,
+    layout: CommitLayout,
+    parallelism: NonZeroUsize,
 ) -> io::Result<BTreeMap<Hash, Commit>> {
-    let root_dir = root_dir.as_ref();
+    let commit_dirs = commit_dirs(root_dir, layout)?;
+
+    // The directories are split into contiguous chunks, each read by its own
+    // thread. The results are then merged in directory order, so the error
+    // returned is the same as if the commits were read serially.
+    let chunk_size = commit_dirs.len().div_ceil(parallelism.get()).max(1);
+
+    let results: Vec<io::Result<Vec<Commit>>> = thread::scope(|scope| {
+        let handles: Vec<_> = commit_dirs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|commit_dir| read_commit(engine, commit_dir))
+                        .collect()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle.join().expect("Reading commits should not panic")
+            })
+            .collect()
+    });
+
     let mut commits = BTreeMap::new();
 
-    for entry in fs::read_dir(root_dir)? {
-        let entry = entry?;
-        if entry.path().is_dir() {
-            let commit = read_commit(engine, entry.path())?;
+    for result in results {
+        for commit in result? {
             let root = *commit.index.root();
             commits.insert(root, commit);
         }
@@ -187,6 +749,211 @@ fn read_all_commits<P: AsRef<Path>>(
     Ok(commits)
 }
 
+/// Checks that the store in the given `root_dir` has the current format
+/// version, marking it with the version if it has none.
+///
+/// Stores created before the version file was introduced are of the current
+/// version, and are therefore marked as such.
+fn check_format_version<P: AsRef<Path>>(root_dir: P) -> io::Result<()> {
+    let root_dir = root_dir.as_ref();
+
+    match read_format_version(root_dir)? {
+        None => write_format_version(root_dir),
+        Some(version) if version == STORE_FORMAT_VERSION => Ok(()),
+        Some(version) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Store {root_dir:?} has format version {version}, but version \
+                 {STORE_FORMAT_VERSION} is required. Migrate it using \
+                 `VM::migrate_store` if it is older, or by exporting its \
+                 commits using a release supporting version {version}, and \
+                 importing them into a new store"
+            ),
+        )),
+    }
+}
+
+/// Reads the format version of the store in the given `root_dir`, returning
+/// `None` if the store is not marked with one.
+fn read_format_version<P: AsRef<Path>>(root_dir: P) -> io::Result<Option<u32>> {
+    let version_path = root_dir.as_ref().join(VERSION_FILE);
+
+    let version = match fs::read_to_string(&version_path) {
+        Ok(version) => version,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    version.trim().parse().map(Some).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid store format version file: {version_path:?}"),
+        )
+    })
+}
+
+fn write_format_version<P: AsRef<Path>>(root_dir: P) -> io::Result<()> {
+    let version_path = root_dir.as_ref().join(VERSION_FILE);
+    fs::write(version_path, STORE_FORMAT_VERSION.to_string())
+        .map_err(classify_io_error)
+}
+
+/// Migrates the store in the given `root_dir`, laid out according to `layout`,
+/// to the current format version.
+///
+/// Any commit writes or imports left unfinished are removed, and the index
+/// files missing from commits are rebuilt from the commits' files, before the
+/// store is marked with the current version. Running the migration on a store
+/// that is already current is safe, and leaves it unchanged.
+///
+/// This should not be called on a directory in use by a store.
+pub fn migrate_store<P: AsRef<Path>>(
+    root_dir: P,
+    layout: CommitLayout,
+) -> io::Result<()> {
+    let root_dir = root_dir.as_ref();
+
+    if let Some(version) = read_format_version(root_dir)? {
+        if version > STORE_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Store {root_dir:?} has format version {version}, which \
+                     is newer than the supported version \
+                     {STORE_FORMAT_VERSION}"
+                ),
+            ));
+        }
+    }
+
+    for entry in fs::read_dir(root_dir)? {
+        let entry = entry?;
+
+        if is_unfinished_dir(&entry) {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+
+    for commit_dir in commit_dirs(root_dir, layout)? {
+        if !commit_dir.join(INDEX_FILE).exists() {
+            let root = commit_root(&commit_dir, layout)?;
+            rebuild_index(root_dir, layout, root)?;
+        }
+    }
+
+    write_format_version(root_dir)
+}
+
+/// Returns the root of the commit in the given `commit_dir`, as given by its
+/// path in a store laid out according to `layout`.
+fn commit_root<P: AsRef<Path>>(
+    commit_dir: P,
+    layout: CommitLayout,
+) -> io::Result<Hash> {
+    let commit_dir = commit_dir.as_ref();
+
+    let name = |path: Option<&Path>| {
+        path.and_then(Path::file_name)
+            .and_then(|name| name.to_str())
+            .map(String::from)
+    };
+
+    let root_hex = match layout {
+        CommitLayout::Flat => name(Some(commit_dir)),
+        CommitLayout::Fanout => name(commit_dir.parent())
+            .zip(name(Some(commit_dir)))
+            .map(|(fanout, rest)| fanout + &rest),
+    };
+
+    let mut root = [0u8; 32];
+    root_hex
+        .and_then(|root_hex| hex::decode_to_slice(root_hex, &mut root).ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid commit directory: {commit_dir:?}"),
+            )
+        })?;
+
+    Ok(root.into())
+}
+
+/// Returns the directories of all the commits in the `root_dir`.
+fn commit_dirs<P: AsRef<Path>>(
+    root_dir: P,
+    layout: CommitLayout,
+) -> io::Result<Vec<PathBuf>> {
+    let root_dir = root_dir.as_ref();
+    let mut commit_dirs = Vec::new();
+
+    for entry in fs::read_dir(root_dir)? {
+        let entry = entry?;
+
+        // Skip any commit writes or imports left unfinished.
+        if is_unfinished_dir(&entry) {
+            continue;
+        }
+
+        if entry.path().is_dir() {
+            match layout {
+                CommitLayout::Flat => commit_dirs.push(entry.path()),
+                CommitLayout::Fanout => {
+                    for entry in fs::read_dir(entry.path())? {
+                        let entry = entry?;
+                        if entry.path().is_dir() {
+                            commit_dirs.push(entry.path());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(commit_dirs)
+}
+
+/// Returns the position of each of the given `commits` in the order they were
+/// created, according to their timestamp file. For commits without one, this
+/// is approximated by the modification time of their index file, and commits
+/// whose index can't be inspected either are placed first.
+fn creation_order<P: AsRef<Path>>(
+    root_dir: P,
+    layout: CommitLayout,
+    commits: &BTreeMap<Hash, Commit>,
+) -> BTreeMap<Hash, u64> {
+    let root_dir = root_dir.as_ref();
+
+    let mut roots: Vec<_> = commits
+        .keys()
+        .map(|root| {
+            let commit_dir = layout.commit_dir(root_dir, *root);
+            let created = match read_timestamp(&commit_dir) {
+                Ok(Some(timestamp)) => Some(timestamp),
+                _ => fs::metadata(commit_dir.join(INDEX_FILE))
+                    .and_then(|metadata| metadata.modified())
+                    .ok(),
+            };
+            (created, *root)
+        })
+        .collect();
+    roots.sort();
+
+    roots
+        .into_iter()
+        .enumerate()
+        .map(|(position, (_, root))| (root, position as u64))
+        .collect()
+}
+
+/// Returns whether the given entry of a store's root directory is the
+/// directory of a commit write or import that was never finished.
+fn is_unfinished_dir(entry: &fs::DirEntry) -> bool {
+    let file_name = entry.file_name();
+    let file_name = file_name.to_string_lossy();
+    file_name.starts_with(IMPORT_DIR_PREFIX)
+        || file_name.starts_with(TMP_DIR_PREFIX)
+}
+
 fn read_commit<P: AsRef<Path>>(
     engine: &Engine,
     commit_dir: P,
@@ -273,28 +1040,107 @@ pub(crate) struct Commit {
     index: ContractIndex,
 }
 
+/// Information about a commit in the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitInfo {
+    /// The number of contracts in the commit.
+    pub contracts: usize,
+    /// The total size of the files in the commit directory, in bytes - not
+    /// counting the files holding the commit's timestamp, parent, and memory
+    /// lengths.
+    ///
+    /// Files are hard linked between commits whenever possible, so they may
+    /// be counted in the size of multiple commits while taking up space on
+    /// disk only once.
+    pub disk_size: u64,
+}
+
+/// The contract-level difference between two commits.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CommitDelta {
+    /// Contracts present in the later commit, but not in the earlier one.
+    pub added: BTreeSet<ContractId>,
+    /// Contracts present in the earlier commit, but not in the later one.
+    pub removed: BTreeSet<ContractId>,
+    /// Contracts present in both commits, whose state differs between them.
+    pub changed: BTreeSet<ContractId>,
+}
+
+impl CommitDelta {
+    fn new(from: &Commit, to: &Commit) -> Self {
+        let mut delta = Self::default();
+
+        for (contract, from_elem) in from.index.iter() {
+            match to.index.get(contract) {
+                None => {
+                    delta.removed.insert(*contract);
+                }
+                Some(to_elem) => {
+                    if *from_elem.tree.root() != *to_elem.tree.root() {
+                        delta.changed.insert(*contract);
+                    }
+                }
+            }
+        }
+
+        for (contract, _) in to.index.iter() {
+            if !from.index.contains_key(contract) {
+                delta.added.insert(*contract);
+            }
+        }
+
+        delta
+    }
+}
+
 pub(crate) enum Call {
     Commit {
         contracts: BTreeMap<ContractId, ContractDataEntry>,
+        destructed: BTreeSet<ContractId>,
         base: Option<Commit>,
+        base_root_dir: PathBuf,
+        base_layout: CommitLayout,
         replier: mpsc::SyncSender<io::Result<Commit>>,
     },
     GetCommits {
         replier: mpsc::SyncSender<Vec<Hash>>,
     },
+    GetCommitsChronological {
+        replier: mpsc::SyncSender<Vec<Hash>>,
+    },
     CommitDelete {
         commit: Hash,
         replier: mpsc::SyncSender<io::Result<()>>,
     },
+    CommitGc {
+        keep: BTreeSet<Hash>,
+        replier: mpsc::SyncSender<io::Result<Vec<Hash>>>,
+    },
     CommitHold {
         base: Hash,
         replier: mpsc::SyncSender<Option<Commit>>,
     },
+    SessionHold {
+        base: Hash,
+        max_sessions: usize,
+        replier: mpsc::SyncSender<io::Result<Commit>>,
+    },
+    CommitDiff {
+        from: Hash,
+        to: Hash,
+        replier: mpsc::SyncSender<io::Result<CommitDelta>>,
+    },
+    CommitImport {
+        dir: PathBuf,
+        commit: Commit,
+        replier: mpsc::SyncSender<io::Result<Hash>>,
+    },
     SessionDrop(Hash),
 }
 
 fn sync_loop<P: AsRef<Path>>(
     root_dir: P,
+    layout: CommitLayout,
     commits: BTreeMap<Hash, Commit>,
     calls: mpsc::Receiver<Call>,
 ) {
@@ -303,110 +1149,288 @@ fn sync_loop<P: AsRef<Path>>(
     let mut sessions = BTreeMap::new();
     let mut commits = commits;
 
+    // The position of each commit in the order they were created. Entries are
+    // never removed, so the length of the map is always the next position.
+    let mut created = creation_order(root_dir, layout, &commits);
+
     let mut delete_bag = BTreeMap::new();
 
     for call in calls {
-        match call {
-            // Writes a session to disk and adds it to the map of existing commits.
-            Call::Commit {
-                contracts,
-                base,
-                replier,
-            } => {
-                let io_result = write_commit(root_dir, &mut commits, base, contracts);
-                let _ = replier.send(io_result);
-            }
-            // Copy all commits and send them back to the caller.
-            Call::GetCommits {
-                replier
-            } => {
-                let _ = replier.send(commits.keys().copied().collect());
-            }
-            // Delete a commit from disk. If the commit is currently in use - as
-            // in it is held by at least one session using `Call::SessionHold` -
-            // queue it for deletion once no session is holding it.
-            Call::CommitDelete { commit: root, replier } => {
-                if sessions.contains_key(&root) {
-                    match delete_bag.entry(root) {
-                        Vacant(entry) => {
-                            entry.insert(vec![replier]);
-                        }
-                        Occupied(mut entry) => {
-                            entry.get_mut().push(replier);
+        // A panic while handling a call is contained to that call, so a
+        // single bad call can't bring down the whole store. Calls replying
+        // with a result reply with an error instead, so their caller isn't
+        // left without a reply.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            match call {
+                // Writes a session to disk and adds it to the map of existing commits.
+                Call::Commit {
+                    contracts,
+                    destructed,
+                    base,
+                    base_root_dir,
+                    base_layout,
+                    replier,
+                } => {
+                    let io_result = contain_panic("Writing commit", || {
+                        write_commit(
+                            root_dir,
+                            layout,
+                            &base_root_dir,
+                            base_layout,
+                            &mut commits,
+                            base,
+                            contracts,
+                            destructed,
+                        )
+                    });
+                    if let Ok(commit) = &io_result {
+                        let position = created.len() as u64;
+                        created.entry(*commit.index.root()).or_insert(position);
+                    }
+                    let _ = replier.send(io_result);
+                }
+                // Copy all commits and send them back to the caller.
+                Call::GetCommits {
+                    replier
+                } => {
+                    let _ = replier.send(commits.keys().copied().collect());
+                }
+                // Get all commits, sorted by the order in which they were created.
+                Call::GetCommitsChronological { replier } => {
+                    let mut roots: Vec<Hash> = commits.keys().copied().collect();
+                    roots.sort_by_key(|root| (created.get(root).copied(), *root));
+                    let _ = replier.send(roots);
+                }
+                // Delete a commit from disk. If the commit is currently in use - as
+                // in it is held by at least one session using `Call::SessionHold` -
+                // queue it for deletion once no session is holding it.
+                Call::CommitDelete { commit: root, replier } => {
+                    if sessions.contains_key(&root) {
+                        match delete_bag.entry(root) {
+                            Vacant(entry) => {
+                                entry.insert(vec![replier]);
+                            }
+                            Occupied(mut entry) => {
+                                entry.get_mut().push(replier);
+                            }
                         }
+
+                        return;
                     }
 
-                    continue;
+                    let io_result = contain_panic("Deleting commit", || {
+                        delete_commit_dir(root_dir, layout, root)
+                    });
+                    commits.remove(&root);
+                    let _ = replier.send(io_result);
                 }
+                // Delete all commits not in the set to keep. Commits in use are
+                // queued for deletion as with `Call::CommitDelete`, but without
+                // anyone waiting on the deletion.
+                Call::CommitGc { keep, replier } => {
+                    let roots: Vec<Hash> = commits
+                        .keys()
+                        .filter(|root| !keep.contains(root))
+                        .copied()
+                        .collect();
+
+                    let io_result = contain_panic("Collecting commits", || {
+                        for root in &roots {
+                            if sessions.contains_key(root) {
+                                let (replier, _) = mpsc::sync_channel(1);
+                                delete_bag
+                                    .entry(*root)
+                                    .or_insert_with(Vec::new)
+                                    .push(replier);
+                                continue;
+                            }
 
-                let io_result = delete_commit_dir(root_dir, root);
-                commits.remove(&root);
-                let _ = replier.send(io_result);
-            }
-            // Increment the hold count of a commit to prevent it from deletion
-            // on a `Call::CommitDelete`.
-            Call::CommitHold {
-                base,
-                replier,
-            } => {
-                let base_commit = commits.get(&base).cloned();
-
-                if base_commit.is_some() {
-                    match sessions.entry(base) {
-                        Vacant(entry) => {
-                            entry.insert(1);
+                            delete_commit_dir(root_dir, layout, *root)?;
+                            commits.remove(root);
                         }
-                        Occupied(mut entry) => {
-                            *entry.get_mut() += 1;
+                        Ok(())
+                    });
+
+                    let _ = replier.send(io_result.map(|_| roots));
+                }
+                // Increment the hold count of a commit to prevent it from deletion
+                // on a `Call::CommitDelete`.
+                Call::CommitHold {
+                    base,
+                    replier,
+                } => {
+                    let base_commit = commits.get(&base).cloned();
+
+                    if base_commit.is_some() {
+                        match sessions.entry(base) {
+                            Vacant(entry) => {
+                                entry.insert(1);
+                            }
+                            Occupied(mut entry) => {
+                                *entry.get_mut() += 1;
+                            }
                         }
                     }
+
+                    let _ = replier.send(base_commit);
                 }
+                // Increment the hold count of a commit as in `Call::CommitHold`,
+                // unless the commit is already held `max_sessions` times.
+                Call::SessionHold {
+                    base,
+                    max_sessions,
+                    replier,
+                } => {
+                    let held = sessions.get(&base).copied().unwrap_or(0);
+
+                    let io_result = match commits.get(&base) {
+                        None => Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("No such base commit: {}", hex::encode(base)),
+                        )),
+                        Some(_) if held >= max_sessions => Err(io::Error::new(
+                            io::ErrorKind::Other,
+                            format!(
+                                "Too many sessions on commit {}: the maximum is \
+                                 {max_sessions}",
+                                hex::encode(base)
+                            ),
+                        )),
+                        Some(commit) => {
+                            *sessions.entry(base).or_insert(0) += 1;
+                            Ok(commit.clone())
+                        }
+                    };
 
-                let _ = replier.send(base_commit);
-            }
-            // Signal that a session with a base commit has dropped and
-            // decrements the hold count, once incremented using
-            // `Call::SessionHold`. If this is the last session that held that
-            // commit, and there are queued deletions, execute them.
-            Call::SessionDrop(base) => match sessions.entry(base) {
-                Vacant(_) => unreachable!("If a session is dropped there must be a session hold entry"),
-                Occupied(mut entry) => {
-                    *entry.get_mut() -= 1;
-
-                    if *entry.get() == 0 {
-                        entry.remove();
-
-                        // Try all deletions first
-                        match delete_bag.entry(base) {
-                            Vacant(_) => {}
-                            Occupied(entry) => {
-                                for replier in entry.remove() {
-                                    let io_result =
-                                        delete_commit_dir(root_dir, base);
-                                    commits.remove(&base);
-                                    let _ = replier.send(io_result);
+                    let _ = replier.send(io_result);
+                }
+                // Compare the indices of two commits in memory.
+                Call::CommitDiff { from, to, replier } => {
+                    let no_such_commit = |root: Hash| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("No such commit: {}", hex::encode(root)),
+                        )
+                    };
+
+                    let io_result = match (commits.get(&from), commits.get(&to)) {
+                        (Some(from), Some(to)) => Ok(CommitDelta::new(from, to)),
+                        (None, _) => Err(no_such_commit(from)),
+                        (_, None) => Err(no_such_commit(to)),
+                    };
+                    let _ = replier.send(io_result);
+                }
+                // Moves an imported commit into place and adds it to the map of
+                // existing commits.
+                Call::CommitImport { dir, commit, replier } => {
+                    let io_result = contain_panic("Importing commit", || {
+                        move_imported_commit(
+                            root_dir,
+                            layout,
+                            &mut commits,
+                            dir,
+                            commit,
+                        )
+                    });
+                    if let Ok(root) = &io_result {
+                        let position = created.len() as u64;
+                        created.entry(*root).or_insert(position);
+                    }
+                    let _ = replier.send(io_result);
+                }
+                // Signal that a session with a base commit has dropped and
+                // decrements the hold count, once incremented using
+                // `Call::SessionHold`. If this is the last session that held that
+                // commit, and there are queued deletions, execute them.
+                Call::SessionDrop(base) => match sessions.entry(base) {
+                    Vacant(_) => unreachable!("If a session is dropped there must be a session hold entry"),
+                    Occupied(mut entry) => {
+                        *entry.get_mut() -= 1;
+
+                        if *entry.get() == 0 {
+                            entry.remove();
+
+                            // The commit is deleted once, no matter how many
+                            // deletions were queued, and every replier gets the
+                            // same result.
+                            if let Some(repliers) = delete_bag.remove(&base) {
+                                let io_result =
+                                    delete_commit_dir(root_dir, layout, base);
+                                commits.remove(&base);
+
+                                for replier in repliers {
+                                    let _ = replier.send(
+                                        io_result.as_ref().map(|_| ()).map_err(
+                                            |err| {
+                                                io::Error::new(
+                                                    err.kind(),
+                                                    err.to_string(),
+                                                )
+                                            },
+                                        ),
+                                    );
                                 }
                             }
                         }
                     }
-                }
-            },
-        }
+                },
+            }
+        }));
     }
 }
 
+/// Runs `f`, turning a panic into an error reporting that what it was `doing`
+/// panicked.
+fn contain_panic<T, F>(doing: &str, f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T>,
+{
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{doing} panicked: {}", panic_message(&*payload)),
+        ))
+    })
+}
+
+/// Returns the message of a panic with the given `payload`.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
 fn write_commit<P: AsRef<Path>>(
     root_dir: P,
+    layout: CommitLayout,
+    base_root_dir: P,
+    base_layout: CommitLayout,
     commits: &mut BTreeMap<Hash, Commit>,
     base: Option<Commit>,
     commit_contracts: BTreeMap<ContractId, ContractDataEntry>,
+    destructed: BTreeSet<ContractId>,
 ) -> io::Result<Commit> {
     let root_dir = root_dir.as_ref();
 
+    // The files of the base commit are taken from this store if it contains
+    // the commit, and otherwise from the store the session was spawned from.
+    let (base_root_dir, base_layout) = match &base {
+        Some(base) if commits.contains_key(&*base.index.root()) => {
+            (root_dir, layout)
+        }
+        _ => (base_root_dir.as_ref(), base_layout),
+    };
+
     let mut index = base
         .as_ref()
         .map_or(ContractIndex::default(), |base| base.index.clone());
 
+    for contract_id in &destructed {
+        index.remove(contract_id);
+    }
+
     for (contract_id, contract_data) in &commit_contracts {
         if contract_data.is_new {
             index.remove_and_insert(*contract_id, &contract_data.memory);
@@ -416,8 +1440,7 @@ fn write_commit<P: AsRef<Path>>(
     }
 
     let root = *index.root();
-    let root_hex = hex::encode(root);
-    let commit_dir = root_dir.join(root_hex);
+    let commit_dir = layout.commit_dir(root_dir, root);
 
     // Don't write the commit if it already exists on disk. This may happen if
     // the same transactions on the same base commit for example.
@@ -425,35 +1448,65 @@ fn write_commit<P: AsRef<Path>>(
         return Ok(commit.clone());
     }
 
-    match write_commit_inner(
-        root_dir,
-        &commit_dir,
+    // The commit is written to a temporary directory, and only moved to its
+    // final location once all its files are written and flushed to disk. This
+    // way a crash during the write never leaves a partial commit in the store.
+    let tmp_dir =
+        root_dir.join(format!("{TMP_DIR_PREFIX}{}", hex::encode(root)));
+    let _ = fs::remove_dir_all(&tmp_dir);
+
+    let io_result = write_commit_inner(
+        base_root_dir,
+        base_layout,
+        &tmp_dir,
         base,
         index,
         commit_contracts,
-    ) {
+    )
+    .and_then(|commit| {
+        sync_dir_all(&tmp_dir)?;
+
+        let parent_dir = commit_dir.parent().unwrap_or(root_dir);
+        fs::create_dir_all(parent_dir)?;
+        fs::rename(&tmp_dir, &commit_dir)?;
+
+        // The rename is only durable once the directory containing the commit
+        // is flushed, as is the creation of a fanout directory.
+        sync_dir(parent_dir)?;
+        if parent_dir != root_dir {
+            sync_dir(root_dir)?;
+        }
+
+        Ok(commit)
+    });
+
+    match io_result {
         Ok(commit) => {
             commits.insert(root, commit.clone());
             Ok(commit)
         }
         Err(err) => {
-            let _ = fs::remove_dir_all(commit_dir);
-            Err(err)
+            let _ = fs::remove_dir_all(tmp_dir);
+            Err(classify_io_error(err))
         }
     }
 }
 
-/// Writes a commit to disk.
+/// Writes a commit to disk, taking the files of the `base` commit from the
+/// given `base_root_dir`, laid out according to `base_layout`.
 fn write_commit_inner<P: AsRef<Path>>(
-    root_dir: P,
+    base_root_dir: P,
+    base_layout: CommitLayout,
     commit_dir: P,
     base: Option<Commit>,
     index: ContractIndex,
     commit_contracts: BTreeMap<ContractId, ContractDataEntry>,
 ) -> io::Result<Commit> {
-    let root_dir = root_dir.as_ref();
+    let base_root_dir = base_root_dir.as_ref();
     let commit_dir = commit_dir.as_ref();
 
+    let parent = base.as_ref().map(|base| *base.index.root());
+
     struct Base {
         bytecode_dir: PathBuf,
         memory_dir: PathBuf,
@@ -478,9 +1531,7 @@ fn write_commit_inner<P: AsRef<Path>>(
             memory_dir,
             base: base.map(|inner| {
                 let base_root = *inner.index.root();
-
-                let base_hex = hex::encode(base_root);
-                let base_dir = root_dir.join(base_hex);
+                let base_dir = base_layout.commit_dir(base_root_dir, base_root);
 
                 Base {
                     bytecode_dir: base_dir.join(BYTECODE_DIR),
@@ -532,9 +1583,9 @@ fn write_commit_inner<P: AsRef<Path>>(
 
                 let base_memory_dir = base.memory_dir.join(&contract_hex);
 
-                fs::hard_link(base_bytecode_path, bytecode_path)?;
-                fs::hard_link(base_module_path, module_path)?;
-                fs::hard_link(base_metadata_path, metadata_path)?;
+                link_or_copy(base_bytecode_path, bytecode_path)?;
+                link_or_copy(base_module_path, module_path)?;
+                link_or_copy(base_metadata_path, metadata_path)?;
 
                 for page_index in &elem.page_indices {
                     // Only write the clean pages, since the dirty ones have
@@ -544,16 +1595,20 @@ fn write_commit_inner<P: AsRef<Path>>(
                         let base_page_path =
                             page_path(&base_memory_dir, *page_index);
 
-                        fs::hard_link(base_page_path, new_page_path)?;
+                        link_or_copy(base_page_path, new_page_path)?;
                     }
                 }
             }
         }
     }
 
+    // Contracts untouched by the session are linked from the base commit,
+    // unless they were destructed and are therefore no longer in the index.
     if let Some(base) = &directories.base {
         for (contract, elem) in base.inner.index.iter() {
-            if !commit_contracts.contains_key(contract) {
+            if !commit_contracts.contains_key(contract)
+                && index.contains_key(contract)
+            {
                 let contract_hex = hex::encode(contract);
 
                 let bytecode_path =
@@ -574,23 +1629,278 @@ fn write_commit_inner<P: AsRef<Path>>(
 
                 fs::create_dir_all(&memory_dir)?;
 
-                fs::hard_link(base_bytecode_path, bytecode_path)?;
-                fs::hard_link(base_module_path, module_path)?;
-                fs::hard_link(base_metadata_path, metadata_path)?;
+                link_or_copy(base_bytecode_path, bytecode_path)?;
+                link_or_copy(base_module_path, module_path)?;
+                link_or_copy(base_metadata_path, metadata_path)?;
 
                 for page_index in &elem.page_indices {
                     let new_page_path = page_path(&memory_dir, *page_index);
                     let base_page_path =
                         page_path(&base_memory_dir, *page_index);
 
-                    fs::hard_link(base_page_path, new_page_path)?;
+                    link_or_copy(base_page_path, new_page_path)?;
                 }
             }
         }
     }
 
-    let index_path = commit_dir.join(INDEX_FILE);
-    let index_bytes = rkyv::to_bytes::<_, 128>(&index)
+    write_timestamp(commit_dir)?;
+    if let Some(parent) = parent {
+        write_parent(commit_dir, parent)?;
+    }
+    write_lengths(commit_dir, &index)?;
+    write_index(commit_dir, &index)?;
+
+    Ok(Commit { index })
+}
+
+/// Writes the current time to the timestamp file in the given `commit_dir`,
+/// as the number of nanoseconds since the Unix epoch.
+fn write_timestamp<P: AsRef<Path>>(commit_dir: P) -> io::Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    let timestamp_path = commit_dir.as_ref().join(TIMESTAMP_FILE);
+    fs::write(timestamp_path, timestamp.to_string())
+}
+
+/// Reads the timestamp file in the given `commit_dir`, returning `None` if
+/// there is none.
+fn read_timestamp<P: AsRef<Path>>(
+    commit_dir: P,
+) -> io::Result<Option<SystemTime>> {
+    let timestamp_path = commit_dir.as_ref().join(TIMESTAMP_FILE);
+
+    let timestamp = match fs::read_to_string(&timestamp_path) {
+        Ok(timestamp) => timestamp,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let nanos: u64 = timestamp.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid commit timestamp file: {timestamp_path:?}"),
+        )
+    })?;
+
+    Ok(Some(SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos)))
+}
+
+/// Writes the root of the `parent` commit to the parent file in the given
+/// `commit_dir`, hex encoded.
+fn write_parent<P: AsRef<Path>>(commit_dir: P, parent: Hash) -> io::Result<()> {
+    let parent_path = commit_dir.as_ref().join(PARENT_FILE);
+    fs::write(parent_path, hex::encode(parent))
+}
+
+/// Reads the parent file in the given `commit_dir`, returning `None` if there
+/// is none.
+fn read_parent<P: AsRef<Path>>(commit_dir: P) -> io::Result<Option<Hash>> {
+    let parent_path = commit_dir.as_ref().join(PARENT_FILE);
+
+    let parent = match fs::read_to_string(&parent_path) {
+        Ok(parent) => parent,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let mut root = [0u8; 32];
+    hex::decode_to_slice(parent.trim(), &mut root).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid commit parent file: {parent_path:?}"),
+        )
+    })?;
+
+    Ok(Some(root.into()))
+}
+
+/// Writes the memory length of each contract in the `index` to the lengths
+/// file in the given `commit_dir`, one contract per line.
+///
+/// The lengths are not covered by the root of the index, and are kept
+/// outside of it so the index can be rebuilt from the commit's files.
+fn write_lengths<P: AsRef<Path>>(
+    commit_dir: P,
+    index: &ContractIndex,
+) -> io::Result<()> {
+    let lengths_path = commit_dir.as_ref().join(LENGTHS_FILE);
+
+    let mut lengths = String::new();
+    for (contract, elem) in index.iter() {
+        lengths.push_str(&format!("{} {}\n", hex::encode(contract), elem.len));
+    }
+
+    fs::write(lengths_path, lengths)
+}
+
+/// Reads the lengths file in the given `commit_dir`, returning `None` if
+/// there is none.
+fn read_lengths<P: AsRef<Path>>(
+    commit_dir: P,
+) -> io::Result<Option<BTreeMap<ContractId, usize>>> {
+    let lengths_path = commit_dir.as_ref().join(LENGTHS_FILE);
+
+    let lengths = match fs::read_to_string(&lengths_path) {
+        Ok(lengths) => lengths,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid commit lengths file: {lengths_path:?}"),
+        )
+    };
+
+    let mut map = BTreeMap::new();
+    for line in lengths.lines() {
+        let (contract_hex, len) = line.split_once(' ').ok_or_else(invalid)?;
+
+        let mut contract = ContractId::uninitialized();
+        hex::decode_to_slice(contract_hex, contract.as_bytes_mut())
+            .map_err(|_| invalid())?;
+        let len = len.parse().map_err(|_| invalid())?;
+
+        map.insert(contract, len);
+    }
+
+    Ok(Some(map))
+}
+
+/// Extracts a commit archive into the given `import_dir`, verifying that the
+/// root of its index matches its files.
+fn read_imported_commit<P: AsRef<Path>, R: Read>(
+    engine: &Engine,
+    import_dir: P,
+    data: R,
+) -> io::Result<Commit> {
+    let import_dir = import_dir.as_ref();
+
+    archive::read_archive(import_dir, data)?;
+
+    verified_commit(engine, import_dir)
+}
+
+/// Reads the commit in the given `import_dir`, verifying that the root of its
+/// index matches its files.
+fn verified_commit<P: AsRef<Path>>(
+    engine: &Engine,
+    import_dir: P,
+) -> io::Result<Commit> {
+    let import_dir = import_dir.as_ref();
+
+    let index = index_from_path(import_dir.join(INDEX_FILE))?;
+    let files_index = index_from_files(import_dir)?;
+
+    let root = *index.root();
+    let files_root = *files_index.root();
+    if root != files_root {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Imported commit {} is corrupted: its files have root {}",
+                hex::encode(root),
+                hex::encode(files_root)
+            ),
+        ));
+    }
+
+    recompile_objectcode(engine, import_dir, &index)?;
+
+    commit_from_dir(engine, import_dir)
+}
+
+/// Compiles the objectcode of each contract in the `index` anew from its
+/// bytecode, replacing whatever is in the given `import_dir`, and checks that
+/// its metadata is valid.
+///
+/// Objectcode is native code that is not covered by the root of a commit, so
+/// it can't be trusted when it comes from elsewhere. Metadata can't be derived
+/// from the bytecode, and is instead checked to belong to its contract.
+fn recompile_objectcode<P: AsRef<Path>>(
+    engine: &Engine,
+    import_dir: P,
+    index: &ContractIndex,
+) -> io::Result<()> {
+    let bytecode_dir = import_dir.as_ref().join(BYTECODE_DIR);
+
+    for (contract, _) in index.iter() {
+        let contract_hex = hex::encode(contract);
+
+        let bytecode_path = bytecode_dir.join(&contract_hex);
+        let module_path = bytecode_path.with_extension(OBJECTCODE_EXTENSION);
+        let metadata_path = bytecode_path.with_extension(METADATA_EXTENSION);
+
+        // The file may be hard linked to a file of another commit, so it is
+        // removed rather than overwritten.
+        match fs::remove_file(&module_path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        let bytecode = Bytecode::from_file(&bytecode_path)?;
+        let module = Module::from_bytecode(engine, bytecode.as_ref())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(module_path, module.serialize())?;
+
+        let metadata = Metadata::from_file(metadata_path)?;
+        if metadata.data().contract_id != *contract {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Metadata of {contract_hex} is of another contract"),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the commit imported into `import_dir` to its directory in the store,
+/// unless the store already contains it.
+fn move_imported_commit<P: AsRef<Path>>(
+    root_dir: P,
+    layout: CommitLayout,
+    commits: &mut BTreeMap<Hash, Commit>,
+    import_dir: PathBuf,
+    commit: Commit,
+) -> io::Result<Hash> {
+    let root = *commit.index.root();
+
+    if commits.contains_key(&root) {
+        let _ = fs::remove_dir_all(import_dir);
+        return Ok(root);
+    }
+
+    let commit_dir = layout.commit_dir(root_dir, root);
+    let io_result = commit_dir
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::rename(&import_dir, &commit_dir));
+
+    match io_result {
+        Ok(()) => {
+            commits.insert(root, commit);
+            Ok(root)
+        }
+        Err(err) => {
+            let _ = fs::remove_dir_all(import_dir);
+            Err(classify_io_error(err))
+        }
+    }
+}
+
+fn write_index<P: AsRef<Path>>(
+    commit_dir: P,
+    index: &ContractIndex,
+) -> io::Result<()> {
+    let index_path = commit_dir.as_ref().join(INDEX_FILE);
+    let index_bytes = rkyv::to_bytes::<_, 128>(index)
         .map_err(|err| {
             io::Error::new(
                 io::ErrorKind::InvalidData,
@@ -598,17 +1908,321 @@ fn write_commit_inner<P: AsRef<Path>>(
             )
         })?
         .to_vec();
-    fs::write(index_path, index_bytes)?;
+    fs::write(index_path, index_bytes)
+}
 
-    Ok(Commit { index })
+/// Rebuilds the index file of the `commit` in the given `root_dir` from the
+/// bytecode and memory files of its contracts.
+///
+/// The rebuilt index is only written if its root matches the commit. The
+/// memory length of each contract is not covered by the root, and is taken
+/// from the lengths file written alongside the commit. Commits without one
+/// are refused, since their lengths can't be recovered from the pages alone.
+pub fn rebuild_index<P: AsRef<Path>>(
+    root_dir: P,
+    layout: CommitLayout,
+    commit: Hash,
+) -> io::Result<()> {
+    let root_hex = hex::encode(commit);
+    let commit_dir = layout.commit_dir(root_dir, commit);
+
+    if read_lengths(&commit_dir)?.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Commit {root_hex} has no lengths file: the lengths of its \
+                 memories are unknown"
+            ),
+        ));
+    }
+
+    let index = index_from_files(&commit_dir)?;
+
+    let root = *index.root();
+    if root != commit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Commit {root_hex} is corrupted: its files have root {}",
+                hex::encode(root)
+            ),
+        ));
+    }
+
+    write_index(commit_dir, &index)
+}
+
+/// Computes the index of the commit in the given `commit_dir` from the
+/// bytecode and memory files of its contracts.
+///
+/// The memory lengths are taken from the commit's lengths file. If there is
+/// none, each is recovered as the larger of the memory's initial length and
+/// the end of its last stored page, which may be shorter than the original
+/// length - this doesn't affect the root of the index.
+fn index_from_files<P: AsRef<Path>>(
+    commit_dir: P,
+) -> io::Result<ContractIndex> {
+    let commit_dir = commit_dir.as_ref();
+    let lengths = read_lengths(commit_dir)?;
+
+    let bytecode_dir = commit_dir.join(BYTECODE_DIR);
+    let memory_dir = commit_dir.join(MEMORY_DIR);
+
+    let mut index = ContractIndex::default();
+
+    for entry in fs::read_dir(memory_dir)? {
+        let entry = entry?;
+
+        let contract_hex = entry.file_name();
+        let contract_hex = contract_hex.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid contract directory: {:?}", entry.path()),
+            )
+        })?;
+
+        let mut contract = ContractId::uninitialized();
+        hex::decode_to_slice(contract_hex, contract.as_bytes_mut()).map_err(
+            |err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid contract directory {contract_hex}: {err}"),
+                )
+            },
+        )?;
+
+        let bytecode = fs::read(bytecode_dir.join(contract_hex))?;
+        let (is_64, initial_len) = memory_type(&bytecode)?;
+
+        let mut pages = BTreeMap::new();
+        for page_entry in fs::read_dir(entry.path())? {
+            let page_entry = page_entry?;
+
+            let page_index = page_entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<usize>().ok())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid page file: {:?}", page_entry.path()),
+                    )
+                })?;
+
+            pages.insert(page_index, fs::read(page_entry.path())?);
+        }
+
+        let pages_len = pages
+            .keys()
+            .next_back()
+            .map_or(0, |page_index| (page_index + 1) * PAGE_SIZE);
+        let len = match &lengths {
+            Some(lengths) => {
+                let len = *lengths.get(&contract).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Missing memory length for {contract_hex}"),
+                    )
+                })?;
+                if len < pages_len {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Memory length of {contract_hex} is shorter than \
+                             its pages"
+                        ),
+                    ));
+                }
+                len
+            }
+            None => initial_len.max(pages_len),
+        };
+
+        index.insert_pages(
+            contract,
+            is_64,
+            len,
+            pages
+                .iter()
+                .map(|(page_index, page)| (*page_index, page.as_slice())),
+        );
+    }
+
+    Ok(index)
+}
+
+/// Returns whether the memory declared by the given `bytecode` is 64-bit, and
+/// its initial length in bytes.
+fn memory_type(bytecode: &[u8]) -> io::Result<(bool, usize)> {
+    for payload in Parser::new(0).parse_all(bytecode) {
+        let payload = payload
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        if let Payload::MemorySection(reader) = payload {
+            if let Some(memory) = reader.into_iter().next() {
+                let memory = memory.map_err(|err| {
+                    io::Error::new(io::ErrorKind::InvalidData, err)
+                })?;
+                return Ok((
+                    memory.memory64,
+                    memory.initial as usize * PAGE_SIZE,
+                ));
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Bytecode doesn't declare a memory",
+    ))
+}
+
+/// Gives errors caused by the store's filesystem being full or read-only an
+/// actionable message, leaving all other errors untouched.
+fn classify_io_error(err: io::Error) -> io::Error {
+    let msg = match err.raw_os_error() {
+        Some(libc::ENOSPC) => "store filesystem is full",
+        Some(libc::EROFS) => "store directory is read-only",
+        _ => return err,
+    };
+
+    io::Error::new(err.kind(), format!("{msg}: {err}"))
+}
+
+/// Flushes all the files in the given `dir` to disk, recursively, together
+/// with the directories themselves.
+fn sync_dir_all<P: AsRef<Path>>(dir: P) -> io::Result<()> {
+    let dir = dir.as_ref();
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() {
+            sync_dir_all(entry.path())?;
+        } else {
+            fs::File::open(entry.path())?.sync_all()?;
+        }
+    }
+
+    sync_dir(dir)
+}
+
+/// Flushes the entries of the given `dir` to disk.
+///
+/// Directories can't be opened as files on Windows, where this does nothing.
+fn sync_dir<P: AsRef<Path>>(dir: P) -> io::Result<()> {
+    if cfg!(unix) {
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Hard links the file at `original` to `link`, falling back to copying it if
+/// linking is not possible - e.g. when crossing filesystems.
+fn link_or_copy<P: AsRef<Path>, Q: AsRef<Path>>(
+    original: P,
+    link: Q,
+) -> io::Result<()> {
+    let original = original.as_ref();
+    let link = link.as_ref();
+
+    if fs::hard_link(original, link).is_err() {
+        fs::copy(original, link)?;
+    }
+
+    Ok(())
+}
+
+/// Gathers information on the given commit, such as its size on disk.
+fn commit_info<P: AsRef<Path>>(
+    root_dir: P,
+    layout: CommitLayout,
+    root: Hash,
+    commit: &Commit,
+) -> io::Result<CommitInfo> {
+    let commit_dir = layout.commit_dir(root_dir, root);
+
+    Ok(CommitInfo {
+        contracts: commit.index.iter().count(),
+        disk_size: commit_size(commit_dir)?,
+    })
+}
+
+/// Sums the sizes of the files in the given `commit_dir`, leaving out its
+/// sidecar files.
+fn commit_size<P: AsRef<Path>>(commit_dir: P) -> io::Result<u64> {
+    let commit_dir = commit_dir.as_ref();
+
+    let mut size = dir_size(commit_dir)?;
+    for file in SIDECAR_FILES {
+        match fs::metadata(commit_dir.join(file)) {
+            Ok(metadata) => size -= metadata.len(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(size)
+}
+
+/// Sums the sizes of all the files in the given directory, recursively.
+fn dir_size<P: AsRef<Path>>(dir: P) -> io::Result<u64> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        if metadata.is_dir() {
+            size += dir_size(entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+
+    Ok(size)
 }
 
 /// Delete the given commit's directory.
 fn delete_commit_dir<P: AsRef<Path>>(
     root_dir: P,
+    layout: CommitLayout,
     root: Hash,
 ) -> io::Result<()> {
-    let root = hex::encode(root);
-    let commit_dir = root_dir.as_ref().join(root);
-    fs::remove_dir_all(commit_dir)
+    let commit_dir = layout.commit_dir(root_dir, root);
+    fs::remove_dir_all(&commit_dir)?;
+
+    // Remove the fanout directory if it is left empty, ignoring the error if
+    // it isn't.
+    if layout == CommitLayout::Fanout {
+        if let Some(fanout_dir) = commit_dir.parent() {
+            let _ = fs::remove_dir(fanout_dir);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_io_error_messages() {
+        for (code, prefix) in [
+            (libc::ENOSPC, "store filesystem is full: "),
+            (libc::EROFS, "store directory is read-only: "),
+        ] {
+            let err = io::Error::from_raw_os_error(code);
+            let kind = err.kind();
+
+            let classified = classify_io_error(err);
+            assert!(classified.to_string().starts_with(prefix));
+            assert_eq!(classified.kind(), kind);
+        }
+
+        let err = io::Error::from_raw_os_error(libc::ENOENT);
+        let classified = classify_io_error(err);
+        assert_eq!(classified.raw_os_error(), Some(libc::ENOENT));
+    }
 }
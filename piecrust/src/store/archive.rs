@@ -0,0 +1,376 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! A portable single-stream archive of the files in a commit directory.
+//!
+//! The archive starts with an 8 byte magic and a little-endian `u32` version,
+//! followed by one entry per file. Each entry is the length of the file's path
+//! relative to the commit directory as a little-endian `u32`, followed by the
+//! path itself using `/` as a separator, followed by the length of the file as
+//! a little-endian `u64`, followed by the file's contents.
+//!
+//! A store archive holds several commits in one stream. It starts with its
+//! own 8 byte magic and a little-endian `u32` version, followed by one frame
+//! per commit. Each frame is the commit's 32 byte root, followed by the number
+//! of files in the commit as a little-endian `u32`, followed by one entry per
+//! file. A file entry is its path, encoded as in a commit archive, followed by
+//! the 32 byte blake3 hash of its contents and a tag byte. If the tag is `0`,
+//! the length of the file as a little-endian `u64` and its contents follow.
+//! If it is `1`, the contents were already written earlier in the stream, and
+//! the file is reconstructed by hard linking it to the first file with the
+//! same hash.
+//!
+//! Since files shared between commits are hard linked in the store, this
+//! deduplication keeps a store archive close to the size of the store on disk.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"piecrust";
+const VERSION: u32 = 1;
+
+const STORE_MAGIC: &[u8; 8] = b"pcstore\0";
+const STORE_VERSION: u32 = 1;
+
+/// Tag of a store archive file entry whose contents follow it.
+const CONTENTS_INLINE: u8 = 0;
+/// Tag of a store archive file entry whose contents were already written.
+const CONTENTS_SEEN: u8 = 1;
+
+/// Writes all files in the given `commit_dir` to `out`, in a deterministic
+/// order.
+pub fn write_archive<P: AsRef<Path>, W: Write>(
+    commit_dir: P,
+    mut out: W,
+) -> io::Result<()> {
+    let commit_dir = commit_dir.as_ref();
+
+    let mut paths = Vec::new();
+    collect_files(commit_dir, PathBuf::new(), &mut paths)?;
+    paths.sort();
+
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+
+    for path in paths {
+        let mut file = File::open(commit_dir.join(&path))?;
+        let file_len = file.metadata()?.len();
+
+        write_path(&path, &mut out)?;
+        out.write_all(&file_len.to_le_bytes())?;
+
+        let copied = io::copy(&mut (&mut file).take(file_len), &mut out)?;
+        if copied != file_len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("File changed while archiving: {path:?}"),
+            ));
+        }
+    }
+
+    out.flush()
+}
+
+/// Reads an archive written using [`write_archive`] from `data`, writing its
+/// files into the given `dir`.
+pub fn read_archive<P: AsRef<Path>, R: Read>(
+    dir: P,
+    mut data: R,
+) -> io::Result<()> {
+    let dir = dir.as_ref();
+
+    let mut magic = [0u8; 8];
+    data.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a commit archive",
+        ));
+    }
+
+    let mut version = [0u8; 4];
+    data.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsupported commit archive version: {version}"),
+        ));
+    }
+
+    loop {
+        let mut path_len = [0u8; 4];
+        match data.read_exact(&mut path_len) {
+            Ok(()) => {}
+            // The archive ends cleanly at an entry boundary.
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+        let path = read_path(u32::from_le_bytes(path_len), &mut data)?;
+
+        let mut file_len = [0u8; 8];
+        data.read_exact(&mut file_len)?;
+        let file_len = u64::from_le_bytes(file_len);
+
+        let file_path = dir.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = File::create(file_path)?;
+        let copied = io::copy(&mut (&mut data).take(file_len), &mut file)?;
+        if copied != file_len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Truncated commit archive",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the header of a store archive to `out`.
+pub fn write_store_header<W: Write>(mut out: W) -> io::Result<()> {
+    out.write_all(STORE_MAGIC)?;
+    out.write_all(&STORE_VERSION.to_le_bytes())
+}
+
+/// Writes the files in `commit_dir` to `out` as a frame of a store archive.
+///
+/// Files whose contents are in `seen` are written as references to them, and
+/// the contents of all other files are added to it.
+pub fn write_store_commit<P: AsRef<Path>, W: Write>(
+    root: [u8; 32],
+    commit_dir: P,
+    seen: &mut BTreeSet<[u8; 32]>,
+    mut out: W,
+) -> io::Result<()> {
+    let commit_dir = commit_dir.as_ref();
+
+    let mut paths = Vec::new();
+    collect_files(commit_dir, PathBuf::new(), &mut paths)?;
+    paths.sort();
+
+    out.write_all(&root)?;
+    out.write_all(&(paths.len() as u32).to_le_bytes())?;
+
+    for path in paths {
+        let contents = fs::read(commit_dir.join(&path))?;
+        let hash: [u8; 32] = blake3::hash(&contents).into();
+
+        write_path(&path, &mut out)?;
+        out.write_all(&hash)?;
+
+        if seen.insert(hash) {
+            out.write_all(&[CONTENTS_INLINE])?;
+            out.write_all(&(contents.len() as u64).to_le_bytes())?;
+            out.write_all(&contents)?;
+        } else {
+            out.write_all(&[CONTENTS_SEEN])?;
+        }
+    }
+
+    out.flush()
+}
+
+/// Reads the header of a store archive from `data`.
+pub fn read_store_header<R: Read>(mut data: R) -> io::Result<()> {
+    let mut magic = [0u8; 8];
+    data.read_exact(&mut magic)?;
+    if &magic != STORE_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a store archive",
+        ));
+    }
+
+    let mut version = [0u8; 4];
+    data.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != STORE_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsupported store archive version: {version}"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Reads the next commit frame of a store archive from `data`, writing its
+/// files into the given `dir`, and returning the root of the commit together
+/// with the hash of the contents of each file.
+///
+/// Files written as references are hard linked to the path returned by
+/// `seen`, or to an earlier file of the same frame. Returns `None` if the
+/// archive ends cleanly before the frame.
+#[allow(clippy::type_complexity)]
+pub fn read_store_commit<P, R, F>(
+    dir: P,
+    mut data: R,
+    seen: F,
+) -> io::Result<Option<([u8; 32], BTreeMap<PathBuf, [u8; 32]>)>>
+where
+    P: AsRef<Path>,
+    R: Read,
+    F: Fn(&[u8; 32]) -> Option<PathBuf>,
+{
+    let dir = dir.as_ref();
+
+    let mut root = [0u8; 32];
+    match data.read_exact(&mut root) {
+        Ok(()) => {}
+        // The archive ends cleanly at a frame boundary.
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            return Ok(None)
+        }
+        Err(err) => return Err(err),
+    }
+
+    let mut n_files = [0u8; 4];
+    data.read_exact(&mut n_files)?;
+    let n_files = u32::from_le_bytes(n_files);
+
+    let mut files = BTreeMap::new();
+    let mut frame_seen = BTreeMap::new();
+
+    for _ in 0..n_files {
+        let mut path_len = [0u8; 4];
+        data.read_exact(&mut path_len)?;
+        let path = read_path(u32::from_le_bytes(path_len), &mut data)?;
+
+        let mut hash = [0u8; 32];
+        data.read_exact(&mut hash)?;
+
+        let mut tag = [0u8; 1];
+        data.read_exact(&mut tag)?;
+
+        let file_path = dir.join(&path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match tag[0] {
+            CONTENTS_INLINE => {
+                let mut file_len = [0u8; 8];
+                data.read_exact(&mut file_len)?;
+                let file_len = u64::from_le_bytes(file_len);
+
+                let mut contents = Vec::new();
+                (&mut data).take(file_len).read_to_end(&mut contents)?;
+                if contents.len() as u64 != file_len {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Truncated store archive",
+                    ));
+                }
+
+                if blake3::hash(&contents) != hash {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Contents of {path:?} don't match their hash"),
+                    ));
+                }
+
+                fs::write(&file_path, contents)?;
+                frame_seen.entry(hash).or_insert(file_path);
+            }
+            CONTENTS_SEEN => {
+                let original = seen(&hash)
+                    .or_else(|| frame_seen.get(&hash).cloned())
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("Contents of {path:?} were never written"),
+                        )
+                    })?;
+
+                if fs::hard_link(&original, &file_path).is_err() {
+                    fs::copy(&original, &file_path)?;
+                }
+            }
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid file entry tag: {tag}"),
+                ))
+            }
+        }
+
+        files.insert(path, hash);
+    }
+
+    Ok(Some((root, files)))
+}
+
+/// Writes the length of the given `path` followed by the path itself, using
+/// `/` as a separator.
+fn write_path<W: Write>(path: &Path, mut out: W) -> io::Result<()> {
+    let path_str = path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid file path: {path:?}"),
+            )
+        })?
+        .join("/");
+
+    out.write_all(&(path_str.len() as u32).to_le_bytes())?;
+    out.write_all(path_str.as_bytes())
+}
+
+/// Reads a path of the given length from `data`.
+fn read_path<R: Read>(path_len: u32, mut data: R) -> io::Result<PathBuf> {
+    let mut path = vec![0u8; path_len as usize];
+    data.read_exact(&mut path)?;
+    let path = String::from_utf8(path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    relative_path(&path)
+}
+
+/// Parses a path from an archive, ensuring it stays within the directory it
+/// is extracted to.
+fn relative_path(path: &str) -> io::Result<PathBuf> {
+    let path = PathBuf::from_iter(path.split('/'));
+
+    let is_relative = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+
+    if path.as_os_str().is_empty() || !is_relative {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid path in commit archive: {path:?}"),
+        ));
+    }
+
+    Ok(path)
+}
+
+fn collect_files(
+    base: &Path,
+    relative: PathBuf,
+    paths: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(base.join(&relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            collect_files(base, path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
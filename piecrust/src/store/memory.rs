@@ -9,7 +9,9 @@ use std::{
     fmt::{Debug, Formatter},
     io,
     ops::{Deref, DerefMut, Range},
+    ptr,
     sync::atomic::AtomicUsize,
+    sync::Arc,
 };
 
 use crumbles::{LocateFile, Mmap};
@@ -20,12 +22,75 @@ pub const PAGE_SIZE: usize = 0x10000;
 const WASM32_MAX_PAGES: usize = 0x10000;
 const WASM64_MAX_PAGES: usize = 0x4000000;
 
+/// A report on the contract memories currently loaded from a store.
+///
+/// Only the memories of contracts are covered, and by their logical length -
+/// the length the contracts see - rather than by the bytes resident in
+/// physical memory, which may be fewer since pages are loaded lazily. The
+/// memory used by instances and by deserialized modules is not included.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The number of contract memories loaded by live sessions.
+    pub memories: usize,
+    /// The total logical length of the loaded memories, in bytes.
+    pub logical_len: usize,
+    /// The number of dirty pages zeroed on session drop since the store was
+    /// opened. See [`VM::zero_memories_on_drop`].
+    ///
+    /// [`VM::zero_memories_on_drop`]: crate::VM::zero_memories_on_drop
+    pub zeroed_pages: usize,
+}
+
+/// Keeps track of the number and total length of the memories loaded from a
+/// store, and of the number of pages zeroed in them.
+#[derive(Debug, Default)]
+pub struct MemoryTracker {
+    memories: AtomicUsize,
+    logical_len: AtomicUsize,
+    zeroed_pages: AtomicUsize,
+}
+
+impl MemoryTracker {
+    fn add(&self, len: usize) {
+        self.memories.fetch_add(1, Ordering::SeqCst);
+        self.logical_len.fetch_add(len, Ordering::SeqCst);
+    }
+
+    fn remove(&self, len: usize) {
+        self.memories.fetch_sub(1, Ordering::SeqCst);
+        self.logical_len.fetch_sub(len, Ordering::SeqCst);
+    }
+
+    fn resize(&self, old_len: usize, new_len: usize) {
+        if new_len > old_len {
+            self.logical_len
+                .fetch_add(new_len - old_len, Ordering::SeqCst);
+        } else {
+            self.logical_len
+                .fetch_sub(old_len - new_len, Ordering::SeqCst);
+        }
+    }
+
+    fn zeroed(&self, n_pages: usize) {
+        self.zeroed_pages.fetch_add(n_pages, Ordering::SeqCst);
+    }
+
+    pub fn report(&self) -> MemoryReport {
+        MemoryReport {
+            memories: self.memories.load(Ordering::SeqCst),
+            logical_len: self.logical_len.load(Ordering::SeqCst),
+            zeroed_pages: self.zeroed_pages.load(Ordering::SeqCst),
+        }
+    }
+}
+
 pub struct MemoryInner {
     pub mmap: Mmap,
-    pub current_len: usize,
+    current_len: usize,
     pub is_new: bool,
     is_64: bool,
     ref_count: AtomicUsize,
+    tracker: Arc<MemoryTracker>,
 }
 
 impl Debug for MemoryInner {
@@ -60,20 +125,24 @@ pub struct Memory {
 }
 
 impl Memory {
-    pub fn new(is_64: bool) -> io::Result<Self> {
+    pub fn new(is_64: bool, tracker: Arc<MemoryTracker>) -> io::Result<Self> {
         let max_pages = if is_64 {
             WASM64_MAX_PAGES
         } else {
             WASM32_MAX_PAGES
         };
 
+        let mmap = Mmap::new(max_pages, PAGE_SIZE)?;
+        tracker.add(0);
+
         Ok(Self {
             inner: Box::leak(Box::new(MemoryInner {
-                mmap: Mmap::new(max_pages, PAGE_SIZE)?,
+                mmap,
                 current_len: 0,
                 is_new: true,
                 is_64,
                 ref_count: AtomicUsize::new(1),
+                tracker,
             })),
         })
     }
@@ -82,6 +151,7 @@ impl Memory {
         is_64: bool,
         file_locator: FL,
         len: usize,
+        tracker: Arc<MemoryTracker>,
     ) -> io::Result<Self>
     where
         FL: 'static + LocateFile,
@@ -92,15 +162,18 @@ impl Memory {
             WASM32_MAX_PAGES
         };
 
+        let mmap =
+            unsafe { Mmap::with_files(max_pages, PAGE_SIZE, file_locator)? };
+        tracker.add(len);
+
         Ok(Self {
             inner: Box::leak(Box::new(MemoryInner {
-                mmap: unsafe {
-                    Mmap::with_files(max_pages, PAGE_SIZE, file_locator)?
-                },
+                mmap,
                 current_len: len,
                 is_new: false,
                 is_64,
                 ref_count: AtomicUsize::new(1),
+                tracker,
             })),
         })
     }
@@ -108,6 +181,38 @@ impl Memory {
     pub fn is_64(&self) -> bool {
         self.inner.is_64
     }
+
+    /// Returns the current length of the memory.
+    pub fn current_len(&self) -> usize {
+        self.inner.current_len
+    }
+
+    /// Overwrites all dirty pages of the memory with zeros.
+    ///
+    /// Volatile writes are used to ensure the zeroing is not optimized away.
+    pub fn zero_dirty_pages(&mut self) {
+        let page_indices: Vec<usize> = self
+            .dirty_pages()
+            .map(|(_, _, page_index)| *page_index)
+            .collect();
+
+        self.inner.tracker.zeroed(page_indices.len());
+
+        for page_index in page_indices {
+            let offset = page_index * PAGE_SIZE;
+            for byte in &mut self.inner.mmap[offset..][..PAGE_SIZE] {
+                // SAFETY: the pointer is derived from a mutable reference, and
+                // is therefore valid for writes and properly aligned.
+                unsafe { ptr::write_volatile(byte, 0) };
+            }
+        }
+    }
+
+    /// Sets the current length of the memory.
+    pub fn set_current_len(&mut self, len: usize) {
+        self.inner.tracker.resize(self.inner.current_len, len);
+        self.inner.current_len = len;
+    }
 }
 
 /// This implementation of clone is dangerous, and must be accompanied by the
@@ -135,6 +240,7 @@ impl Clone for Memory {
 impl Drop for Memory {
     fn drop(&mut self) {
         if self.ref_count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.tracker.remove(self.inner.current_len);
             unsafe {
                 let _ = Box::from_raw(self.inner);
             }
@@ -166,7 +272,20 @@ unsafe impl LinearMemory for Memory {
     }
 
     fn grow_to(&mut self, new_size: usize) -> Result<(), dusk_wasmtime::Error> {
-        self.inner.current_len = new_size;
+        let old_size = self.inner.current_len;
+        self.set_current_len(new_size);
+
+        // The root of the state only covers the pages written to, so the last
+        // page of a grown memory is marked dirty by rewriting one of its bytes.
+        // This way growing a memory without writing to it still changes the
+        // root, and the new length is committed.
+        if new_size > old_size {
+            let byte = &mut self.inner.mmap[new_size - 1] as *mut u8;
+            // SAFETY: the byte is within the memory, and the volatile accesses
+            // ensure the write is not elided.
+            unsafe { ptr::write_volatile(byte, ptr::read_volatile(byte)) };
+        }
+
         Ok(())
     }
 
@@ -4,9 +4,11 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
+use std::collections::BTreeMap;
 use std::io;
 use std::ops::Deref;
 use std::path::Path;
+use std::sync::Mutex;
 
 use dusk_wasmtime::Engine;
 
@@ -112,3 +114,105 @@ impl Deref for Module {
         &self.module
     }
 }
+
+/// A report on the use of a [`ModuleCache`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleCacheReport {
+    /// The number of modules currently in the cache.
+    pub modules: usize,
+    /// The number of module loads served from the cache.
+    pub hits: u64,
+    /// The number of module loads that had to deserialize the module.
+    pub misses: u64,
+}
+
+/// A bounded cache of deserialized modules, shared between all sessions
+/// spawned from a store.
+///
+/// Modules are keyed by the hash of the bytecode they were compiled from, and
+/// the least recently used module is evicted when the cache is full. The cache
+/// starts with a size of zero, meaning it holds no modules.
+#[derive(Debug, Default)]
+pub struct ModuleCache {
+    inner: Mutex<ModuleCacheInner>,
+}
+
+#[derive(Debug, Default)]
+struct ModuleCacheInner {
+    size: usize,
+    clock: u64,
+    modules: BTreeMap<[u8; 32], (u64, Module)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ModuleCacheInner {
+    fn evict(&mut self) {
+        while self.modules.len() > self.size {
+            let oldest = self
+                .modules
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(key, _)| *key)
+                .expect("The cache should not be empty");
+            self.modules.remove(&oldest);
+        }
+    }
+}
+
+impl ModuleCache {
+    /// Sets the maximum number of modules held by the cache, evicting the
+    /// least recently used ones if it holds more.
+    pub fn set_size(&self, size: usize) {
+        let mut inner = self.inner.lock().expect("Lock should not be poisoned");
+        inner.size = size;
+        inner.evict();
+    }
+
+    /// Returns the module compiled from the given `bytecode`, calling `load`
+    /// to deserialize it if it's not in the cache.
+    pub fn get_or_load<F>(&self, bytecode: &[u8], load: F) -> io::Result<Module>
+    where
+        F: FnOnce() -> io::Result<Module>,
+    {
+        let key: [u8; 32] = blake3::hash(bytecode).into();
+
+        {
+            let mut inner =
+                self.inner.lock().expect("Lock should not be poisoned");
+            inner.clock += 1;
+
+            let clock = inner.clock;
+            if let Some((last_used, module)) = inner.modules.get_mut(&key) {
+                *last_used = clock;
+                let module = module.clone();
+                inner.hits += 1;
+                return Ok(module);
+            }
+            inner.misses += 1;
+        }
+
+        // The lock is not held while loading, so sessions loading different
+        // modules don't wait on each other.
+        let module = load()?;
+
+        let mut inner = self.inner.lock().expect("Lock should not be poisoned");
+        if inner.size > 0 {
+            let clock = inner.clock;
+            inner.modules.insert(key, (clock, module.clone()));
+            inner.evict();
+        }
+
+        Ok(module)
+    }
+
+    /// Returns a report on the use of the cache.
+    pub fn report(&self) -> ModuleCacheReport {
+        let inner = self.inner.lock().expect("Lock should not be poisoned");
+        ModuleCacheReport {
+            modules: inner.modules.len(),
+            hits: inner.hits,
+            misses: inner.misses,
+        }
+    }
+}
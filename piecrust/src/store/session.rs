@@ -5,7 +5,7 @@
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
 use std::collections::btree_map::Entry::{Occupied, Vacant};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc};
@@ -13,11 +13,13 @@ use std::{io, mem};
 
 use dusk_wasmtime::Engine;
 use piecrust_uplink::ContractId;
+use tempfile::TempDir;
 
 use crate::contract::ContractMetadata;
-use crate::store::tree::{Hash, PageOpening};
+use crate::store::tree::{ContractIndex, Hash, PageOpening};
 use crate::store::{
-    Bytecode, Call, Commit, Memory, Metadata, Module, BYTECODE_DIR, MEMORY_DIR,
+    Bytecode, Call, Commit, CommitLayout, ContractStore, Memory, MemoryTracker,
+    Metadata, Module, ModuleCache, BYTECODE_DIR, MEMORY_DIR,
     METADATA_EXTENSION, OBJECTCODE_EXTENSION, PAGE_SIZE,
 };
 use crate::Error;
@@ -42,37 +44,60 @@ pub struct ContractDataEntry {
 /// [`commit`]: ContractSession::commit
 pub struct ContractSession {
     contracts: BTreeMap<ContractId, ContractDataEntry>,
+    destructed: BTreeSet<ContractId>,
     engine: Engine,
 
     base: Option<Commit>,
     root_dir: PathBuf,
+    layout: CommitLayout,
 
     call: mpsc::Sender<Call>,
+    memory_tracker: Arc<MemoryTracker>,
+    module_cache: Arc<ModuleCache>,
+    zero_memories: bool,
+    max_sessions_per_commit: Option<usize>,
+    tmp_dir: Option<Arc<TempDir>>,
 }
 
 impl Debug for ContractSession {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ContractSession")
             .field("contracts", &self.contracts)
+            .field("destructed", &self.destructed)
             .field("base", &self.base)
             .field("root_dir", &self.root_dir)
+            .field("layout", &self.layout)
             .finish()
     }
 }
 
 impl ContractSession {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new<P: AsRef<Path>>(
         root_dir: P,
+        layout: CommitLayout,
         engine: Engine,
         base: Option<Commit>,
         call: mpsc::Sender<Call>,
+        memory_tracker: Arc<MemoryTracker>,
+        module_cache: Arc<ModuleCache>,
+        zero_memories: bool,
+        max_sessions_per_commit: Option<usize>,
+        tmp_dir: Option<Arc<TempDir>>,
     ) -> Self {
         Self {
             contracts: BTreeMap::new(),
+            destructed: BTreeSet::new(),
             engine,
             base,
             root_dir: root_dir.as_ref().into(),
+            layout,
             call,
+            memory_tracker,
+            module_cache,
+            zero_memories,
+            max_sessions_per_commit,
+            tmp_dir,
         }
     }
 
@@ -87,6 +112,9 @@ impl ContractSession {
     /// [`contract`]: ContractSession::contract
     pub fn root(&self) -> Hash {
         let mut commit = self.base.clone().unwrap_or_default();
+        for contract in &self.destructed {
+            commit.index.remove(contract);
+        }
         for (contract, entry) in &self.contracts {
             commit.index.insert(*contract, &entry.memory);
         }
@@ -95,6 +123,30 @@ impl ContractSession {
         *root
     }
 
+    /// Returns the number of bytes committing the session would newly write
+    /// to disk.
+    ///
+    /// These are the dirty pages of all contracts, together with the
+    /// bytecode, object code, and metadata of newly deployed contracts. Files
+    /// hard linked from the base commit, and the index file, are not
+    /// included.
+    pub fn estimated_commit_bytes(&self) -> u64 {
+        let mut bytes = 0;
+
+        for entry in self.contracts.values() {
+            let n_dirty_pages = entry.memory.dirty_pages().count();
+            bytes += (n_dirty_pages * PAGE_SIZE) as u64;
+
+            if entry.is_new {
+                bytes += entry.bytecode.as_ref().len() as u64;
+                bytes += entry.module.serialize().len() as u64;
+                bytes += entry.metadata.as_ref().len() as u64;
+            }
+        }
+
+        bytes
+    }
+
     /// Returns an iterator through all the pages of a contract, together with a
     /// proof of their inclusion in the state.
     pub fn memory_pages(
@@ -133,23 +185,73 @@ impl ContractSession {
     ///
     /// [`contract`]: ContractSession::contract
     pub fn commit(&mut self) -> io::Result<Hash> {
+        // A session that changed nothing would commit to its base, so its root
+        // is returned without re-deriving it or contacting the store.
+        if let Some(root) = self.unchanged_base_root() {
+            return Ok(root);
+        }
+
+        let call = self.call.clone();
+        self.commit_with(&call)
+    }
+
+    /// Returns the root of the base commit if the session has not modified
+    /// it - i.e. no contract was deployed, destructed, had its memory written
+    /// to, or had its memory grown.
+    fn unchanged_base_root(&self) -> Option<Hash> {
+        let base = self.base.as_ref()?;
+
+        let unchanged = self.destructed.is_empty()
+            && self.contracts.iter().all(|(contract, entry)| {
+                let base_len =
+                    base.index.get(contract).map(|element| element.len);
+
+                !entry.is_new
+                    && base_len == Some(entry.memory.current_len())
+                    && entry.memory.dirty_pages().next().is_none()
+            });
+
+        unchanged.then(|| *base.index.root())
+    }
+
+    /// Commits the given session to disk, consuming the session and adding it
+    /// to the given `store`, instead of the one it was created from.
+    ///
+    /// If the base commit of the session is present in the target `store`, the
+    /// files of unchanged contracts are hard linked from it. Otherwise, they
+    /// are hard linked from the base commit in the original store, or copied
+    /// if that is not possible - such as when the stores are on different
+    /// filesystems.
+    ///
+    /// # Safety
+    /// This method should only be called once, while immediately allowing the
+    /// `ContractSession` to drop.
+    pub fn commit_to(&mut self, store: &ContractStore) -> io::Result<Hash> {
+        self.commit_with(&store.call)
+    }
+
+    fn commit_with(&mut self, call: &mpsc::Sender<Call>) -> io::Result<Hash> {
         let (replier, receiver) = mpsc::sync_channel(1);
 
         let mut contracts = BTreeMap::new();
+        let mut destructed = BTreeSet::new();
         let mut base = self.base.as_ref().map(|c| Commit {
             index: c.index.clone(),
         });
 
         mem::swap(&mut self.contracts, &mut contracts);
+        mem::swap(&mut self.destructed, &mut destructed);
         mem::swap(&mut self.base, &mut base);
 
-        self.call
-            .send(Call::Commit {
-                contracts,
-                base,
-                replier,
-            })
-            .expect("The receiver should never drop before sending");
+        call.send(Call::Commit {
+            contracts,
+            destructed,
+            base,
+            base_root_dir: self.root_dir.clone(),
+            base_layout: self.layout,
+            replier,
+        })
+        .expect("The receiver should never drop before sending");
 
         receiver
             .recv()
@@ -166,11 +268,18 @@ impl ContractSession {
     /// - The contract has been [`deploy`]ed in this session
     /// - The contract was deployed to the base commit
     ///
+    /// and it has not been [`destruct`]ed in this session.
+    ///
     /// [`deploy`]: ContractSession::deploy
+    /// [`destruct`]: ContractSession::destruct_contract
     pub fn contract(
         &mut self,
         contract: ContractId,
     ) -> io::Result<Option<ContractDataEntry>> {
+        if self.destructed.contains(&contract) {
+            return Ok(None);
+        }
+
         match self.contracts.entry(contract) {
             Vacant(entry) => match &self.base {
                 None => Ok(None),
@@ -179,8 +288,8 @@ impl ContractSession {
 
                     match base_commit.index.contains_key(&contract) {
                         true => {
-                            let base_hex = hex::encode(*base);
-                            let base_dir = self.root_dir.join(base_hex);
+                            let base_dir =
+                                self.layout.commit_dir(&self.root_dir, *base);
 
                             let contract_hex = hex::encode(contract);
 
@@ -190,42 +299,22 @@ impl ContractSession {
                                 .with_extension(OBJECTCODE_EXTENSION);
                             let metadata_path = bytecode_path
                                 .with_extension(METADATA_EXTENSION);
-                            let memory_path =
-                                base_dir.join(MEMORY_DIR).join(contract_hex);
 
                             let bytecode = Bytecode::from_file(bytecode_path)?;
-                            let module =
-                                Module::from_file(&self.engine, module_path)?;
+                            let module = self
+                                .module_cache
+                                .get_or_load(bytecode.as_ref(), || {
+                                    Module::from_file(&self.engine, module_path)
+                                })?;
                             let metadata = Metadata::from_file(metadata_path)?;
 
-                            let memory = match base_commit.index.get(&contract)
-                            {
-                                Some(elem) => {
-                                    let page_indices =
-                                        elem.page_indices.clone();
-                                    let memory_path = memory_path.clone();
-
-                                    Memory::from_files(
-                                        module.is_64(),
-                                        move |page_index: usize| {
-                                            match page_indices
-                                                .contains(&page_index)
-                                            {
-                                                true => {
-                                                    let page_path = memory_path
-                                                        .join(format!(
-                                                            "{page_index}"
-                                                        ));
-                                                    Some(page_path)
-                                                }
-                                                false => None,
-                                            }
-                                        },
-                                        elem.len,
-                                    )?
-                                }
-                                None => Memory::new(module.is_64())?,
-                            };
+                            let memory = memory_from_base(
+                                &base_dir,
+                                &base_commit.index,
+                                &contract,
+                                module.is_64(),
+                                self.memory_tracker.clone(),
+                            )?;
 
                             let contract = entry
                                 .insert(ContractDataEntry {
@@ -247,14 +336,202 @@ impl ContractSession {
         }
     }
 
+    /// Forks the session, returning a new session with the same base commit
+    /// and a copy of each contract in this one.
+    ///
+    /// The memories of the contracts are copied, meaning that modifications
+    /// made to them in one session are not visible in the other.
+    ///
+    /// The fork holds the base commit just like a session spawned from the
+    /// store does, so it errors if the base commit is already held by the
+    /// maximum number of sessions.
+    pub fn fork(&self) -> io::Result<ContractSession> {
+        let base = match &self.base {
+            Some(base) => Some(self.hold_base(*base.index.root())?),
+            None => None,
+        };
+
+        // The fork is created before copying the contracts, so the base commit
+        // is released should copying fail.
+        let mut fork = ContractSession::new(
+            &self.root_dir,
+            self.layout,
+            self.engine.clone(),
+            base,
+            self.call.clone(),
+            self.memory_tracker.clone(),
+            self.module_cache.clone(),
+            self.zero_memories,
+            self.max_sessions_per_commit,
+            self.tmp_dir.clone(),
+        );
+        fork.destructed = self.destructed.clone();
+
+        for (contract, entry) in &self.contracts {
+            let is_64 = entry.memory.is_64();
+
+            let mut memory = match (&fork.base, entry.is_new) {
+                (Some(base), false) => {
+                    let base_dir = self
+                        .layout
+                        .commit_dir(&self.root_dir, *base.index.root());
+                    memory_from_base(
+                        &base_dir,
+                        &base.index,
+                        contract,
+                        is_64,
+                        self.memory_tracker.clone(),
+                    )?
+                }
+                _ => Memory::new(is_64, self.memory_tracker.clone())?,
+            };
+
+            // Writing the dirty pages to the new memory leaves exactly the
+            // same pages dirty in it. A memory that has yet to be initialized
+            // by its instance must stay so in the fork.
+            memory.set_current_len(entry.memory.current_len());
+            memory.is_new = entry.memory.is_new;
+            for (dirty_page, _, page_index) in entry.memory.dirty_pages() {
+                let offset = page_index * PAGE_SIZE;
+                memory.mmap[offset..][..PAGE_SIZE].copy_from_slice(dirty_page);
+            }
+
+            fork.contracts.insert(
+                *contract,
+                ContractDataEntry {
+                    bytecode: entry.bytecode.clone(),
+                    module: entry.module.clone(),
+                    metadata: entry.metadata.clone(),
+                    memory,
+                    is_new: entry.is_new,
+                },
+            );
+        }
+
+        Ok(fork)
+    }
+
+    /// Increments the hold count of the given base commit, respecting the
+    /// maximum number of sessions per commit if one is set.
+    fn hold_base(&self, base: Hash) -> io::Result<Commit> {
+        match self.max_sessions_per_commit {
+            Some(max_sessions) => {
+                let (replier, receiver) = mpsc::sync_channel(1);
+                self.call
+                    .send(Call::SessionHold {
+                        base,
+                        max_sessions,
+                        replier,
+                    })
+                    .expect("The receiver should never drop before sending");
+
+                receiver
+                    .recv()
+                    .expect("The receiver should always receive a reply")
+            }
+            None => {
+                let (replier, receiver) = mpsc::sync_channel(1);
+                self.call
+                    .send(Call::CommitHold { base, replier })
+                    .expect("The receiver should never drop before sending");
+
+                receiver
+                    .recv()
+                    .expect("The receiver should always receive a reply")
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!(
+                                "No such base commit: {}",
+                                hex::encode(base)
+                            ),
+                        )
+                    })
+            }
+        }
+    }
+
+    /// Discards all the contracts in the session, and releases the base commit
+    /// if any, leaving the session with no base.
+    pub fn reset(&mut self) {
+        self.release();
+        self.contracts.clear();
+        self.destructed.clear();
+    }
+
+    /// Discards all the modifications made to the contracts in the session,
+    /// leaving it as it was when spawned from its base commit.
+    pub fn rollback(&mut self) {
+        if self.zero_memories {
+            for entry in self.contracts.values_mut() {
+                entry.memory.zero_dirty_pages();
+            }
+        }
+
+        self.contracts.clear();
+        self.destructed.clear();
+    }
+
+    /// Zeroes the contract memories if configured, and signals the store that
+    /// the base commit is no longer held by the session.
+    fn release(&mut self) {
+        if self.zero_memories {
+            for entry in self.contracts.values_mut() {
+                entry.memory.zero_dirty_pages();
+            }
+        }
+
+        if let Some(base) = self.base.take() {
+            let root = base.index.root();
+            let _ = self.call.send(Call::SessionDrop(*root));
+        }
+    }
+
     /// Remove the given contract from the session.
     pub fn remove_contract(&mut self, contract: &ContractId) {
         self.contracts.remove(contract);
     }
 
+    /// Destructs the given contract, removing it from the session.
+    ///
+    /// The contract will no longer be available in the session, and it is not
+    /// carried forward into the commit written from it.
+    pub fn destruct_contract(&mut self, contract: ContractId) {
+        self.contracts.remove(&contract);
+        self.destructed.insert(contract);
+    }
+
+    /// Returns the current length of the memory of each contract loaded in the
+    /// session.
+    pub fn memory_lens(
+        &self,
+    ) -> impl Iterator<Item = (ContractId, usize)> + '_ {
+        self.contracts
+            .iter()
+            .map(|(contract, entry)| (*contract, entry.memory.current_len()))
+    }
+
+    /// Returns the IDs of all contracts deployed in the session, including the
+    /// ones in the base commit.
+    pub fn deployed_contracts(&self) -> Vec<ContractId> {
+        let mut contracts: BTreeSet<ContractId> =
+            self.contracts.keys().copied().collect();
+
+        if let Some(base) = &self.base {
+            contracts.extend(base.index.iter().map(|(contract, _)| *contract));
+        }
+
+        contracts
+            .into_iter()
+            .filter(|contract| !self.destructed.contains(contract))
+            .collect()
+    }
+
     /// Checks if contract is deployed
     pub fn contract_deployed(&mut self, contract_id: ContractId) -> bool {
-        if self.contracts.contains_key(&contract_id) {
+        if self.destructed.contains(&contract_id) {
+            false
+        } else if self.contracts.contains_key(&contract_id) {
             true
         } else if let Some(base_commit) = &self.base {
             base_commit.index.contains_key(&contract_id)
@@ -279,7 +556,16 @@ impl ContractSession {
         let bytecode = Bytecode::new(bytecode)?;
         let module = Module::new(&self.engine, module)?;
         let metadata = Metadata::new(metadata_bytes, metadata)?;
-        let memory = Memory::new(module.is_64())?;
+        let memory = Memory::new(module.is_64(), self.memory_tracker.clone())?;
+
+        // Contracts destructed in this session cannot be redeployed until the
+        // session is committed.
+        if self.destructed.contains(&contract_id) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Destructed contract '{contract_id}'"),
+            ));
+        }
 
         // If the position is already filled in the tree, the contract cannot be
         // inserted.
@@ -345,11 +631,43 @@ impl ContractSession {
     }
 }
 
+/// Returns the memory of the given `contract` as stored in the base commit
+/// located at `base_dir`, or a new memory if the commit has none for it.
+fn memory_from_base(
+    base_dir: &Path,
+    index: &ContractIndex,
+    contract: &ContractId,
+    is_64: bool,
+    memory_tracker: Arc<MemoryTracker>,
+) -> io::Result<Memory> {
+    match index.get(contract) {
+        Some(elem) => {
+            let page_indices = elem.page_indices.clone();
+            let memory_path =
+                base_dir.join(MEMORY_DIR).join(hex::encode(contract));
+
+            Memory::from_files(
+                is_64,
+                move |page_index: usize| match page_indices
+                    .contains(&page_index)
+                {
+                    true => {
+                        let page_path =
+                            memory_path.join(format!("{page_index}"));
+                        Some(page_path)
+                    }
+                    false => None,
+                },
+                elem.len,
+                memory_tracker,
+            )
+        }
+        None => Memory::new(is_64, memory_tracker),
+    }
+}
+
 impl Drop for ContractSession {
     fn drop(&mut self) {
-        if let Some(base) = self.base.take() {
-            let root = base.index.root();
-            let _ = self.call.send(Call::SessionDrop(*root));
-        }
+        self.release();
     }
 }
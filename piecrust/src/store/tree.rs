@@ -146,7 +146,7 @@ impl ContractIndex {
         }
         let element = self.contracts.get_mut(&contract).unwrap();
 
-        element.len = memory.current_len;
+        element.len = memory.current_len();
 
         for (dirty_page, _, page_index) in memory.dirty_pages() {
             element.page_indices.insert(*page_index);
@@ -158,6 +158,40 @@ impl ContractIndex {
             .insert(position_from_contract(&contract), *element.tree.root());
     }
 
+    /// Inserts a contract with the given memory length and pages into the
+    /// index, replacing any previous entry for the contract.
+    pub fn insert_pages<'a, I>(
+        &mut self,
+        contract: ContractId,
+        is_64: bool,
+        len: usize,
+        pages: I,
+    ) where
+        I: IntoIterator<Item = (usize, &'a [u8])>,
+    {
+        let mut element = ContractIndexElement {
+            tree: PageTree::new(is_64),
+            len,
+            page_indices: BTreeSet::new(),
+        };
+
+        for (page_index, page) in pages {
+            element.page_indices.insert(page_index);
+            element.tree.insert(page_index as u64, Hash::new(page));
+        }
+
+        self.tree
+            .insert(position_from_contract(&contract), *element.tree.root());
+        self.contracts.insert(contract, element);
+    }
+
+    /// Removes a contract from the index, if present.
+    pub fn remove(&mut self, contract: &ContractId) {
+        if self.contracts.remove(contract).is_some() {
+            self.tree.remove(position_from_contract(contract));
+        }
+    }
+
     pub fn remove_and_insert(&mut self, contract: ContractId, memory: &Memory) {
         self.contracts.remove(&contract);
         self.insert(contract, memory);
@@ -0,0 +1,111 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+//! Utilities for writing tests against the VM.
+
+use std::collections::BTreeMap;
+
+use piecrust_uplink::ContractId;
+
+use crate::contract::ContractData;
+use crate::error::Error;
+use crate::session::{Session, SessionData};
+use crate::vm::VM;
+
+const DEFAULT_OWNER: [u8; 32] = [0u8; 32];
+const DEFAULT_GAS_LIMIT: u64 = 1_000_000;
+
+/// Builds a [`Session`] with a set of contracts already deployed.
+///
+/// This removes the need to repeatedly spawn a session and deploy each
+/// contract by hand when writing tests.
+///
+/// ```ignore
+/// use piecrust::{contract_bytecode, TestSessionBuilder, VM};
+///
+/// let vm = VM::ephemeral()?;
+///
+/// let (mut session, ids) = TestSessionBuilder::new(&vm)
+///     .contract("counter", contract_bytecode!("counter"))
+///     .contract("box", contract_bytecode!("box"))
+///     .build()?;
+///
+/// let counter_id = ids["counter"];
+/// ```
+pub struct TestSessionBuilder<'a> {
+    vm: &'a VM,
+    data: SessionData,
+    owner: Vec<u8>,
+    gas_limit: u64,
+    contracts: Vec<(String, &'a [u8])>,
+}
+
+impl<'a> TestSessionBuilder<'a> {
+    /// Creates a new builder for a session spawned from the given `vm`.
+    pub fn new(vm: &'a VM) -> Self {
+        Self {
+            vm,
+            data: SessionData::default(),
+            owner: DEFAULT_OWNER.to_vec(),
+            gas_limit: DEFAULT_GAS_LIMIT,
+            contracts: Vec::new(),
+        }
+    }
+
+    /// Set the data the session is spawned with.
+    pub fn data(mut self, data: impl Into<SessionData>) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    /// Set the owner of all the deployed contracts.
+    pub fn owner(mut self, owner: impl Into<Vec<u8>>) -> Self {
+        self.owner = owner.into();
+        self
+    }
+
+    /// Set the gas limit used for each deployment.
+    pub fn gas_limit(mut self, gas_limit: u64) -> Self {
+        self.gas_limit = gas_limit;
+        self
+    }
+
+    /// Add a contract to be deployed under the given `name`.
+    ///
+    /// Contracts are deployed in the order they are added.
+    pub fn contract<S: Into<String>>(
+        mut self,
+        name: S,
+        bytecode: &'a [u8],
+    ) -> Self {
+        self.contracts.push((name.into(), bytecode));
+        self
+    }
+
+    /// Spawns the session and deploys all contracts, returning the session
+    /// together with the IDs of the contracts, mapped by their names.
+    ///
+    /// # Errors
+    /// If spawning the session fails, or if a deployment fails. In the latter
+    /// case, the error of the failing deployment is returned unchanged.
+    pub fn build(
+        self,
+    ) -> Result<(Session, BTreeMap<String, ContractId>), Error> {
+        let mut session = self.vm.session(self.data)?;
+        let mut ids = BTreeMap::new();
+
+        for (name, bytecode) in self.contracts {
+            let id = session.deploy(
+                bytecode,
+                ContractData::builder().owner(self.owner.clone()),
+                self.gas_limit,
+            )?;
+            ids.insert(name, id);
+        }
+
+        Ok((session, ids))
+    }
+}
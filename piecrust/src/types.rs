@@ -4,12 +4,11 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
-use piecrust_uplink::SCRATCH_BUF_BYTES;
 use rkyv::ser::serializers::{
     BufferScratch, BufferSerializer, CompositeSerializer,
 };
 
 pub type StandardBufSerializer<'a> = CompositeSerializer<
     BufferSerializer<&'a mut [u8]>,
-    BufferScratch<&'a mut [u8; SCRATCH_BUF_BYTES]>,
+    BufferScratch<&'a mut [u8]>,
 >;
@@ -7,20 +7,32 @@
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt::{self, Debug, Formatter};
+use std::io::{Read, Write};
+use std::num::NonZeroUsize;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::SystemTime;
 
 use dusk_wasmtime::{
     Config, Engine, ModuleVersionStrategy, OptLevel, Strategy,
     WasmBacktraceDetails,
 };
-use tempfile::tempdir;
+use piecrust_uplink::ContractId;
+use tempfile::{tempdir, tempdir_in};
 
 use crate::session::{Session, SessionData};
-use crate::store::ContractStore;
+use crate::store::{
+    self, CommitDelta, CommitInfo, CommitLayout, ContractStore, MemoryReport,
+    ModuleCacheReport,
+};
 use crate::Error::{self, PersistenceError};
 
+/// The name of the host query returning the fixed time set using
+/// [`VM::with_fixed_time`].
+#[cfg(feature = "test-utils")]
+pub const TIME_QUERY: &str = "time";
+
 fn config() -> Config {
     let mut config = Config::new();
 
@@ -32,6 +44,7 @@ fn config() -> Config {
 
     // 512KiB of max stack is the default, but we want to be explicit about it.
     config.max_wasm_stack(0x80000);
+    // Fuel is used as gas. Its cost per operator is fixed by the runtime.
     config.consume_fuel(true);
 
     config.strategy(Strategy::Cranelift);
@@ -81,6 +94,7 @@ fn config() -> Config {
 pub struct VM {
     engine: Engine,
     host_queries: HostQueries,
+    deploy_policy: SharedDeployPolicy,
     store: ContractStore,
 }
 
@@ -89,6 +103,7 @@ impl Debug for VM {
         f.debug_struct("VM")
             .field("config", self.engine.config())
             .field("host_queries", &self.host_queries)
+            .field("deploy_policy", &self.deploy_policy)
             .field("store", &self.store)
             .finish()
     }
@@ -104,53 +119,163 @@ impl VM {
     /// # Errors
     /// If the directory contains unparseable or inconsistent data.
     pub fn new<P: AsRef<Path>>(root_dir: P) -> Result<Self, Error> {
+        Self::with_commit_layout(root_dir, CommitLayout::default())
+    }
+
+    /// Creates a new `VM`, reading the given `dir`ectory for existing commits
+    /// and bytecode, with the commit directories following the given
+    /// `layout`.
+    ///
+    /// The same layout must be used every time a directory is opened.
+    ///
+    /// # Errors
+    /// If the directory contains unparseable or inconsistent data.
+    pub fn with_commit_layout<P: AsRef<Path>>(
+        root_dir: P,
+        layout: CommitLayout,
+    ) -> Result<Self, Error> {
+        Self::with_load_parallelism(root_dir, layout, default_parallelism())
+    }
+
+    /// Creates a new `VM`, reading the given `dir`ectory for existing commits
+    /// and bytecode using up to `parallelism` threads, with the commit
+    /// directories following the given `layout`.
+    ///
+    /// By default, the commits are read using as many threads as the
+    /// available parallelism of the machine. Reading them in parallel speeds
+    /// up the startup of a `VM` with many commits.
+    ///
+    /// # Errors
+    /// If the directory contains unparseable or inconsistent data.
+    pub fn with_load_parallelism<P: AsRef<Path>>(
+        root_dir: P,
+        layout: CommitLayout,
+        parallelism: NonZeroUsize,
+    ) -> Result<Self, Error> {
         let config = config();
 
         let engine = Engine::new(&config).expect(
             "Configuration should be valid since its set at compile time",
         );
 
-        let store = ContractStore::new(engine.clone(), root_dir)
-            .map_err(|err| PersistenceError(Arc::new(err)))?;
+        let store =
+            ContractStore::new(engine.clone(), root_dir, layout, parallelism)
+                .map_err(|err| PersistenceError(Arc::new(err)))?;
 
         Ok(Self {
             engine,
             host_queries: HostQueries::default(),
+            deploy_policy: SharedDeployPolicy::default(),
             store,
         })
     }
 
+    /// Rebuilds the index file of the commit with the given `root` in the
+    /// `root_dir`ectory, laid out according to `layout`, allowing a `VM` to be
+    /// created from a directory in which the index file of a commit is
+    /// missing or corrupted.
+    ///
+    /// The index is recomputed from the bytecode and memory files of the
+    /// commit, with the memory lengths recorded alongside it, and is only
+    /// written if its root matches the commit's.
+    ///
+    /// This should not be called on a directory in use by a `VM`.
+    ///
+    /// # Errors
+    /// If the files of the commit are not readable, if the memory lengths
+    /// were not recorded, or if the files don't produce the commit's root -
+    /// in which case the commit is corrupted.
+    pub fn rebuild_index<P: AsRef<Path>>(
+        root_dir: P,
+        layout: CommitLayout,
+        root: [u8; 32],
+    ) -> Result<(), Error> {
+        store::rebuild_index(root_dir, layout, root.into())
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Migrates the store in the `root_dir`ectory, laid out according to
+    /// `layout`, to the current [`STORE_FORMAT_VERSION`], allowing a `VM` to
+    /// be created from it.
+    ///
+    /// Any commit writes or imports left unfinished are removed, and missing
+    /// index files are rebuilt as in [`rebuild_index`]. The migration is
+    /// idempotent, and running it on a store of the current version is safe.
+    ///
+    /// This should not be called on a directory in use by a `VM`.
+    ///
+    /// # Errors
+    /// If the store is of a newer version than the current one, or if the
+    /// files of a commit missing its index don't produce the commit's root.
+    ///
+    /// [`STORE_FORMAT_VERSION`]: crate::STORE_FORMAT_VERSION
+    /// [`rebuild_index`]: VM::rebuild_index
+    pub fn migrate_store<P: AsRef<Path>>(
+        root_dir: P,
+        layout: CommitLayout,
+    ) -> Result<(), Error> {
+        store::migrate_store(root_dir, layout)
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
     /// Creates a new `VM` using a new temporary directory.
     ///
-    /// Any session commits made by this machine should be considered discarded
-    /// once this `VM` instance drops.
+    /// The directory and all commits in it are removed once this `VM`
+    /// instance and all the sessions it spawned drop. Sessions may therefore
+    /// outlive the `VM`, and still be committed.
     ///
     /// # Errors
     /// If creating a temporary directory fails.
     pub fn ephemeral() -> Result<Self, Error> {
-        let tmp = tempdir().map_err(|err| PersistenceError(Arc::new(err)))?;
-        let tmp = tmp.path().to_path_buf();
-
-        let config = config();
+        let tmp_dir =
+            tempdir().map_err(|err| PersistenceError(Arc::new(err)))?;
 
-        let engine = Engine::new(&config).expect(
-            "Configuration should be valid since its set at compile time",
-        );
+        let mut vm = Self::with_load_parallelism(
+            tmp_dir.path(),
+            CommitLayout::default(),
+            default_parallelism(),
+        )?;
+        vm.store.set_tmp_dir(tmp_dir);
 
-        let store = ContractStore::new(engine.clone(), tmp)
-            .map_err(|err| PersistenceError(Arc::new(err)))?;
+        Ok(vm)
+    }
 
-        Ok(Self {
-            engine,
-            host_queries: HostQueries::default(),
-            store,
-        })
+    /// Creates a new `VM` using a new, uniquely named, temporary directory
+    /// under the given `dir`.
+    ///
+    /// As with [`ephemeral`], the directory and all commits in it are removed
+    /// once this `VM` instance and all the sessions it spawned drop. This
+    /// allows for placing the state of short-lived machines on a chosen
+    /// disk, such as a fast scratch disk, without leaving it behind.
+    ///
+    /// # Errors
+    /// If creating the temporary directory fails.
+    ///
+    /// [`ephemeral`]: VM::ephemeral
+    pub fn ephemeral_in<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let tmp_dir =
+            tempdir_in(dir).map_err(|err| PersistenceError(Arc::new(err)))?;
+
+        let mut vm = Self::with_load_parallelism(
+            tmp_dir.path(),
+            CommitLayout::default(),
+            default_parallelism(),
+        )?;
+        vm.store.set_tmp_dir(tmp_dir);
+
+        Ok(vm)
     }
 
     /// Registers a [host `query`] with the given `name`.
     ///
-    /// The query will be available to any session spawned *after* this was
-    /// called.
+    /// The queries are shared between the `VM` and all the sessions it spawns,
+    /// so the query will be available to any session - including ones spawned
+    /// *before* this was called - from their next call onwards. Registering a
+    /// query with the name of an existing one replaces it.
+    ///
+    /// Registration is thread-safe, and may happen while sessions execute
+    /// calls on other threads. A call that is already executing may or may
+    /// not observe the new query.
     ///
     /// [host `query`]: HostQuery
     pub fn register_host_query<Q, S>(&mut self, name: S, query: Q)
@@ -161,8 +286,103 @@ impl VM {
         self.host_queries.insert(name, query);
     }
 
+    /// Registers a [fallible host `query`] with the given `name`.
+    ///
+    /// Works as [`register_host_query`], except that the query may fail the
+    /// call performing it by returning an error - such as when its input is
+    /// malformed.
+    ///
+    /// [fallible host `query`]: FallibleHostQuery
+    /// [`register_host_query`]: VM::register_host_query
+    pub fn register_fallible_host_query<Q, S>(&mut self, name: S, query: Q)
+    where
+        Q: 'static + FallibleHostQuery,
+        S: Into<Cow<'static, str>>,
+    {
+        self.host_queries.insert_fallible(name, query);
+    }
+
+    /// Sets the [`policy`] deciding whether contracts may be deployed.
+    ///
+    /// The policy is consulted by [`Session::deploy`] before anything is
+    /// written, and a deploy it rejects fails with the error it returns. By
+    /// default all deploys are allowed. Like host queries, the policy is
+    /// shared with all sessions spawned by the `VM`, and setting it replaces
+    /// any previous one.
+    ///
+    /// [`policy`]: DeployPolicy
+    pub fn set_deploy_policy<P>(&mut self, policy: P)
+    where
+        P: 'static + DeployPolicy,
+    {
+        self.deploy_policy.set(policy);
+    }
+
+    /// Makes the `"time"` host query available to contracts, always returning
+    /// the given fixed `time`.
+    ///
+    /// This is meant as a convenience for tests, allowing contracts to observe
+    /// a deterministic time without any per-session plumbing. Production
+    /// deployments should instead pass the time to contracts as session
+    /// metadata.
+    #[cfg(feature = "test-utils")]
+    pub fn with_fixed_time(mut self, time: u64) -> Self {
+        let time_bytes = rkyv::to_bytes::<_, 8>(&time)
+            .expect("Serializing a `u64` should succeed")
+            .to_vec();
+
+        self.register_host_query(TIME_QUERY, move |buf: &mut [u8], _: u32| {
+            buf[..time_bytes.len()].copy_from_slice(&time_bytes);
+            time_bytes.len() as u32
+        });
+
+        self
+    }
+
+    /// Set whether sessions zero the contract memories they modified when
+    /// dropped without being committed.
+    ///
+    /// This prevents sensitive data produced during a session from lingering
+    /// in RAM after the session ends, at the cost of some performance. It is
+    /// disabled by default, and applies to any session spawned *after* this
+    /// was called.
+    pub fn zero_memories_on_drop(&mut self, enabled: bool) {
+        self.store.set_zero_memories(enabled);
+    }
+
+    /// Set the maximum number of live sessions that may use the same commit
+    /// as their base.
+    ///
+    /// Spawning a session on a commit already in use by the maximum number of
+    /// sessions fails, protecting against a caller holding an unbounded
+    /// number of sessions and preventing the commit from ever being deleted.
+    /// Forks and snapshots of a session hold its base commit too, so taking
+    /// them fails likewise once the limit is reached. Exports and other
+    /// internal uses of a commit count towards the limit.
+    /// By default the number of sessions is unbounded, and the limit applies
+    /// to sessions spawned *after* this was called.
+    pub fn set_max_sessions_per_commit(&mut self, max_sessions: usize) {
+        self.store.set_max_sessions_per_commit(max_sessions);
+    }
+
+    /// Set the maximum number of deserialized contract modules kept in memory
+    /// and shared between sessions.
+    ///
+    /// Loading a contract from a commit normally deserializes its compiled
+    /// code, which is slow for large contracts. With a cache, contracts
+    /// with the same bytecode are deserialized only once, and the least
+    /// recently used modules are evicted when the cache is full. The cache
+    /// is disabled by default, and setting its size to zero disables it again.
+    pub fn set_module_cache_size(&mut self, size: usize) {
+        self.store.set_module_cache_size(size);
+    }
+
     /// Spawn a [`Session`].
     ///
+    /// A session spawned from a base commit works on its own copy of the
+    /// state, so it can be used to branch off a commit: changes made in it -
+    /// and the commit written from it - never affect the base commit.
+    ///
     /// # Errors
     /// If base commit is provided but does not exist.
     ///
@@ -183,15 +403,67 @@ impl VM {
             self.engine.clone(),
             contract_session,
             self.host_queries.clone(),
+            self.deploy_policy.clone(),
             data,
         ))
     }
 
+    /// Spawn a [`Session`] branching off the given `base` commit.
+    ///
+    /// This is the same as calling [`session`] with only the base set. Commits
+    /// are content-addressed by their root, so a branch can't be a copy of its
+    /// base under another name: it is written as a commit of its own once it
+    /// is committed with changes, and the base is never affected by it.
+    ///
+    /// # Errors
+    /// If the base commit does not exist.
+    ///
+    /// [`Session`]: Session
+    /// [`session`]: VM::session
+    pub fn branch_session(&self, base: [u8; 32]) -> Result<Session, Error> {
+        self.session(SessionData::builder().base(base))
+    }
+
+    /// Spawn a read-only [`Session`], for querying a state without the risk of
+    /// modifying it.
+    ///
+    /// Contracts can be called in a read-only session, but any changes they
+    /// make to the state are discarded at the end of each call. Deploying
+    /// contracts, and committing the session, fail with
+    /// [`Error::ReadOnlySession`].
+    ///
+    /// # Errors
+    /// If base commit is provided but does not exist.
+    ///
+    /// [`Session`]: Session
+    pub fn read_only_session(
+        &self,
+        data: impl Into<SessionData>,
+    ) -> Result<Session, Error> {
+        let mut session = self.session(data)?;
+        session.set_read_only();
+        Ok(session)
+    }
+
     /// Return all existing commits.
     pub fn commits(&self) -> Vec<[u8; 32]> {
         self.store.commits().into_iter().map(Into::into).collect()
     }
 
+    /// Return all existing commits, oldest first.
+    ///
+    /// Commits already on disk when the VM was instantiated come first,
+    /// ordered by the creation time recorded alongside them - or, for commits
+    /// written without one, the modification time of their index file.
+    /// Commits created since are returned in the order they were made.
+    pub fn commits_chronological(&self) -> Vec<[u8; 32]> {
+        self.store
+            .commits_chronological()
+            .into_iter()
+            .map(Into::into)
+            .collect()
+    }
+
     /// Deletes the given commit from disk.
     pub fn delete_commit(&self, root: [u8; 32]) -> Result<(), Error> {
         self.store
@@ -199,6 +471,231 @@ impl VM {
             .map_err(|err| PersistenceError(Arc::new(err)))
     }
 
+    /// Deletes all commits from disk, except for the ones to `keep`,
+    /// returning the roots of the deleted commits.
+    ///
+    /// Commits in use by a session are deleted once all sessions using them
+    /// have dropped.
+    pub fn gc(&self, keep: &[[u8; 32]]) -> Result<Vec<[u8; 32]>, Error> {
+        let keep: Vec<_> = keep.iter().copied().map(Into::into).collect();
+        self.store
+            .gc(&keep)
+            .map(|roots| roots.into_iter().map(Into::into).collect())
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Returns information about the given commit, such as the number of
+    /// contracts in it and its size on disk, or `None` if the commit doesn't
+    /// exist.
+    ///
+    /// This allows for introspecting a commit without spawning a session.
+    pub fn commit_info(
+        &self,
+        root: [u8; 32],
+    ) -> Result<Option<CommitInfo>, Error> {
+        self.store
+            .commit_info(root.into())
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Returns the time at which the given commit was written, or `None` if
+    /// the commit doesn't exist or was written without a timestamp.
+    ///
+    /// This allows for sorting commits by recency, and for aging them.
+    pub fn commit_timestamp(
+        &self,
+        root: [u8; 32],
+    ) -> Result<Option<SystemTime>, Error> {
+        self.store
+            .commit_timestamp(root.into())
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Returns the commit the given commit was made on top of, or `None` if
+    /// it was made without a base, or doesn't exist.
+    ///
+    /// Commits written by versions of the store predating recorded parents
+    /// don't have one.
+    pub fn commit_parents(
+        &self,
+        root: [u8; 32],
+    ) -> Result<Option<[u8; 32]>, Error> {
+        self.store
+            .commit_parents(root.into())
+            .map(|parent| parent.map(Into::into))
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Returns the chain of commits leading to the given commit, following
+    /// their parents back to the first one available, oldest first.
+    ///
+    /// The chain ends with the given commit, and is empty if it doesn't
+    /// exist. If the parents form a cycle, the chain starts at the commit
+    /// whose parent is already in it.
+    pub fn commit_chain(&self, root: [u8; 32]) -> Result<Vec<[u8; 32]>, Error> {
+        self.store
+            .commit_chain(root.into())
+            .map(|chain| chain.into_iter().map(Into::into).collect())
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Returns the contracts added, removed, and changed between the `from` and
+    /// `to` commits.
+    ///
+    /// # Errors
+    /// If either commit doesn't exist.
+    pub fn diff_commits(
+        &self,
+        from: [u8; 32],
+        to: [u8; 32],
+    ) -> Result<CommitDelta, Error> {
+        self.store
+            .diff_commits(from.into(), to.into())
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Instantiates every contract in the commit with the given `root`,
+    /// reporting whether each of them can be run.
+    ///
+    /// See [`Session::instantiate_all`].
+    ///
+    /// # Errors
+    /// If the commit doesn't exist.
+    pub fn validate_commit(
+        &self,
+        root: [u8; 32],
+    ) -> Result<Vec<(ContractId, Result<(), Error>)>, Error> {
+        let mut session =
+            self.read_only_session(SessionData::builder().base(root))?;
+        Ok(session.instantiate_all())
+    }
+
+    /// Returns the bytecode and compiled code of the given `contract` in the
+    /// commit with the given `root`, or `None` if the contract doesn't exist
+    /// in it.
+    ///
+    /// The contract's memory is not loaded, making this much cheaper than
+    /// spawning a session for inspecting the code of a contract.
+    ///
+    /// # Errors
+    /// If the commit doesn't exist, or the files can't be read.
+    pub fn contract_code(
+        &self,
+        root: [u8; 32],
+        contract: ContractId,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        self.store
+            .contract_code(root.into(), contract)
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Verifies the files of the commit with the given `root` on disk,
+    /// returning `false` if they don't produce its root.
+    ///
+    /// This recomputes the root from the memory pages of every contract in the
+    /// commit, and is meant for periodic integrity scans. Since the root
+    /// doesn't commit to contract bytecode, bytecode files are only checked to
+    /// be readable.
+    ///
+    /// # Errors
+    /// If the commit doesn't exist, or its files can't be read.
+    pub fn verify_commit(&self, root: [u8; 32]) -> Result<bool, Error> {
+        self.store
+            .verify_commit(root.into())
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Writes the given commit to `out` as a single self-contained archive,
+    /// allowing it to be moved to another machine.
+    ///
+    /// The archive contains the commit's index, together with the bytecode,
+    /// compiled code, metadata, and memory pages of all its contracts.
+    ///
+    /// # Errors
+    /// If the commit doesn't exist, or writing to `out` fails.
+    pub fn export_commit<W: Write>(
+        &self,
+        root: [u8; 32],
+        out: W,
+    ) -> Result<(), Error> {
+        self.store
+            .export_commit(root.into(), out)
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Reads a commit archive produced by [`export_commit`] from `data`,
+    /// adding the commit to this `VM` and returning its root.
+    ///
+    /// The objectcode in the archive is not trusted, and is compiled anew from
+    /// the bytecode of each contract.
+    ///
+    /// # Errors
+    /// If the archive is malformed, or its contents don't match its root.
+    ///
+    /// [`export_commit`]: VM::export_commit
+    pub fn import_commit<R: Read>(&self, data: R) -> Result<[u8; 32], Error> {
+        self.store
+            .import_commit(data)
+            .map(Into::into)
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Writes all commits of this `VM` to `out` as a single archive, allowing
+    /// a new node to be bootstrapped with the whole state history.
+    ///
+    /// Files shared between commits are only written once.
+    ///
+    /// # Errors
+    /// If reading the commits or writing to `out` fails.
+    pub fn export_all<W: Write>(&self, out: W) -> Result<(), Error> {
+        self.store
+            .export_all(out)
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Reads an archive produced by [`export_all`] from `data`, adding all its
+    /// commits to this `VM` and returning their roots.
+    ///
+    /// As in [`import_commit`], the objectcode in the archive is not trusted,
+    /// and is compiled anew from the bytecode of each contract.
+    ///
+    /// # Errors
+    /// If the archive is malformed, or the contents of any of its commits don't
+    /// match their root.
+    ///
+    /// [`export_all`]: VM::export_all
+    /// [`import_commit`]: VM::import_commit
+    pub fn import_all<R: Read>(&self, data: R) -> Result<Vec<[u8; 32]>, Error> {
+        self.store
+            .import_all(data)
+            .map(|roots| roots.into_iter().map(Into::into).collect())
+            .map_err(|err| PersistenceError(Arc::new(err)))
+    }
+
+    /// Returns a report on the contract memories currently loaded by live
+    /// sessions, giving their number and total logical length.
+    ///
+    /// This is useful for capacity planning, and to avoid running out of
+    /// memory when running many sessions concurrently. The report does not
+    /// cover the bytes actually resident in physical memory, nor the memory
+    /// used by instances or by the modules in the [`module_cache_report`].
+    ///
+    /// [`module_cache_report`]: VM::module_cache_report
+    pub fn memory_report(&self) -> MemoryReport {
+        self.store.memory_report()
+    }
+
+    /// Returns a report on the use of the cache of deserialized contract
+    /// modules.
+    ///
+    /// The cache is only used once its size is set using
+    /// [`set_module_cache_size`].
+    ///
+    /// [`set_module_cache_size`]: VM::set_module_cache_size
+    pub fn module_cache_report(&self) -> ModuleCacheReport {
+        self.store.module_cache_report()
+    }
+
     /// Return the root directory of the virtual machine.
     ///
     /// This is either the directory passed in by using [`new`], or the
@@ -210,20 +707,56 @@ impl VM {
         self.store.root_dir()
     }
 
+    /// Returns the format version of the store the VM is using.
+    ///
+    /// This is always [`STORE_FORMAT_VERSION`], since stores of any other
+    /// version are refused when opened.
+    ///
+    /// [`STORE_FORMAT_VERSION`]: crate::STORE_FORMAT_VERSION
+    pub fn store_format_version(&self) -> u32 {
+        self.store.format_version()
+    }
+
+    /// Returns the store used by the virtual machine.
+    pub(crate) fn store(&self) -> &ContractStore {
+        &self.store
+    }
+
     /// Returns a reference to the synchronization thread.
     pub fn sync_thread(&self) -> &thread::Thread {
         self.store.sync_loop()
     }
+
+    /// Returns whether the synchronization thread is still alive.
+    ///
+    /// Failures in writing individual commits - including panics - are
+    /// reported to the caller of [`Session::commit`], leaving the thread
+    /// running. If this returns `false`, no further commits can be made, and
+    /// the `VM` should be restarted.
+    pub fn is_healthy(&self) -> bool {
+        self.store.is_healthy()
+    }
 }
 
+/// The number of threads used to read the commits in a directory, when not
+/// specified.
+fn default_parallelism() -> NonZeroUsize {
+    thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+}
+
+/// The host queries registered with a `VM`.
+///
+/// Clones share the same queries, meaning that a query inserted into one is
+/// visible to all others.
 #[derive(Default, Clone)]
 pub struct HostQueries {
-    map: BTreeMap<Cow<'static, str>, Arc<dyn HostQuery>>,
+    map: Arc<RwLock<BTreeMap<Cow<'static, str>, Arc<dyn FallibleHostQuery>>>>,
 }
 
 impl Debug for HostQueries {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_list().entries(self.map.keys()).finish()
+        let map = self.map.read().expect("Host queries lock is not poisoned");
+        f.debug_list().entries(map.keys()).finish()
     }
 }
 
@@ -233,11 +766,37 @@ impl HostQueries {
         Q: 'static + HostQuery,
         S: Into<Cow<'static, str>>,
     {
-        self.map.insert(name.into(), Arc::new(query));
+        self.insert_fallible(name, move |buf: &mut [u8], len: u32| {
+            Ok(query(buf, len))
+        });
     }
 
-    pub fn call(&self, name: &str, buf: &mut [u8], len: u32) -> Option<u32> {
-        self.map.get(name).map(|host_query| host_query(buf, len))
+    pub fn insert_fallible<Q, S>(&mut self, name: S, query: Q)
+    where
+        Q: 'static + FallibleHostQuery,
+        S: Into<Cow<'static, str>>,
+    {
+        self.map
+            .write()
+            .expect("Host queries lock is not poisoned")
+            .insert(name.into(), Arc::new(query));
+    }
+
+    pub fn call(
+        &self,
+        name: &str,
+        buf: &mut [u8],
+        len: u32,
+    ) -> Option<Result<u32, Error>> {
+        // The query is cloned out of the map so the lock isn't held while it
+        // executes, allowing for queries to be registered concurrently.
+        let host_query = self
+            .map
+            .read()
+            .expect("Host queries lock is not poisoned")
+            .get(name)
+            .cloned();
+        host_query.map(|host_query| host_query(buf, len))
     }
 }
 
@@ -250,3 +809,94 @@ impl HostQueries {
 /// length written.
 pub trait HostQuery: Send + Sync + Fn(&mut [u8], u32) -> u32 {}
 impl<F> HostQuery for F where F: Send + Sync + Fn(&mut [u8], u32) -> u32 {}
+
+/// A query executable on the host, which may fail.
+///
+/// Works as a [`HostQuery`], except that the query may return an error
+/// instead of the length of its return. The error fails the contract call
+/// that performed the query, and is passed on to the calling contract as a
+/// [`ContractError`] if there is one.
+///
+/// [`ContractError`]: crate::ContractError
+pub trait FallibleHostQuery:
+    Send + Sync + Fn(&mut [u8], u32) -> Result<u32, Error>
+{
+}
+impl<F> FallibleHostQuery for F where
+    F: Send + Sync + Fn(&mut [u8], u32) -> Result<u32, Error>
+{
+}
+
+/// A request to deploy a contract, passed to the [deploy policy] for approval.
+///
+/// [deploy policy]: VM::set_deploy_policy
+#[derive(Debug, Clone, Copy)]
+pub struct DeployRequest<'a> {
+    /// The bytecode of the contract.
+    pub bytecode: &'a [u8],
+    /// The ID the contract would be deployed with.
+    pub contract_id: ContractId,
+    /// The owner of the contract.
+    pub owner: &'a [u8],
+}
+
+/// A policy deciding whether a contract may be deployed.
+///
+/// Returning an error rejects the deploy, and the error is returned to the
+/// caller of [`Session::deploy`].
+pub trait DeployPolicy:
+    Send + Sync + Fn(&DeployRequest) -> Result<(), Error>
+{
+}
+impl<F> DeployPolicy for F where
+    F: Send + Sync + Fn(&DeployRequest) -> Result<(), Error>
+{
+}
+
+/// The deploy policy set on a `VM`.
+///
+/// Clones share the same policy, meaning that a policy set on one is used by
+/// all others.
+#[derive(Default, Clone)]
+pub struct SharedDeployPolicy {
+    policy: Arc<RwLock<Option<Arc<dyn DeployPolicy>>>>,
+}
+
+impl Debug for SharedDeployPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let policy = self
+            .policy
+            .read()
+            .expect("Deploy policy lock is not poisoned");
+        f.debug_struct("SharedDeployPolicy")
+            .field("is_set", &policy.is_some())
+            .finish()
+    }
+}
+
+impl SharedDeployPolicy {
+    pub fn set<P>(&mut self, policy: P)
+    where
+        P: 'static + DeployPolicy,
+    {
+        *self
+            .policy
+            .write()
+            .expect("Deploy policy lock is not poisoned") =
+            Some(Arc::new(policy));
+    }
+
+    /// Checks the given `request` against the policy, allowing it if no
+    /// policy is set.
+    pub fn check(&self, request: &DeployRequest) -> Result<(), Error> {
+        let policy = self
+            .policy
+            .read()
+            .expect("Deploy policy lock is not poisoned")
+            .clone();
+        match policy {
+            Some(policy) => policy(request),
+            None => Ok(()),
+        }
+    }
+}
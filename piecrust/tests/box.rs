@@ -63,6 +63,29 @@ pub fn box_set_get_raw() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+pub fn box_call_and_call_raw_agree() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    session.call::<i16, ()>(id, "set", &0x11, LIMIT)?;
+
+    let receipt = session.call::<_, Option<i16>>(id, "get", &(), LIMIT)?;
+    let raw_receipt = session.call_raw(id, "get", vec![], LIMIT)?;
+
+    assert_eq!(deserialize_value(&raw_receipt.data)?, receipt.data);
+    assert_eq!(raw_receipt.gas_spent, receipt.gas_spent);
+
+    Ok(())
+}
+
 fn deserialize_value(bytes: &[u8]) -> Result<Option<i16>, Error> {
     let ta = check_archived_root::<Option<i16>>(bytes)?;
     let ret = ta.deserialize(&mut Infallible).expect("Infallible");
@@ -74,3 +97,84 @@ fn serialize_value(value: i16) -> Result<Vec<u8>, Error> {
         .map_err(|_| Error::ValidationError)?
         .to_vec())
 }
+
+#[test]
+pub fn box_state_hash() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let hash_before = session
+        .call::<_, [u8; 32]>(id, "state_hash", &(), LIMIT)?
+        .data;
+
+    session.call::<i16, ()>(id, "set", &0x11, LIMIT)?;
+
+    let hash_after = session
+        .call::<_, [u8; 32]>(id, "state_hash", &(), LIMIT)?
+        .data;
+
+    assert_ne!(
+        hash_before, hash_after,
+        "the hash should change with the memory"
+    );
+
+    // hashing is charged by the size of the memory
+    let hash_cost =
+        session.memory_len(id)?.expect("the contract should exist") as u64 / 64;
+
+    let receipt = session.call::<_, [u8; 32]>(id, "state_hash", &(), LIMIT)?;
+    assert!(receipt.gas_spent >= hash_cost);
+
+    assert!(
+        session
+            .call::<_, [u8; 32]>(id, "state_hash", &(), hash_cost)
+            .is_err(),
+        "hashing should fail without enough gas for the whole memory"
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn box_max_arg_len() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let value_bytes = serialize_value(0x11)?;
+
+    session.set_max_arg_len(value_bytes.len() - 1);
+
+    match session.call_raw(id, "set", value_bytes.clone(), LIMIT) {
+        Err(Error::ArgumentBufferOverflow { len, max_len }) => {
+            assert_eq!(len, value_bytes.len());
+            assert_eq!(max_len, value_bytes.len() - 1);
+        }
+        other => panic!("Expected the argument to be rejected, got {other:?}"),
+    }
+
+    // arguments within the limit are still accepted
+    let value = session.call::<_, Option<i16>>(id, "get", &(), LIMIT)?.data;
+    assert_eq!(value, None);
+
+    session.set_max_arg_len(value_bytes.len());
+    session.call_raw(id, "set", value_bytes, LIMIT)?;
+
+    let value = session.call::<_, Option<i16>>(id, "get", &(), LIMIT)?.data;
+    assert_eq!(value, Some(0x11));
+
+    Ok(())
+}
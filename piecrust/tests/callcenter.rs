@@ -260,3 +260,191 @@ pub fn cc_self_id() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+pub fn cc_last_call_touched() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let center_id = session.deploy(
+        contract_bytecode!("callcenter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    // a simple query only touches the called contract
+    session.call::<_, i64>(counter_id, "read_value", &(), LIMIT)?;
+    let touched = session.last_call_touched();
+    assert_eq!(touched.len(), 1);
+    assert!(touched.contains(&counter_id));
+
+    // a composing query touches every contract in the chain
+    session.call::<_, i64>(center_id, "query_counter", &counter_id, LIMIT)?;
+    let touched = session.last_call_touched();
+    assert_eq!(touched.len(), 2);
+    assert!(touched.contains(&counter_id));
+    assert!(touched.contains(&center_id));
+
+    Ok(())
+}
+
+#[test]
+pub fn cc_deterministic_execution() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let center_id = session.deploy(
+        contract_bytecode!("callcenter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let base = session.commit()?;
+
+    // execute the same sequence of calls on the same state a few times, with
+    // contracts instantiated in different orders across calls
+    let execute = || -> Result<_, Error> {
+        let mut session = vm.session(SessionData::builder().base(base))?;
+
+        session.call::<_, ()>(
+            center_id,
+            "increment_counter",
+            &counter_id,
+            LIMIT,
+        )?;
+        session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+        session.call::<_, i64>(
+            center_id,
+            "query_counter",
+            &counter_id,
+            LIMIT,
+        )?;
+
+        let mut memories = Vec::new();
+        for contract_id in [counter_id, center_id] {
+            let pages: Vec<(usize, Vec<u8>)> = session
+                .memory_pages(contract_id)
+                .expect("contract should be loaded")
+                .map(|(index, page, _)| (index, page.to_vec()))
+                .collect();
+            memories.push(pages);
+        }
+
+        Ok((session.root(), memories))
+    };
+
+    let (root, memories) = execute()?;
+    for _ in 0..3 {
+        let (other_root, other_memories) = execute()?;
+        assert_eq!(root, other_root, "roots should be identical");
+        assert!(
+            memories == other_memories,
+            "memories should be byte-identical"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn cc_max_call_depth() -> Result<(), Error> {
+    const MAX_DEPTH: usize = 16;
+
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.set_max_call_depth(MAX_DEPTH);
+
+    let center_id = session.deploy(
+        contract_bytecode!("callcenter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    // the top-level call plus 15 nested calls fit in the call stack
+    let calls: u32 = session
+        .call(center_id, "recurse", &(MAX_DEPTH as u32 - 1), LIMIT)?
+        .data;
+    assert_eq!(calls, MAX_DEPTH as u32 - 1);
+    assert_eq!(session.call_stack_depth(), 0);
+
+    // one more nested call overflows the call stack
+    let err = session
+        .call::<_, u32>(center_id, "recurse", &(MAX_DEPTH as u32), LIMIT)
+        .expect_err("the call stack should overflow");
+    assert!(
+        matches!(err, Error::CallStackOverflow(MAX_DEPTH)),
+        "expected a call stack overflow, got {err:?}"
+    );
+
+    // the session is still usable after an overflow
+    let calls: u32 = session.call(center_id, "recurse", &1u32, LIMIT)?.data;
+    assert_eq!(calls, 1);
+
+    Ok(())
+}
+
+#[test]
+pub fn cc_caller_stack() -> Result<(), Error> {
+    const CENTER_A: ContractId = ContractId::from_bytes([1; 32]);
+    const CENTER_B: ContractId = ContractId::from_bytes([2; 32]);
+    const CENTER_C: ContractId = ContractId::from_bytes([3; 32]);
+
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    for center_id in [CENTER_A, CENTER_B, CENTER_C] {
+        session.deploy(
+            contract_bytecode!("callcenter"),
+            ContractData::builder().owner(OWNER).contract_id(center_id),
+            LIMIT,
+        )?;
+    }
+
+    let stack: Vec<ContractId> = session
+        .call(CENTER_C, "return_caller_stack", &(), LIMIT)?
+        .data;
+    assert!(stack.is_empty(), "There should be no callers");
+
+    // A calls B, which calls C
+    let c_arg = rkyv::to_bytes::<_, 16>(&(
+        CENTER_C,
+        String::from("return_caller_stack"),
+        Vec::<u8>::new(),
+    ))
+    .expect("Serialization should succeed")
+    .to_vec();
+
+    let b_res = session
+        .call::<_, Result<Vec<u8>, ContractError>>(
+            CENTER_A,
+            "delegate_query",
+            &(CENTER_B, String::from("delegate_query"), c_arg),
+            LIMIT,
+        )?
+        .data
+        .expect("ICC should succeed");
+
+    let c_res: Result<Vec<u8>, ContractError> =
+        rkyv::from_bytes(&b_res).expect("Deserialization to succeed");
+    let c_res = c_res.expect("ICC should succeed");
+
+    let stack: Vec<ContractId> =
+        rkyv::from_bytes(&c_res).expect("Deserialization to succeed");
+    assert_eq!(stack, vec![CENTER_B, CENTER_A]);
+
+    Ok(())
+}
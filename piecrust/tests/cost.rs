@@ -0,0 +1,123 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use piecrust::{
+    contract_bytecode, estimate_opcode_cost, ContractData, Error, SessionData,
+    VM,
+};
+
+const OWNER: [u8; 32] = [0u8; 32];
+const LIMIT: u64 = 1_000_000;
+
+/// A module exporting a function `f` adding two constants:
+///
+/// ```wat
+/// (module
+///   (func (export "f") (result i32)
+///     i32.const 1
+///     i32.const 2
+///     i32.add))
+/// ```
+const ADD_MODULE: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // export section
+    0x0a, 0x09, 0x01, 0x07, 0x00, 0x41, 0x01, 0x41, 0x02, 0x6a,
+    0x0b, // code section
+];
+
+/// A contract exporting a function `f` adding two constants, following the
+/// calling convention of the VM:
+///
+/// ```wat
+/// (module
+///   (memory (export "memory") 2)
+///   (global (export "A") i32 (i32.const 0))
+///   (func (export "f") (param i32) (result i32)
+///     i32.const 1
+///     i32.const 2
+///     i32.add))
+/// ```
+const ADD_CONTRACT: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x05, 0x03, 0x01, 0x00, 0x02, // memory section
+    0x06, 0x06, 0x01, 0x7f, 0x00, 0x41, 0x00, 0x0b, // global section
+    0x07, 0x12, 0x03, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00,
+    0x01, 0x41, 0x03, 0x00, 0x01, 0x66, 0x00, 0x00, // export section
+    0x0a, 0x09, 0x01, 0x07, 0x00, 0x41, 0x01, 0x41, 0x02, 0x6a,
+    0x0b, // code section
+];
+
+#[test]
+fn estimate_known_function() -> Result<(), Error> {
+    // two constants and an addition, with the `end` being free
+    assert_eq!(estimate_opcode_cost(ADD_MODULE, "f")?, 3);
+
+    assert!(matches!(
+        estimate_opcode_cost(ADD_MODULE, "g"),
+        Err(Error::InvalidFunction(_))
+    ));
+
+    assert!(matches!(
+        estimate_opcode_cost(&ADD_MODULE[..12], "f"),
+        Err(Error::DecodingError(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn estimate_contract_function() -> Result<(), Error> {
+    let bytecode = contract_bytecode!("counter");
+
+    let estimate = estimate_opcode_cost(bytecode, "read_value")?;
+    assert!(estimate > 0, "the function should cost some gas");
+
+    let vm = VM::ephemeral()?;
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        bytecode,
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let receipt = session.call::<_, i64>(id, "read_value", &(), LIMIT)?;
+
+    // The functions called by `read_value` are counted as a single operator
+    // by the estimate, while their full cost is spent at runtime.
+    assert!(
+        estimate <= receipt.gas_spent,
+        "the estimate {estimate} should not exceed the gas spent {}",
+        receipt.gas_spent
+    );
+
+    Ok(())
+}
+
+#[test]
+fn estimate_matches_straight_line_function() -> Result<(), Error> {
+    let estimate = estimate_opcode_cost(ADD_CONTRACT, "f")?;
+    assert_eq!(estimate, 3);
+
+    let vm = VM::ephemeral()?;
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        ADD_CONTRACT,
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let receipt = session.call_raw(id, "f", vec![], LIMIT)?;
+
+    // Without loops, branches, or calls, every operator is executed exactly
+    // once, and the estimate is exact.
+    assert_eq!(estimate, receipt.gas_spent);
+
+    Ok(())
+}
@@ -4,7 +4,9 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
-use piecrust::{contract_bytecode, ContractData, Error, SessionData, VM};
+use piecrust::{
+    contract_bytecode, ContractData, Error, Session, SessionData, VM,
+};
 
 const OWNER: [u8; 32] = [0u8; 32];
 const LIMIT: u64 = 1_000_000;
@@ -57,39 +59,29 @@ fn counter_read_write_simple() -> Result<(), Error> {
 }
 
 #[test]
-fn call_through_c() -> Result<(), Error> {
+fn increment_panic() -> Result<(), Error> {
     let vm = VM::ephemeral()?;
 
     let mut session = vm.session(SessionData::builder())?;
 
     let counter_id = session.deploy(
-        contract_bytecode!("counter"),
-        ContractData::builder().owner(OWNER),
-        LIMIT,
-    )?;
-    let c_example_id = session.deploy(
-        contract_bytecode!("c_example"),
+        contract_bytecode!("fallible_counter"),
         ContractData::builder().owner(OWNER),
         LIMIT,
     )?;
 
-    assert_eq!(
-        session
-            .call::<_, i64>(
-                c_example_id,
-                "increment_and_read",
-                &counter_id,
-                LIMIT,
-            )?
-            .data,
-        0xfd
-    );
+    match session.call::<_, ()>(counter_id, "increment", &true, LIMIT) {
+        Err(Error::Panic(panic_msg)) => {
+            assert_eq!(panic_msg, String::from("Incremental panic"));
+        }
+        _ => panic!("Expected a panic error"),
+    }
 
     Ok(())
 }
 
 #[test]
-fn increment_panic() -> Result<(), Error> {
+fn increment_panic_leaves_state_untouched() -> Result<(), Error> {
     let vm = VM::ephemeral()?;
 
     let mut session = vm.session(SessionData::builder())?;
@@ -99,13 +91,32 @@ fn increment_panic() -> Result<(), Error> {
         ContractData::builder().owner(OWNER),
         LIMIT,
     )?;
+    session.call::<_, ()>(counter_id, "increment", &false, LIMIT)?;
 
-    match session.call::<_, ()>(counter_id, "increment", &true, LIMIT) {
-        Err(Error::Panic(panic_msg)) => {
-            assert_eq!(panic_msg, String::from("Incremental panic"));
-        }
-        _ => panic!("Expected a panic error"),
-    }
+    let pages = |session: &Session| {
+        session
+            .memory_pages(counter_id)
+            .expect("contract should exist")
+            .map(|(index, page, _)| (index, page.to_vec()))
+            .collect::<Vec<_>>()
+    };
+
+    let root = session.root();
+    let before = pages(&session);
+
+    session
+        .call::<_, ()>(counter_id, "increment", &true, LIMIT)
+        .expect_err("the call should panic");
+
+    assert_eq!(session.root(), root);
+    assert_eq!(pages(&session), before, "memory should be byte-identical");
+
+    assert_eq!(
+        session
+            .call::<_, i64>(counter_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfd
+    );
 
     Ok(())
 }
@@ -0,0 +1,42 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use dusk_wasmtime::Store;
+use piecrust::{contract_bytecode, ContractData, Env, Error, SessionData, VM};
+
+const OWNER: [u8; 32] = [0u8; 32];
+const LIMIT: u64 = 1_000_000;
+
+#[test]
+fn wasmtime_store() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    // fuel is enabled in the engine used by the store
+    let fuel = session
+        .with_wasmtime_store(id, |store: &Store<Env>| store.get_fuel())?;
+    assert!(fuel.is_ok(), "fuel should be enabled");
+
+    session.with_wasmtime_store_mut(id, |store| {
+        store.set_fuel(42).expect("fuel should be enabled");
+        assert_eq!(store.get_fuel().expect("fuel should be enabled"), 42);
+    })?;
+
+    // the session is still usable after accessing the store
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfd
+    );
+
+    Ok(())
+}
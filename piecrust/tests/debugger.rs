@@ -4,7 +4,9 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
-use piecrust::{contract_bytecode, ContractData, Error, SessionData, VM};
+use piecrust::{
+    contract_bytecode, ContractData, DebugLevel, Error, SessionData, VM,
+};
 
 const OWNER: [u8; 32] = [0u8; 32];
 const LIMIT: u64 = 1_000_000;
@@ -23,9 +25,50 @@ pub fn debug() -> Result<(), Error> {
 
     session.call::<_, ()>(id, "debug", &String::from("Hello world"), LIMIT)?;
 
+    assert_eq!(
+        session.debug_strings(),
+        [String::from("What a string! Hello world")]
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn debug_records() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("debugger"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    session.call::<_, ()>(id, "debug", &String::from("Hello world"), LIMIT)?;
+    session.call::<_, ()>(
+        id,
+        "log",
+        &(DebugLevel::Warn as u32, String::from("Careful")),
+        LIMIT,
+    )?;
+
     session.with_debug(|dbg| {
-        assert_eq!(dbg, &[String::from("What a string! Hello world")])
+        assert_eq!(dbg.len(), 2);
+
+        assert_eq!(dbg[0].contract, id);
+        assert_eq!(dbg[0].level, DebugLevel::Debug);
+        assert_eq!(dbg[0].message, "What a string! Hello world");
+
+        assert_eq!(dbg[1].contract, id);
+        assert_eq!(dbg[1].level, DebugLevel::Warn);
+        assert_eq!(dbg[1].message, "Careful");
+    });
+
+    session.with_debug_mut(|dbg| {
+        dbg.retain(|record| record.level >= DebugLevel::Warn)
     });
+    assert_eq!(session.debug_strings(), [String::from("Careful")]);
 
     Ok(())
 }
@@ -4,7 +4,9 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
-use piecrust::{contract_bytecode, ContractData, Error, SessionData, VM};
+use piecrust::{
+    contract_bytecode, ContractData, DeployRequest, Error, SessionData, VM,
+};
 use piecrust_uplink::ContractId;
 
 const OWNER: [u8; 32] = [0u8; 32];
@@ -44,3 +46,84 @@ pub fn deploy_with_id() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+pub fn deploy_policy() -> Result<(), Error> {
+    let mut vm = VM::ephemeral()?;
+
+    let mut bytecodes: [&[u8]; 2] = [
+        contract_bytecode!("counter"),
+        contract_bytecode!("callcenter"),
+    ];
+    bytecodes.sort_by_key(|bytecode| bytecode.len());
+    let [small, large] = bytecodes;
+
+    let max_len = small.len();
+    vm.set_deploy_policy(move |request: &DeployRequest| {
+        if request.bytecode.len() > max_len {
+            return Err(Error::InitalizationError("Bytecode too large".into()));
+        }
+        Ok(())
+    });
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let small_id =
+        session.deploy(small, ContractData::builder().owner(OWNER), LIMIT)?;
+    assert!(session.deployed_contracts().contains(&small_id));
+
+    let large_id = ContractId::from([2u8; 32]);
+    let err = session
+        .deploy(
+            large,
+            ContractData::builder().owner(OWNER).contract_id(large_id),
+            LIMIT,
+        )
+        .expect_err("deploying a large contract should be rejected");
+    assert!(
+        matches!(err, Error::InitalizationError(msg) if msg == "Bytecode too large")
+    );
+    assert!(!session.deployed_contracts().contains(&large_id));
+
+    Ok(())
+}
+
+#[test]
+pub fn deploy_if_absent() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let bytecode = contract_bytecode!("counter");
+    let contract_id = ContractId::from([1u8; 32]);
+    let mut session = vm.session(SessionData::builder())?;
+
+    let deployed = session.deploy_if_absent(
+        bytecode,
+        ContractData::builder()
+            .owner(OWNER)
+            .contract_id(contract_id),
+        LIMIT,
+    )?;
+    assert!(deployed, "The contract should be deployed the first time");
+
+    session.call::<_, ()>(contract_id, "increment", &(), LIMIT)?;
+    let root = session.root();
+
+    let deployed = session.deploy_if_absent(
+        bytecode,
+        ContractData::builder()
+            .owner(OWNER)
+            .contract_id(contract_id),
+        LIMIT,
+    )?;
+    assert!(!deployed, "The contract should not be deployed again");
+    assert_eq!(session.root(), root);
+
+    assert_eq!(
+        session
+            .call::<_, i64>(contract_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfd
+    );
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use piecrust::{decode_contract_map, encode_contract_map, ContractId, Error};
+
+#[test]
+fn contract_map_round_trip() -> Result<(), Error> {
+    let entries = vec![
+        (ContractId::from_bytes([1; 32]), vec![0xaa; 100]),
+        (ContractId::from_bytes([2; 32]), vec![]),
+        (ContractId::from_bytes([3; 32]), vec![0x01, 0x02, 0x03]),
+    ];
+
+    let bytes = encode_contract_map(&entries);
+    let decoded = decode_contract_map(&bytes)?;
+    assert_eq!(decoded, entries);
+
+    let empty: Vec<(ContractId, Vec<u8>)> = vec![];
+    let bytes = encode_contract_map(&empty);
+    assert!(bytes.is_empty());
+    assert_eq!(decode_contract_map(&bytes)?, empty);
+
+    Ok(())
+}
+
+#[test]
+fn contract_map_golden() -> Result<(), Error> {
+    let entries = vec![
+        (ContractId::from_bytes([1; 32]), vec![0xaa, 0xbb]),
+        (ContractId::from_bytes([2; 32]), vec![]),
+    ];
+
+    let mut golden = vec![];
+    golden.extend_from_slice(&[1; 32]);
+    golden.extend_from_slice(&[2, 0, 0, 0]);
+    golden.extend_from_slice(&[0xaa, 0xbb]);
+    golden.extend_from_slice(&[2; 32]);
+    golden.extend_from_slice(&[0, 0, 0, 0]);
+
+    assert_eq!(encode_contract_map(&entries), golden);
+    assert_eq!(decode_contract_map(&golden)?, entries);
+
+    Ok(())
+}
+
+#[test]
+fn contract_map_truncated() {
+    let entries = vec![(ContractId::from_bytes([1; 32]), vec![0xaa, 0xbb])];
+    let bytes = encode_contract_map(&entries);
+
+    for len in 1..bytes.len() {
+        assert!(
+            matches!(
+                decode_contract_map(&bytes[..len]),
+                Err(Error::DecodingError(_))
+            ),
+            "decoding a truncated encoding should fail"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use piecrust::{contract_bytecode, ContractData, Error, SessionData, VM};
+
+const OWNER: [u8; 32] = [0u8; 32];
+const LIMIT: u64 = 1_000_000;
+
+#[test]
+fn estimate_gas() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let estimate = session.estimate_gas(id, "increment", &(), LIMIT)?;
+
+    // estimating should not modify the state
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfc
+    );
+
+    let receipt = session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    assert!(
+        estimate >= receipt.gas_spent,
+        "The estimate should be at least the gas spent"
+    );
+
+    session.call::<_, ()>(id, "increment", &(), estimate)?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfe
+    );
+
+    Ok(())
+}
+
+#[test]
+fn estimate_gas_failing_call() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("fallible_counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let result = session.estimate_gas(id, "increment", &true, LIMIT);
+    assert!(matches!(result, Err(Error::Panic(_))));
+
+    Ok(())
+}
@@ -4,7 +4,10 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
-use piecrust::{contract_bytecode, ContractData, Error, SessionData, VM};
+use piecrust::{
+    contract_bytecode, hash_events, ContractData, ContractId, Error,
+    SessionData, VM,
+};
 
 const OWNER: [u8; 32] = [0u8; 32];
 const LIMIT: u64 = 1_000_000;
@@ -33,8 +36,118 @@ pub fn vm_center_events() -> Result<(), Error> {
         let index = i as usize;
         assert_eq!(events[index].source, eventer_id);
         assert_eq!(events[index].topic, "number");
+        assert_eq!(events[index].version, None);
         assert_eq!(events[index].data, i.to_le_bytes());
     }
 
     Ok(())
 }
+
+#[test]
+pub fn events_for_contract() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let eventer_id = session.deploy(
+        contract_bytecode!("eventer"),
+        ContractData::builder()
+            .owner(OWNER)
+            .contract_id(ContractId::from_bytes([1; 32])),
+        LIMIT,
+    )?;
+    let other_id = session.deploy(
+        contract_bytecode!("eventer"),
+        ContractData::builder()
+            .owner(OWNER)
+            .contract_id(ContractId::from_bytes([2; 32])),
+        LIMIT,
+    )?;
+
+    const EVENT_NUM: u32 = 3;
+
+    let mut receipt = session.call::<_, ()>(
+        eventer_id,
+        "emit_events_and_forward",
+        &(EVENT_NUM, other_id),
+        LIMIT,
+    )?;
+    assert_eq!(receipt.events.len() as u32, 2 * EVENT_NUM);
+
+    let eventer_events = receipt.events_for(eventer_id);
+    assert_eq!(eventer_events.len() as u32, EVENT_NUM);
+    assert!(eventer_events.iter().all(|e| e.source == eventer_id));
+
+    let other_events = receipt.take_events_for(other_id);
+    assert_eq!(other_events.len() as u32, EVENT_NUM);
+    assert!(other_events.iter().all(|e| e.source == other_id));
+
+    assert_eq!(receipt.events.len() as u32, EVENT_NUM);
+    assert!(receipt.events.iter().all(|e| e.source == eventer_id));
+
+    Ok(())
+}
+
+#[test]
+pub fn typed_event() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let eventer_id = session.deploy(
+        contract_bytecode!("eventer"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    const VERSION: u16 = 0x0102;
+    const NUM: u32 = 42;
+
+    let receipt = session.call::<_, ()>(
+        eventer_id,
+        "emit_typed_event",
+        &(VERSION, NUM),
+        LIMIT,
+    )?;
+
+    let events = receipt.events;
+    assert_eq!(events.len(), 1);
+
+    assert_eq!(events[0].source, eventer_id);
+    assert_eq!(events[0].topic, "number");
+    assert_eq!(events[0].version, Some(VERSION));
+    assert_eq!(events[0].data, NUM.to_le_bytes());
+
+    Ok(())
+}
+
+#[test]
+pub fn events_hash() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut hashes = Vec::new();
+
+    for num in [3u32, 3, 4] {
+        let mut session = vm.session(SessionData::builder())?;
+
+        let eventer_id = session.deploy(
+            contract_bytecode!("eventer"),
+            ContractData::builder().owner(OWNER),
+            LIMIT,
+        )?;
+
+        let receipt =
+            session.call::<_, ()>(eventer_id, "emit_events", &num, LIMIT)?;
+        assert_eq!(receipt.events_hash(), hash_events(&receipt.events));
+
+        hashes.push(receipt.events_hash());
+    }
+
+    assert_eq!(hashes[0], hashes[1], "same events should hash the same");
+    assert_ne!(
+        hashes[0], hashes[2],
+        "different events should hash differently"
+    );
+
+    Ok(())
+}
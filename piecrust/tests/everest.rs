@@ -4,7 +4,10 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
-use piecrust::{contract_bytecode, ContractData, Error, SessionData, VM};
+use piecrust::{
+    contract_bytecode, ContractData, ContractId, Error, Session, SessionData,
+    VM,
+};
 
 const OWNER: [u8; 32] = [0u8; 32];
 const LIMIT: u64 = 1_000_000;
@@ -42,3 +45,136 @@ pub fn meta_data_optionality() -> Result<(), Error> {
     assert!(height.is_none());
     Ok(())
 }
+
+#[test]
+pub fn meta_data_enumeration() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let session = vm.session(
+        SessionData::builder()
+            .insert("height", 29_000u64)?
+            .insert("chain_id", 42u8)?
+            .insert("timestamp", 1_700_000_000u64)?,
+    )?;
+
+    assert_eq!(session.meta_keys(), vec!["chain_id", "height", "timestamp"]);
+
+    let entries = session.meta_entries();
+    let names: Vec<_> = entries.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["chain_id", "height", "timestamp"]);
+
+    for (name, value) in entries {
+        assert_eq!(session.meta(&name), Some(value));
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn global_meta_data() -> Result<(), Error> {
+    const HEIGHT: u64 = 29_000u64;
+    const GLOBAL_HEIGHT: u64 = 42_000u64;
+
+    let vm = VM::ephemeral()?;
+    let mut session =
+        vm.session(SessionData::builder().insert("height", HEIGHT)?)?;
+
+    let id_0 = session.deploy(
+        contract_bytecode!("everest"),
+        ContractData::builder()
+            .owner(OWNER)
+            .contract_id(ContractId::from_bytes([1; 32])),
+        LIMIT,
+    )?;
+    let id_1 = session.deploy(
+        contract_bytecode!("everest"),
+        ContractData::builder()
+            .owner(OWNER)
+            .contract_id(ContractId::from_bytes([2; 32])),
+        LIMIT,
+    )?;
+
+    let height: Option<u64> =
+        session.call(id_0, "get_global_height", &(), LIMIT)?.data;
+    assert!(height.is_none(), "Global data should not be set yet");
+
+    session.set_global_meta("height", GLOBAL_HEIGHT)?;
+
+    for id in [id_0, id_1] {
+        let height: Option<u64> =
+            session.call(id, "get_global_height", &(), LIMIT)?.data;
+        assert_eq!(height, Some(GLOBAL_HEIGHT));
+
+        let height: Option<u64> =
+            session.call(id, "get_height", &(), LIMIT)?.data;
+        assert_eq!(height, Some(HEIGHT));
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn call_with_meta() -> Result<(), Error> {
+    const HEIGHT: u64 = 29_000u64;
+
+    let vm = VM::ephemeral()?;
+    let mut session =
+        vm.session(SessionData::builder().insert("height", HEIGHT)?)?;
+
+    let id = session.deploy(
+        contract_bytecode!("everest"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    for call_height in [1u64, 2u64] {
+        let meta = [(
+            String::from("height"),
+            Session::serialize_data(&call_height)?,
+        )];
+
+        let height: Option<u64> = session
+            .call_with_meta(id, "get_height", &(), &meta, LIMIT)?
+            .data;
+        assert_eq!(height, Some(call_height));
+    }
+
+    let height: Option<u64> = session.call(id, "get_height", &(), LIMIT)?.data;
+    assert_eq!(height, Some(HEIGHT), "Session metadata should be restored");
+
+    Ok(())
+}
+
+#[test]
+pub fn large_meta_data() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let value = vec![0xab; 128 * 1024];
+    let serialized =
+        Session::serialize_data(&value).expect("Serializing should succeed");
+
+    let mut session = vm.session(
+        SessionData::builder()
+            .insert("large", value.clone())?
+            .insert_raw("large_raw", value.clone())
+            .insert_raw("height", value.clone()),
+    )?;
+
+    assert_eq!(session.meta("large"), Some(serialized));
+    assert_eq!(session.meta("large_raw"), Some(value));
+
+    // data larger than the argument buffer can't be read by a contract
+    let id = session.deploy(
+        contract_bytecode!("everest"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    assert!(
+        session
+            .call::<_, Option<u64>>(id, "get_height", &(), LIMIT)
+            .is_err(),
+        "reading data larger than the argument buffer should fail"
+    );
+
+    Ok(())
+}
@@ -97,3 +97,27 @@ fn feed_out_of_gas() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn feed_call_stream() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("feeder"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    const FEED_NUM: u32 = 100;
+    const GAS_LIMIT: u64 = 10_000_000;
+
+    let numbers = session
+        .feed_call::<_, u32>(id, "feed_num", &FEED_NUM, GAS_LIMIT)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assert_eq!(numbers, (0..FEED_NUM).collect::<Vec<_>>());
+
+    Ok(())
+}
@@ -29,3 +29,25 @@ pub fn fibo() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+pub fn fibonacci_stack_overflow() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("fibonacci"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let err = session
+        .call::<u32, u64>(id, "nth", &1_000_000, 1_000_000_000)
+        .expect_err("Recursing this deep should overflow the stack");
+
+    assert!(err.as_runtime_error().is_some());
+    assert_eq!(err.as_trap(), Some(&dusk_wasmtime::Trap::StackOverflow));
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use piecrust::fuzz::{run_ops, ExecutionSummary, Op};
+use piecrust::{contract_bytecode, ContractData, Error, SessionData, VM};
+
+const OWNER: [u8; 32] = [0u8; 32];
+const LIMIT: u64 = 1_000_000;
+
+fn summarize(vm: &VM) -> Result<ExecutionSummary, Error> {
+    let mut session = vm.session(SessionData::builder())?;
+
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let eventer_id = session.deploy(
+        contract_bytecode!("eventer"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let num_events = rkyv::to_bytes::<_, 8>(&3u32).unwrap().to_vec();
+
+    let op = |contract, fn_name: &str, fn_arg: Vec<u8>| Op {
+        contract,
+        fn_name: fn_name.into(),
+        fn_arg,
+        gas_limit: LIMIT,
+    };
+
+    let ops = [
+        op(counter_id, "increment", vec![]),
+        op(eventer_id, "emit_events", num_events),
+        op(counter_id, "no_such_function", vec![]),
+        op(counter_id, "increment", vec![]),
+        op(counter_id, "read_value", vec![]),
+    ];
+
+    Ok(run_ops(&mut session, &ops))
+}
+
+#[test]
+fn run_ops_deterministic() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let summary = summarize(&vm)?;
+    let other_summary = summarize(&vm)?;
+
+    assert_eq!(summary, other_summary);
+
+    match summary {
+        ExecutionSummary::Completed { spent, failed, .. } => {
+            assert!(spent > 0);
+            assert_eq!(failed, vec![2]);
+        }
+        ExecutionSummary::Panicked { .. } => {
+            panic!("no operation should panic")
+        }
+    }
+
+    Ok(())
+}
@@ -108,3 +108,84 @@ fn error_reverts_growth() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn host_read_write_sub_range() -> Result<(), Error> {
+    const OFFSET: u32 = 100;
+    const LEN: u32 = 64;
+
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("grower"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    session.call_raw(id, "append", [0; 1024], LIMIT)?;
+
+    let mut write_bytes = OFFSET.to_le_bytes().to_vec();
+    write_bytes.extend_from_slice(&[0xab; LEN as usize]);
+    session.call_raw(id, "host_write_at", write_bytes, LIMIT)?;
+
+    let mut view_bytes = [0; 8];
+    view_bytes[..4].copy_from_slice(&OFFSET.to_le_bytes());
+    view_bytes[4..].copy_from_slice(&LEN.to_le_bytes());
+
+    let host_view = session.call_raw(id, "host_view", view_bytes, LIMIT)?;
+    assert_eq!(host_view.data, [0xab; LEN as usize]);
+
+    let view = session.call_raw(id, "view", view_bytes, LIMIT)?;
+    assert_eq!(view.data, host_view.data);
+
+    // the bytes around the written range should be untouched
+    let mut before_bytes = [0; 8];
+    before_bytes[..4].copy_from_slice(&0u32.to_le_bytes());
+    before_bytes[4..].copy_from_slice(&OFFSET.to_le_bytes());
+
+    let before = session.call_raw(id, "host_view", before_bytes, LIMIT)?;
+    assert_eq!(before.data, [0; OFFSET as usize]);
+
+    Ok(())
+}
+
+#[test]
+fn max_memory_len() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("grower"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let initial_len = session
+        .memory_len(id)?
+        .expect("The contract should exist in this session");
+    session.set_max_memory_len(initial_len);
+
+    // a session within the limit commits fine
+    let root = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(root))?;
+    session.set_max_memory_len(initial_len);
+
+    for b in 0..=u8::MAX {
+        let bytes = [b; ARGBUF_LEN];
+        session.call_raw(id, "append", bytes, LIMIT)?;
+    }
+    assert!(
+        session.memory_len(id)?.expect("The contract should exist")
+            > initial_len,
+        "The memory should have grown"
+    );
+
+    match session.commit() {
+        Err(Error::MemoryLimitExceeded(contract)) => assert_eq!(contract, id),
+        _ => panic!("Committing an oversized memory should fail"),
+    }
+
+    Ok(())
+}
@@ -87,6 +87,76 @@ pub fn host_hash() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+pub fn host_query_registered_after_session() -> Result<(), Error> {
+    let mut vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("host"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let v = vec![0u8, 1, 2];
+    session
+        .call::<_, [u8; 32]>(id, "host_hash", &v, LIMIT)
+        .expect_err("query should fail since it's not registered");
+
+    vm.register_host_query("hash", hash);
+
+    let h = session
+        .call::<_, [u8; 32]>(id, "host_hash", &v, LIMIT)
+        .expect("query should succeed once registered")
+        .data;
+    assert_eq!(blake3::hash(&[0u8, 1, 2]).as_bytes(), &h);
+
+    Ok(())
+}
+
+#[test]
+pub fn host_hash_scratch_size() -> Result<(), Error> {
+    let vm = new_ephemeral_vm()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("host"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    // Serializing a vector needs scratch space for the resolver of each of
+    // its elements, which for strings is a `usize`.
+    let strings = |n: usize| -> Vec<String> {
+        (0..n).map(|i| format!("item{i}")).collect()
+    };
+
+    let under = strings(7);
+    let h = session
+        .call::<_, [u8; 32]>(id, "host_hash_all", &under, LIMIT)
+        .expect("call should succeed with the default scratch size")
+        .data;
+    assert_eq!(blake3::hash(under.concat().as_bytes()).as_bytes(), &h);
+
+    let over = strings(9);
+    let err = session
+        .call::<_, [u8; 32]>(id, "host_hash_all", &over, LIMIT)
+        .expect_err("call should fail with the default scratch size");
+    assert!(matches!(err, Error::CompositeSerializerError(_)));
+
+    session.set_scratch_size(1024);
+
+    let h = session
+        .call::<_, [u8; 32]>(id, "host_hash_all", &over, LIMIT)
+        .expect("call should succeed with a larger scratch size")
+        .data;
+    assert_eq!(blake3::hash(over.concat().as_bytes()).as_bytes(), &h);
+
+    Ok(())
+}
+
 /// Proves that we know a number `c` such that `a + b = c`.
 #[derive(Default)]
 struct TestCircuit {
@@ -186,3 +256,108 @@ pub fn host_proof() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+pub fn host_imports() -> Result<(), Error> {
+    let vm = new_ephemeral_vm()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let host_id = session.deploy(
+        contract_bytecode!("host"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let imports = session.host_imports(host_id)?;
+    assert!(imports.iter().any(|import| import == "hq"));
+
+    let imports = session.host_imports(counter_id)?;
+    assert!(!imports.iter().any(|import| import == "hq"));
+
+    Ok(())
+}
+
+#[test]
+pub fn fallible_host_query() -> Result<(), Error> {
+    let mut vm = VM::ephemeral()?;
+
+    // rejects empty input, hashing it otherwise
+    vm.register_fallible_host_query("hash", |buf: &mut [u8], len: u32| {
+        let a = unsafe { rkyv::archived_root::<Vec<u8>>(&buf[..len as usize]) };
+        if a.is_empty() {
+            return Err(Error::Panic(String::from("empty input")));
+        }
+        Ok(hash(buf, len))
+    });
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("host"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let v = vec![0u8, 1, 2];
+    let h = session
+        .call::<_, [u8; 32]>(id, "host_hash", &v, LIMIT)
+        .expect("query should succeed")
+        .data;
+    assert_eq!(blake3::hash(&[0u8, 1, 2]).as_bytes(), &h);
+
+    match session.call::<_, [u8; 32]>(id, "host_hash", &Vec::<u8>::new(), LIMIT)
+    {
+        Err(Error::Panic(msg)) => assert_eq!(msg, "empty input"),
+        other => panic!("Expected the query to fail, got {other:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+pub fn host_query_limit() -> Result<(), Error> {
+    let vm = new_ephemeral_vm()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("host"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    const MAX_QUERIES: usize = 8;
+    session.set_host_query_limit(MAX_QUERIES);
+
+    let v = vec![0u8, 1, 2];
+
+    // the count is reset on every call
+    for _ in 0..2 {
+        session.call::<_, [u8; 32]>(
+            id,
+            "host_hash_repeat",
+            &(MAX_QUERIES as u32, v.clone()),
+            LIMIT,
+        )?;
+    }
+
+    match session.call::<_, [u8; 32]>(
+        id,
+        "host_hash_repeat",
+        &(MAX_QUERIES as u32 + 1, v),
+        LIMIT,
+    ) {
+        Err(Error::HostQueryLimitExceeded(limit)) => {
+            assert_eq!(limit, MAX_QUERIES)
+        }
+        other => panic!("Expected the limit to be exceeded, got {other:?}"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use piecrust::{
+    contract_bytecode, ContractData, Error, MemoryReport, SessionData, VM,
+};
+
+const OWNER: [u8; 32] = [0u8; 32];
+const LIMIT: u64 = 1_000_000;
+
+#[test]
+fn memory_report() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    assert_eq!(vm.memory_report(), MemoryReport::default());
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let report = vm.memory_report();
+    assert_eq!(report.memories, 1);
+    assert!(report.logical_len > 0);
+
+    session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let new_report = vm.memory_report();
+    assert_eq!(new_report.memories, 2);
+    assert!(new_report.logical_len > report.logical_len);
+
+    drop(session);
+
+    assert_eq!(
+        vm.memory_report(),
+        MemoryReport::default(),
+        "Dropping the session should release all memories"
+    );
+
+    Ok(())
+}
@@ -123,3 +123,70 @@ fn owner_of() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn contract_abi() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let micro_id = session.deploy(
+        contract_bytecode!("micro"),
+        ContractData::builder().owner([0u8; 32]),
+        LIMIT,
+    )?;
+    let metadata_id = session.deploy(
+        contract_bytecode!("metadata"),
+        ContractData::builder().owner([0u8; 32]),
+        LIMIT,
+    )?;
+
+    assert_eq!(
+        session.contract_abi(micro_id)?,
+        Some(b"change(u32) -> u32".to_vec())
+    );
+    assert_eq!(session.contract_abi(metadata_id)?, None);
+
+    // the ABI is available after committing
+    let commit_id = session.commit()?;
+    let mut session = vm.session(SessionData::builder().base(commit_id))?;
+    assert_eq!(
+        session.contract_abi(micro_id)?,
+        Some(b"change(u32) -> u32".to_vec())
+    );
+
+    let non_existing = ContractId::from_bytes([0xff; 32]);
+    assert!(matches!(
+        session.contract_abi(non_existing),
+        Err(Error::ContractDoesNotExist(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn block_context() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("metadata"),
+        ContractData::builder().owner([0u8; 33]),
+        LIMIT,
+    )?;
+
+    let context = session
+        .call::<_, (u64, u64)>(id, "read_block_context", &(), LIMIT)?
+        .data;
+    assert_eq!(context, (0, 0));
+
+    session.set_block_context(42, 1_700_000_000);
+
+    let context = session
+        .call::<_, (u64, u64)>(id, "read_block_context", &(), LIMIT)?
+        .data;
+    assert_eq!(context, (42, 1_700_000_000));
+
+    Ok(())
+}
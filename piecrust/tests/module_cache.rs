@@ -0,0 +1,51 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use piecrust::{
+    contract_bytecode, ContractData, Error, ModuleCacheReport, SessionData, VM,
+};
+
+const OWNER: [u8; 32] = [0u8; 32];
+const LIMIT: u64 = 1_000_000;
+
+#[test]
+fn module_cache() -> Result<(), Error> {
+    let mut vm = VM::ephemeral()?;
+    vm.set_module_cache_size(1);
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+
+    assert_eq!(vm.module_cache_report(), ModuleCacheReport::default());
+
+    for _ in 0..2 {
+        let mut session = vm.session(SessionData::builder().base(commit))?;
+        assert_eq!(
+            session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+            0xfc
+        );
+    }
+
+    assert_eq!(
+        vm.module_cache_report(),
+        ModuleCacheReport {
+            modules: 1,
+            hits: 1,
+            misses: 1,
+        },
+        "The module should only be deserialized once"
+    );
+
+    vm.set_module_cache_size(0);
+    assert_eq!(vm.module_cache_report().modules, 0);
+
+    Ok(())
+}
@@ -4,13 +4,43 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
+use std::num::NonZeroUsize;
+
 use piecrust::{
-    contract_bytecode, ContractData, ContractId, Error, SessionData, VM,
+    contract_bytecode, CommitLayout, ContractData, ContractId, Error,
+    SessionData, STORE_FORMAT_VERSION, VM,
 };
+use piecrust_uplink::ContractError;
 
 const OWNER: [u8; 32] = [0u8; 32];
 const LIMIT: u64 = 1_000_000;
 
+const PAGE_SIZE: usize = 0x10000;
+
+/// A contract whose `grow` function grows its memory by a page, without
+/// writing to it:
+///
+/// ```wat
+/// (module
+///   (memory (export "memory") 2)
+///   (global (export "A") i32 (i32.const 0))
+///   (func (export "grow") (param i32) (result i32)
+///     (drop (memory.grow (i32.const 1)))
+///     i32.const 0))
+/// ```
+const GROW_CONTRACT: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x05, 0x03, 0x01, 0x00, 0x02, // memory section
+    0x06, 0x06, 0x01, 0x7f, 0x00, 0x41, 0x00, 0x0b, // global section
+    0x07, 0x15, 0x03, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00,
+    0x01, 0x41, 0x03, 0x00, 0x04, 0x67, 0x72, 0x6f, 0x77, 0x00,
+    0x00, // export section
+    0x0a, 0x0b, 0x01, 0x09, 0x00, 0x41, 0x01, 0x40, 0x00, 0x1a, 0x41, 0x00,
+    0x0b, // code section
+];
+
 #[test]
 fn session_commits_persistence() -> Result<(), Error> {
     let vm = VM::ephemeral()?;
@@ -316,3 +346,1938 @@ fn migration_self_id_remains_same() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn commit_to_other_vm() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+    let archive_vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    // The base commit is not in the archive, so it is written in full.
+    let session = vm.session(SessionData::builder().base(base))?;
+    assert_eq!(session.commit_to(&archive_vm)?, base);
+    assert_eq!(archive_vm.commits(), vec![base]);
+
+    // The base commit is now in the archive, so unchanged files are taken
+    // from it.
+    let mut session = vm.session(SessionData::builder().base(base))?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    let root = session.commit_to(&archive_vm)?;
+
+    assert_eq!(vm.commits(), vec![base]);
+
+    let mut archive_commits = archive_vm.commits();
+    archive_commits.sort();
+    let mut expected_commits = vec![base, root];
+    expected_commits.sort();
+    assert_eq!(archive_commits, expected_commits);
+
+    let archive_vm = VM::new(archive_vm.root_dir())?;
+    let mut session = archive_vm.session(SessionData::builder().base(root))?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfd
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn commit_to_unwritable_dir() -> Result<(), Error> {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempfile::tempdir().expect("Creating a tempdir should succeed");
+    let root_dir = tmp.path();
+
+    let vm = VM::new(root_dir)?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    fs::set_permissions(root_dir, fs::Permissions::from_mode(0o555))
+        .expect("Setting permissions should succeed");
+
+    // Privileged users can write to directories without write permissions.
+    let probe_path = root_dir.join("probe");
+    let privileged = fs::write(&probe_path, b"probe").is_ok();
+    let _ = fs::remove_file(probe_path);
+
+    let result = session.commit();
+
+    fs::set_permissions(root_dir, fs::Permissions::from_mode(0o755))
+        .expect("Setting permissions should succeed");
+
+    if privileged {
+        result.expect("Committing as a privileged user should succeed");
+        return Ok(());
+    }
+
+    // A directory without write permissions is not on a read-only
+    // filesystem, so the error is left untouched.
+    match result {
+        Err(Error::PersistenceError(io_err)) => {
+            assert_eq!(io_err.kind(), std::io::ErrorKind::PermissionDenied);
+            assert!(!io_err.to_string().contains("read-only"));
+        }
+        res => panic!("Expected a persistence error, got {res:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn zero_memories_on_drop() -> Result<(), Error> {
+    let mut vm = VM::ephemeral()?;
+    vm.zero_memories_on_drop(true);
+
+    let id;
+    let commit;
+    {
+        let mut session = vm.session(SessionData::builder())?;
+        id = session.deploy(
+            contract_bytecode!("counter"),
+            ContractData::builder().owner(OWNER),
+            LIMIT,
+        )?;
+        session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+        commit = session.commit()?;
+    }
+
+    // Committed memories are handed over to the store, and not zeroed.
+    assert_eq!(vm.memory_report().zeroed_pages, 0);
+
+    // Modify the state without committing, dropping the session and
+    // zeroing its memories.
+    {
+        let mut session = vm.session(SessionData::builder().base(commit))?;
+        session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+        assert_eq!(
+            session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+            0xfe
+        );
+    }
+
+    let report = vm.memory_report();
+    assert!(report.zeroed_pages > 0, "The dirty pages should be zeroed");
+    assert_eq!(report.memories, 0);
+
+    // The committed state must be left untouched by the zeroing.
+    let mut session = vm.session(SessionData::builder().base(commit))?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfd
+    );
+
+    Ok(())
+}
+
+#[test]
+fn commit_info() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    let commit = session.commit()?;
+
+    let info = vm.commit_info(commit)?.expect("commit should exist");
+    assert_eq!(info.contracts, 2);
+    assert!(info.disk_size > 0, "commit should take up space on disk");
+
+    assert_eq!(vm.commit_info([0; 32])?, None);
+
+    vm.delete_commit(commit)?;
+    assert_eq!(vm.commit_info(commit)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn rollback() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(base))?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    assert_ne!(session.root(), base);
+
+    session.rollback();
+    assert_eq!(session.root(), base);
+    assert_eq!(session.block_spent(), 0);
+    assert_eq!(
+        session
+            .call::<_, i64>(counter_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfc
+    );
+
+    // the session can be reused after a rollback
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    let commit = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(commit))?;
+    assert_eq!(
+        session
+            .call::<_, i64>(counter_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfd
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reset_to_genesis() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let genesis_root = session.root();
+
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    let base = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(base))?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+
+    session.reset_to_genesis();
+    assert_eq!(session.root(), genesis_root);
+    assert!(
+        session
+            .call::<_, i64>(counter_id, "read_value", &(), LIMIT)
+            .is_err(),
+        "the counter should not exist after a reset"
+    );
+
+    // build a new independent state in the same session
+    let box_id = session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.call::<i16, ()>(box_id, "set", &0x11, LIMIT)?;
+    let commit = session.commit()?;
+
+    assert_ne!(commit, base);
+    assert_eq!(vm.commits().len(), 2, "the base commit should be kept");
+
+    // the base commit is left untouched
+    let mut session = vm.session(SessionData::builder().base(base))?;
+    assert_eq!(
+        session
+            .call::<_, i64>(counter_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfd
+    );
+    assert!(
+        session
+            .call::<_, Option<i16>>(box_id, "get", &(), LIMIT)
+            .is_err(),
+        "the box should not exist in the base commit"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn rebuild_missing_index() -> Result<(), Error> {
+    let tmp = tempfile::tempdir().expect("Creating a tempdir should succeed");
+    let root_dir = tmp.path();
+
+    let vm = VM::new(root_dir)?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    let memory_len = session.memory_len(id)?;
+    let commit = session.commit()?;
+    drop(vm);
+
+    let commit_dir = root_dir.join(hex::encode(commit));
+    std::fs::remove_file(commit_dir.join("index"))
+        .expect("Removing the index file should succeed");
+
+    assert!(
+        VM::new(&root_dir).is_err(),
+        "the VM should fail to load a commit without an index"
+    );
+
+    VM::rebuild_index(&root_dir, CommitLayout::Flat, commit)?;
+
+    let vm = VM::new(&root_dir)?;
+    assert_eq!(vm.commits(), vec![commit]);
+
+    let mut session = vm.session(SessionData::builder().base(commit))?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfd
+    );
+    assert_eq!(session.memory_len(id)?, memory_len);
+
+    Ok(())
+}
+
+#[test]
+fn rebuild_index_without_lengths() -> Result<(), Error> {
+    let tmp = tempfile::tempdir().expect("Creating a tempdir should succeed");
+    let root_dir = tmp.path();
+
+    let vm = VM::new(root_dir)?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+    drop(vm);
+
+    let commit_dir = root_dir.join(hex::encode(commit));
+    std::fs::remove_file(commit_dir.join("index"))
+        .expect("Removing the index file should succeed");
+    std::fs::remove_file(commit_dir.join("lengths"))
+        .expect("Removing the lengths file should succeed");
+
+    assert!(
+        VM::rebuild_index(root_dir, CommitLayout::Flat, commit).is_err(),
+        "rebuilding the index without the memory lengths should fail"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn rebuild_corrupted_index() -> Result<(), Error> {
+    let tmp = tempfile::tempdir().expect("Creating a tempdir should succeed");
+    let root_dir = tmp.path();
+
+    let vm = VM::new(root_dir)?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+    drop(vm);
+
+    // corrupt a memory page
+    let commit_dir = root_dir.join(hex::encode(commit));
+    let memory_dir = commit_dir.join("memory");
+    let contract_dir = std::fs::read_dir(memory_dir)
+        .expect("Reading the memory directory should succeed")
+        .next()
+        .expect("There should be a contract memory")
+        .expect("Reading the contract memory should succeed")
+        .path();
+    let page_path = std::fs::read_dir(contract_dir)
+        .expect("Reading the contract memory should succeed")
+        .next()
+        .expect("There should be a page")
+        .expect("Reading the page should succeed")
+        .path();
+
+    let mut page =
+        std::fs::read(&page_path).expect("Reading the page should succeed");
+    page[0] ^= 0xff;
+    std::fs::write(&page_path, page).expect("Writing the page should succeed");
+
+    assert!(
+        VM::rebuild_index(&root_dir, CommitLayout::Flat, commit).is_err(),
+        "rebuilding the index of a corrupted commit should fail"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn diff_commits() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let box_id = session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(base))?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    let vector_id = session.deploy(
+        contract_bytecode!("vector"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let child = session.commit()?;
+
+    let delta = vm.diff_commits(base, child)?;
+    assert_eq!(delta.added.into_iter().collect::<Vec<_>>(), vec![vector_id]);
+    assert!(delta.removed.is_empty());
+    assert_eq!(
+        delta.changed.into_iter().collect::<Vec<_>>(),
+        vec![counter_id]
+    );
+
+    let delta = vm.diff_commits(child, base)?;
+    assert!(delta.added.is_empty());
+    assert_eq!(
+        delta.removed.into_iter().collect::<Vec<_>>(),
+        vec![vector_id]
+    );
+    assert!(!delta.changed.contains(&box_id));
+
+    assert!(vm.diff_commits(base, base)?.changed.is_empty());
+    assert!(vm.diff_commits(base, [0; 32]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn read_only_session() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    let mut session =
+        vm.read_only_session(SessionData::builder().base(base))?;
+    assert!(session.is_read_only());
+
+    // queries succeed
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfc
+    );
+
+    // transactions execute, but their changes are discarded
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfc
+    );
+
+    assert!(matches!(
+        session.deploy(
+            contract_bytecode!("box"),
+            ContractData::builder().owner(OWNER),
+            LIMIT,
+        ),
+        Err(Error::ReadOnlySession)
+    ));
+    assert!(matches!(session.commit(), Err(Error::ReadOnlySession)));
+
+    assert_eq!(vm.commits(), vec![base]);
+
+    Ok(())
+}
+
+#[test]
+fn fanout_commit_layout() -> Result<(), Error> {
+    const N_COMMITS: usize = 16;
+
+    let root_dir = tempfile::tempdir()
+        .expect("Creating a temporary directory should succeed");
+    let root_dir = root_dir.path();
+
+    let vm = VM::with_commit_layout(root_dir, CommitLayout::Fanout)?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let mut commit = session.commit()?;
+
+    let mut commits = vec![commit];
+    for _ in 1..N_COMMITS {
+        let mut session = vm.session(SessionData::builder().base(commit))?;
+        session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+        commit = session.commit()?;
+        commits.push(commit);
+    }
+
+    let root_hex = hex::encode(commit);
+    let (fanout, rest) = root_hex.split_at(2);
+    assert!(
+        root_dir.join(fanout).join(rest).is_dir(),
+        "the commit should be stored under its fanout directory"
+    );
+
+    drop(vm);
+
+    let vm = VM::with_commit_layout(root_dir, CommitLayout::Fanout)?;
+
+    let mut loaded_commits = vm.commits();
+    loaded_commits.sort();
+    commits.sort();
+    assert_eq!(loaded_commits, commits);
+
+    let mut session = vm.session(SessionData::builder().base(commit))?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfc + N_COMMITS as i64 - 1
+    );
+
+    Ok(())
+}
+
+#[test]
+fn estimated_commit_bytes() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    assert_eq!(session.estimated_commit_bytes(), 0);
+
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+
+    let estimate = session.estimated_commit_bytes();
+    assert!(estimate > 0, "a new contract should take up space");
+
+    let commit = session.commit()?;
+
+    let info = vm.commit_info(commit)?.expect("The commit should exist");
+    let index_len = std::fs::metadata(
+        vm.root_dir().join(hex::encode(commit)).join("index"),
+    )
+    .expect("The index file should exist")
+    .len();
+
+    assert_eq!(info.disk_size, estimate + index_len);
+
+    Ok(())
+}
+
+#[test]
+fn export_import_commit() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let box_id = session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    session.call::<i16, ()>(box_id, "set", &0x11, LIMIT)?;
+    let commit = session.commit()?;
+
+    let mut archive = Vec::new();
+    vm.export_commit(commit, &mut archive)?;
+
+    let tmp = tempfile::tempdir().expect("Creating a tempdir should succeed");
+    let root_dir = tmp.path();
+
+    let other_vm = VM::new(root_dir)?;
+    let imported = other_vm.import_commit(archive.as_slice())?;
+    assert_eq!(imported, commit);
+    assert_eq!(other_vm.commits(), vec![commit]);
+
+    let mut session = other_vm.session(SessionData::builder().base(commit))?;
+    assert_eq!(
+        session
+            .call::<_, i64>(counter_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfd
+    );
+    assert_eq!(
+        session
+            .call::<_, Option<i16>>(box_id, "get", &(), LIMIT)?
+            .data,
+        Some(0x11)
+    );
+
+    // the imported commit should survive a restart
+    drop(session);
+    drop(other_vm);
+    let other_vm = VM::new(root_dir)?;
+    assert_eq!(other_vm.commits(), vec![commit]);
+
+    // tampering with a memory page should make the import fail
+    let (page, _) =
+        archive_contents(&archive, |path| path.starts_with("memory/"));
+    archive[page] ^= 0xff;
+    assert!(
+        VM::ephemeral()?.import_commit(archive.as_slice()).is_err(),
+        "importing a tampered archive should fail"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn import_recompiles_objectcode() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+
+    let mut archive = Vec::new();
+    vm.export_commit(commit, &mut archive)?;
+
+    // objectcode isn't covered by the root, so tampering with it should go
+    // unnoticed by the verification, but not survive the import
+    let (objectcode, objectcode_len) =
+        archive_contents(&archive, |path| path.ends_with(".a"));
+    archive[objectcode + objectcode_len / 2] ^= 0xff;
+    let tampered = archive[objectcode..][..objectcode_len].to_vec();
+
+    let other_vm = VM::ephemeral()?;
+    assert_eq!(other_vm.import_commit(archive.as_slice())?, commit);
+
+    let objectcode_path = other_vm
+        .root_dir()
+        .join(hex::encode(commit))
+        .join("bytecode")
+        .join(hex::encode(id.as_bytes()))
+        .with_extension("a");
+    let imported = std::fs::read(objectcode_path)
+        .expect("Reading the objectcode should succeed");
+    assert_ne!(imported, tampered, "the objectcode should be recompiled");
+
+    let mut session = other_vm.session(SessionData::builder().base(commit))?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfc
+    );
+
+    Ok(())
+}
+
+#[test]
+fn import_all_recompiles_objectcode() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+
+    let mut archive = Vec::new();
+    vm.export_all(&mut archive)?;
+
+    // the contents of files in a store archive are hashed, but the hash can
+    // be recomputed by whoever tampers with them
+    let (hash, objectcode, objectcode_len) =
+        store_archive_contents(&archive, |path| path.ends_with(".a"));
+    archive[objectcode + objectcode_len / 2] ^= 0xff;
+    let tampered = archive[objectcode..][..objectcode_len].to_vec();
+    archive[hash..][..32].copy_from_slice(blake3::hash(&tampered).as_bytes());
+
+    let other_vm = VM::ephemeral()?;
+    assert_eq!(other_vm.import_all(archive.as_slice())?, vec![commit]);
+
+    let objectcode_path = other_vm
+        .root_dir()
+        .join(hex::encode(commit))
+        .join("bytecode")
+        .join(hex::encode(id.as_bytes()))
+        .with_extension("a");
+    let imported = std::fs::read(objectcode_path)
+        .expect("Reading the objectcode should succeed");
+    assert_ne!(imported, tampered, "the objectcode should be recompiled");
+
+    let mut session = other_vm.session(SessionData::builder().base(commit))?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfc
+    );
+
+    Ok(())
+}
+
+/// Returns the offset of the hash, and the offset and length of the contents
+/// of the first file written inline in the first commit of a store `archive`
+/// whose path matches `matches`.
+fn store_archive_contents(
+    archive: &[u8],
+    matches: impl Fn(&str) -> bool,
+) -> (usize, usize, usize) {
+    // skip the magic, the version, and the root of the commit
+    let mut offset = 44;
+
+    let n_files =
+        u32::from_le_bytes(archive[offset..offset + 4].try_into().unwrap());
+    offset += 4;
+
+    for _ in 0..n_files {
+        let path_len =
+            u32::from_le_bytes(archive[offset..offset + 4].try_into().unwrap())
+                as usize;
+        offset += 4;
+
+        let path = std::str::from_utf8(&archive[offset..offset + path_len])
+            .expect("Paths should be UTF-8");
+        offset += path_len;
+
+        let hash = offset;
+        offset += 32;
+
+        let tag = archive[offset];
+        offset += 1;
+        if tag != 0 {
+            continue;
+        }
+
+        let file_len =
+            u64::from_le_bytes(archive[offset..offset + 8].try_into().unwrap())
+                as usize;
+        offset += 8;
+
+        if matches(path) {
+            return (hash, offset, file_len);
+        }
+        offset += file_len;
+    }
+
+    panic!("No matching file in the archive");
+}
+
+/// Returns the offset and length of the contents of the first file in a commit
+/// `archive` whose path matches `matches`.
+fn archive_contents(
+    archive: &[u8],
+    matches: impl Fn(&str) -> bool,
+) -> (usize, usize) {
+    // skip the magic and the version
+    let mut offset = 12;
+
+    loop {
+        let path_len =
+            u32::from_le_bytes(archive[offset..offset + 4].try_into().unwrap())
+                as usize;
+        offset += 4;
+
+        let path = std::str::from_utf8(&archive[offset..offset + path_len])
+            .expect("Paths should be UTF-8");
+        offset += path_len;
+
+        let file_len =
+            u64::from_le_bytes(archive[offset..offset + 8].try_into().unwrap())
+                as usize;
+        offset += 8;
+
+        if matches(path) {
+            return (offset, file_len);
+        }
+        offset += file_len;
+    }
+}
+
+#[test]
+fn parallel_commit_loading() -> Result<(), Error> {
+    const N_COMMITS: usize = 16;
+
+    let tmp = tempfile::tempdir().expect("Creating a tempdir should succeed");
+    let root_dir = tmp.path();
+
+    let vm = VM::new(root_dir)?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let mut commit = session.commit()?;
+
+    for _ in 1..N_COMMITS {
+        let mut session = vm.session(SessionData::builder().base(commit))?;
+        session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+        commit = session.commit()?;
+    }
+
+    drop(vm);
+
+    let load = |parallelism: usize| -> Result<Vec<[u8; 32]>, Error> {
+        let parallelism = NonZeroUsize::new(parallelism)
+            .expect("Parallelism should be non-zero");
+        let vm = VM::with_load_parallelism(
+            &root_dir,
+            CommitLayout::Flat,
+            parallelism,
+        )?;
+        let mut commits = vm.commits();
+        commits.sort();
+        Ok(commits)
+    };
+
+    let serial = load(1)?;
+    assert_eq!(serial.len(), N_COMMITS);
+
+    for parallelism in [2, 3, 4, N_COMMITS, 2 * N_COMMITS] {
+        assert_eq!(load(parallelism)?, serial);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn dry_run() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let root = session.root();
+
+    let receipt = session.dry_run::<_, ()>(id, "increment", &(), LIMIT)?;
+    assert!(receipt.gas_spent > 0, "the call should spend gas");
+
+    assert_eq!(session.root(), root, "the root should be unchanged");
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfc
+    );
+
+    // the same call executed for real spends the same gas
+    let spent = session
+        .call::<_, ()>(id, "increment", &(), LIMIT)?
+        .gas_spent;
+    assert_eq!(spent, receipt.gas_spent);
+    assert_ne!(session.root(), root);
+
+    Ok(())
+}
+
+#[test]
+fn self_destruct() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let fallible_id = session.deploy(
+        contract_bytecode!("fallible_counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(base))?;
+
+    // a failed call leaves the contract in place
+    session
+        .call::<_, ()>(fallible_id, "destruct", &true, LIMIT)
+        .expect_err("the call should panic");
+    assert_eq!(
+        session
+            .call::<_, i64>(fallible_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfc
+    );
+
+    session.call::<_, ()>(fallible_id, "destruct", &false, LIMIT)?;
+    assert!(matches!(
+        session.call::<_, i64>(fallible_id, "read_value", &(), LIMIT),
+        Err(Error::ContractDoesNotExist(id)) if id == fallible_id
+    ));
+
+    let commit = session.commit()?;
+    assert_ne!(commit, base);
+
+    let info = vm.commit_info(commit)?.expect("commit should exist");
+    assert_eq!(info.contracts, 1);
+
+    let mut session = vm.session(SessionData::builder().base(commit))?;
+    assert!(matches!(
+        session.call::<_, i64>(fallible_id, "read_value", &(), LIMIT),
+        Err(Error::ContractDoesNotExist(id)) if id == fallible_id
+    ));
+    assert_eq!(
+        session
+            .call::<_, i64>(counter_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfc
+    );
+
+    Ok(())
+}
+
+#[test]
+fn self_destruct_in_failed_callee() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let fallible_id = session.deploy(
+        contract_bytecode!("fallible_counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let center_id = session.deploy(
+        contract_bytecode!("callcenter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    // the callee fails after destructing itself, but the call succeeds
+    let arg = rkyv::to_bytes::<_, 16>(&true)
+        .expect("Serialization should succeed")
+        .to_vec();
+    session
+        .call::<_, Result<Vec<u8>, ContractError>>(
+            center_id,
+            "delegate_query",
+            &(fallible_id, String::from("destruct"), arg),
+            LIMIT,
+        )?
+        .data
+        .expect_err("the callee should fail");
+
+    assert_eq!(
+        session
+            .call::<_, i64>(fallible_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfc
+    );
+
+    Ok(())
+}
+
+#[test]
+fn deployed_contracts() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let box_id = session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(base))?;
+    let eventer_id = session.deploy(
+        contract_bytecode!("eventer"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let mut expected = vec![counter_id, box_id, eventer_id];
+    expected.sort();
+
+    assert_eq!(session.deployed_contracts(), expected);
+
+    Ok(())
+}
+
+#[test]
+fn store_format_version() -> Result<(), Error> {
+    use std::fs;
+
+    let root_dir = tempfile::tempdir()
+        .expect("Creating a temporary directory should succeed");
+
+    let vm = VM::new(root_dir.path())?;
+    assert_eq!(vm.store_format_version(), STORE_FORMAT_VERSION);
+    drop(vm);
+
+    // reopening a store with the current version succeeds
+    VM::new(root_dir.path())?;
+
+    fs::write(
+        root_dir.path().join("version"),
+        (STORE_FORMAT_VERSION - 1).to_string(),
+    )
+    .expect("Writing the version file should succeed");
+
+    match VM::new(root_dir.path()) {
+        Err(Error::PersistenceError(io_err)) => {
+            assert!(io_err.to_string().contains("format version"));
+        }
+        Ok(_) => panic!("Expected opening an older store to fail"),
+        Err(err) => panic!("Expected a persistence error, got {err:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn fork_session() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(base))?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+
+    let root = session.root();
+
+    let mut fork = session.fork()?;
+    assert_eq!(fork.root(), root, "the fork should start at the same state");
+    assert_eq!(
+        fork.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfd
+    );
+
+    fork.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    assert_eq!(
+        fork.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfe
+    );
+    assert_ne!(fork.root(), root);
+
+    assert_eq!(session.root(), root, "the parent should be unaffected");
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfd
+    );
+
+    // both sessions can be committed independently
+    let fork_commit = fork.commit()?;
+    let commit = session.commit()?;
+    assert_eq!(commit, root);
+    assert_ne!(fork_commit, commit);
+
+    // contracts deployed in the session keep their state in the fork
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+
+    let mut fork = session.fork()?;
+    assert_eq!(
+        fork.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfd
+    );
+
+    Ok(())
+}
+
+#[test]
+fn migrate_store() -> Result<(), Error> {
+    use std::fs;
+
+    let root_dir = tempfile::tempdir()
+        .expect("Creating a temporary directory should succeed");
+    let root_dir = root_dir.path();
+
+    let vm = VM::with_commit_layout(root_dir, CommitLayout::Fanout)?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    let commit = session.commit()?;
+    drop(vm);
+
+    // make the store look like one from before the version file, with a
+    // commit missing its index and an unfinished import
+    let commit_hex = hex::encode(commit);
+    let (fanout, rest) = commit_hex.split_at(2);
+    fs::remove_file(root_dir.join("version"))
+        .expect("Removing the version file should succeed");
+    fs::remove_file(root_dir.join(fanout).join(rest).join("index"))
+        .expect("Removing the index file should succeed");
+    fs::create_dir(root_dir.join(".import-0123"))
+        .expect("Creating the import directory should succeed");
+
+    assert!(
+        VM::with_commit_layout(root_dir, CommitLayout::Fanout).is_err(),
+        "the VM should fail to load a commit without an index"
+    );
+
+    // migrating twice is the same as migrating once
+    VM::migrate_store(root_dir, CommitLayout::Fanout)?;
+    VM::migrate_store(root_dir, CommitLayout::Fanout)?;
+
+    assert!(!root_dir.join(".import-0123").exists());
+
+    let vm = VM::with_commit_layout(root_dir, CommitLayout::Fanout)?;
+    assert_eq!(vm.commits(), vec![commit]);
+
+    let mut session = vm.session(SessionData::builder().base(commit))?;
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfd
+    );
+
+    Ok(())
+}
+
+#[test]
+fn gc_commits() -> Result<(), Error> {
+    use std::collections::BTreeSet;
+
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let mut commits = vec![session.commit()?];
+    for _ in 0..4 {
+        let base = *commits.last().unwrap();
+        let mut session = vm.session(SessionData::builder().base(base))?;
+        session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+        commits.push(session.commit()?);
+    }
+
+    let keep = [commits[1], commits[4]];
+
+    // a commit in use is only deleted once the session using it drops
+    let held = vm.session(SessionData::builder().base(commits[2]))?;
+
+    let deleted: BTreeSet<_> = vm.gc(&keep)?.into_iter().collect();
+    assert_eq!(
+        deleted,
+        BTreeSet::from([commits[0], commits[2], commits[3]])
+    );
+
+    let remaining: BTreeSet<_> = vm.commits().into_iter().collect();
+    assert_eq!(
+        remaining,
+        BTreeSet::from([commits[1], commits[2], commits[4]])
+    );
+
+    drop(held);
+
+    // there is no way to wait for the deferred deletion, but any call made
+    // after the drop is processed after it
+    let remaining: BTreeSet<_> = vm.commits().into_iter().collect();
+    assert_eq!(remaining, BTreeSet::from(keep));
+
+    Ok(())
+}
+
+#[test]
+fn gc_then_delete_held_commit() -> Result<(), Error> {
+    use std::thread;
+    use std::time::Duration;
+
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+
+    let held = vm.session(SessionData::builder().base(commit))?;
+    vm.gc(&[])?;
+
+    thread::scope(|s| {
+        let deletion = s.spawn(|| vm.delete_commit(commit));
+
+        // the deletion blocks until the session drops, so give it time to be
+        // queued behind the one made by the collection
+        thread::sleep(Duration::from_millis(100));
+        assert!(!deletion.is_finished(), "the deletion should be queued");
+        drop(held);
+
+        deletion
+            .join()
+            .expect("The deleting thread should not panic")
+            .expect("The deletion should succeed");
+    });
+
+    assert!(vm.commits().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn commits_chronological() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let mut commits = vec![session.commit()?];
+    for _ in 0..4 {
+        let base = *commits.last().unwrap();
+        let mut session = vm.session(SessionData::builder().base(base))?;
+        session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+        commits.push(session.commit()?);
+    }
+
+    assert_eq!(vm.commits_chronological(), commits);
+
+    // committing the same state again doesn't move the commit
+    let session = vm.session(SessionData::builder().base(commits[0]))?;
+    assert_eq!(session.commit()?, commits[0]);
+
+    assert_eq!(vm.commits_chronological(), commits);
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_restore() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+
+    let snapshot = session.snapshot()?;
+    let snapshot_root = session.root();
+
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    let later = session.snapshot()?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    assert_ne!(session.root(), snapshot_root);
+
+    session.restore(snapshot)?;
+    assert_eq!(session.root(), snapshot_root);
+    assert_eq!(session.call_record(1), None);
+    assert_eq!(
+        session.call::<_, i64>(id, "read_value", &(), LIMIT)?.data,
+        0xfd
+    );
+
+    // snapshots taken after the one restored are discarded
+    let err = session
+        .restore(later)
+        .expect_err("restoring a discarded snapshot should fail");
+    assert!(matches!(err, Error::InvalidSnapshot(_)));
+
+    // a snapshot may be restored more than once
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    session.restore(snapshot)?;
+    assert_eq!(session.root(), snapshot_root);
+
+    // identifiers of discarded snapshots are not reused
+    let newer = session.snapshot()?;
+    assert_ne!(newer, later);
+    let err = session
+        .restore(later)
+        .expect_err("restoring a discarded snapshot should fail");
+    assert!(matches!(err, Error::InvalidSnapshot(_)));
+
+    // resetting the session discards all snapshots
+    session.reset_to_genesis();
+    assert_eq!(session.call_record(0), None);
+    assert_eq!(session.block_spent(), 0);
+    let err = session
+        .restore(newer)
+        .expect_err("restoring a snapshot after a reset should fail");
+    assert!(matches!(err, Error::InvalidSnapshot(_)));
+
+    Ok(())
+}
+
+#[test]
+fn verify_commit() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+    let root_dir = vm.root_dir().to_path_buf();
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    let commit = session.commit()?;
+
+    assert!(vm.verify_commit(commit)?, "an intact commit should verify");
+
+    // corrupt a memory page
+    let contract_dir = root_dir
+        .join(hex::encode(commit))
+        .join("memory")
+        .join(hex::encode(id.as_bytes()));
+    let page_path = std::fs::read_dir(contract_dir)
+        .expect("Reading the contract memory should succeed")
+        .next()
+        .expect("There should be a page")
+        .expect("Reading the page should succeed")
+        .path();
+
+    let mut page =
+        std::fs::read(&page_path).expect("Reading the page should succeed");
+    page[0] ^= 0xff;
+    std::fs::write(&page_path, page).expect("Writing the page should succeed");
+
+    assert!(
+        !vm.verify_commit(commit)?,
+        "a corrupted commit should fail verification"
+    );
+
+    assert!(
+        vm.verify_commit([0u8; 32]).is_err(),
+        "verifying a non-existing commit should error"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn converging_histories_share_commit() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+    let root_dir = vm.root_dir().to_path_buf();
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("double_counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    // the same increments, made in a different order
+    let mut commits = Vec::new();
+    for fnames in [
+        ["increment_left", "increment_right"],
+        ["increment_right", "increment_left"],
+    ] {
+        let mut session = vm.session(SessionData::builder().base(base))?;
+        for fname in fnames {
+            session.call::<_, ()>(id, fname, &(), LIMIT)?;
+        }
+        commits.push(session.commit()?);
+    }
+
+    assert_eq!(commits[0], commits[1], "the same state has the same root");
+    assert_eq!(vm.commits().len(), 2, "the second commit should be a no-op");
+
+    let commit_dirs = std::fs::read_dir(&root_dir)
+        .expect("Reading the root directory should succeed")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .count();
+    assert_eq!(commit_dirs, 2, "only one directory is written per root");
+
+    Ok(())
+}
+
+#[test]
+fn contract_code() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let bytecode = contract_bytecode!("counter");
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        bytecode,
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+
+    let (code, objectcode) = vm
+        .contract_code(commit, id)?
+        .expect("The contract should exist in the commit");
+    assert_eq!(code, bytecode);
+    assert!(
+        !objectcode.is_empty(),
+        "the compiled code should be present"
+    );
+
+    let missing = ContractId::from_bytes([0xff; 32]);
+    assert_eq!(vm.contract_code(commit, missing)?, None);
+
+    assert!(
+        vm.contract_code([0u8; 32], id).is_err(),
+        "reading from a non-existing commit should error"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn unfinished_commit_ignored() -> Result<(), Error> {
+    let tmp = tempfile::tempdir().expect("Creating a tempdir should succeed");
+    let root_dir = tmp.path();
+
+    let vm = VM::new(root_dir)?;
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+    drop(vm);
+
+    // simulate a crash while writing the next commit
+    let mut unfinished_dir =
+        root_dir.join(format!(".tmp-{}", hex::encode([1; 32])));
+    unfinished_dir.push("memory");
+    std::fs::create_dir_all(&unfinished_dir)
+        .expect("Creating the directory should succeed");
+
+    let vm = VM::new(root_dir)?;
+    assert_eq!(vm.commits(), vec![commit]);
+
+    let mut session = vm.session(SessionData::builder().base(commit))?;
+    session.call::<_, ()>(id, "increment", &(), LIMIT)?;
+    let next_commit = session.commit()?;
+
+    let mut commits = vm.commits();
+    commits.sort();
+    let mut expected = vec![commit, next_commit];
+    expected.sort();
+    assert_eq!(commits, expected);
+
+    Ok(())
+}
+
+#[test]
+fn max_sessions_per_commit() -> Result<(), Error> {
+    const MAX_SESSIONS: usize = 3;
+
+    let mut vm = VM::ephemeral()?;
+    vm.set_max_sessions_per_commit(MAX_SESSIONS);
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+
+    let mut sessions = (0..MAX_SESSIONS)
+        .map(|_| vm.session(SessionData::builder().base(commit)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assert!(
+        vm.session(SessionData::builder().base(commit)).is_err(),
+        "spawning a session over the limit should fail"
+    );
+
+    assert!(
+        sessions[0].fork().is_err(),
+        "forking a session over the limit should fail"
+    );
+    assert!(
+        sessions[0].snapshot().is_err(),
+        "snapshotting a session over the limit should fail"
+    );
+
+    // dropping a session frees up its place
+    sessions.pop();
+    let mut fork = sessions[0].fork()?;
+    assert!(
+        fork.snapshot().is_err(),
+        "the fork should take up the freed place"
+    );
+
+    drop(fork);
+    vm.session(SessionData::builder().base(commit))?;
+
+    Ok(())
+}
+
+#[test]
+fn validate_commit() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+    let root_dir = vm.root_dir().to_path_buf();
+
+    let mut session = vm.session(SessionData::builder())?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let box_id = session.deploy(
+        contract_bytecode!("box"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+
+    let results = vm.validate_commit(commit)?;
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|(_, result)| result.is_ok()));
+
+    // corrupt the compiled code of the counter
+    let objectcode_path = root_dir
+        .join(hex::encode(commit))
+        .join("bytecode")
+        .join(hex::encode(counter_id.as_bytes()))
+        .with_extension("a");
+    std::fs::write(objectcode_path, b"corrupted")
+        .expect("Writing the compiled code should succeed");
+
+    for (contract, result) in vm.validate_commit(commit)? {
+        if contract == counter_id {
+            assert!(result.is_err(), "the corrupted contract should fail");
+        } else {
+            assert_eq!(contract, box_id);
+            assert!(result.is_ok(), "the intact contract should succeed");
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn export_import_all() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base_commit = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(base_commit))?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    let commit = session.commit()?;
+
+    let mut archive = Vec::new();
+    vm.export_all(&mut archive)?;
+
+    // the files shared by both commits should only be written once
+    let mut base_archive = Vec::new();
+    vm.export_commit(base_commit, &mut base_archive)?;
+    let mut commit_archive = Vec::new();
+    vm.export_commit(commit, &mut commit_archive)?;
+    assert!(archive.len() < base_archive.len() + commit_archive.len());
+
+    let other_vm = VM::ephemeral()?;
+    let imported = other_vm.import_all(archive.as_slice())?;
+    assert_eq!(imported, vec![base_commit, commit]);
+
+    let mut commits = other_vm.commits();
+    commits.sort();
+    let mut expected = vec![base_commit, commit];
+    expected.sort();
+    assert_eq!(commits, expected);
+
+    let mut session = other_vm.session(SessionData::builder().base(commit))?;
+    assert_eq!(
+        session
+            .call::<_, i64>(counter_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfd
+    );
+
+    // the bytecode of the counter should be hard linked between the commits
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let bytecode_path = |commit: [u8; 32]| {
+            other_vm
+                .root_dir()
+                .join(hex::encode(commit))
+                .join("bytecode")
+                .join(hex::encode(counter_id.as_bytes()))
+        };
+
+        let base_metadata = std::fs::metadata(bytecode_path(base_commit))
+            .expect("The bytecode should exist in the base commit");
+        let metadata = std::fs::metadata(bytecode_path(commit))
+            .expect("The bytecode should exist in the commit");
+        assert_eq!(base_metadata.ino(), metadata.ino());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn commit_timestamp() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let first_commit = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(first_commit))?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    let second_commit = session.commit()?;
+
+    let first_timestamp = vm
+        .commit_timestamp(first_commit)?
+        .expect("The first commit should have a timestamp");
+    let second_timestamp = vm
+        .commit_timestamp(second_commit)?
+        .expect("The second commit should have a timestamp");
+    assert!(second_timestamp > first_timestamp);
+
+    assert_eq!(vm.commit_timestamp([0u8; 32])?, None);
+
+    // commits written without a timestamp should have none
+    let timestamp_path = vm
+        .root_dir()
+        .join(hex::encode(first_commit))
+        .join("timestamp");
+    std::fs::remove_file(timestamp_path)
+        .expect("Removing the timestamp should succeed");
+    assert_eq!(vm.commit_timestamp(first_commit)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn ephemeral_removed_on_drop() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+    let root_dir = vm.root_dir().to_path_buf();
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.commit()?;
+    assert!(root_dir.is_dir());
+
+    drop(vm);
+    assert!(!root_dir.exists(), "The store directory should be removed");
+
+    Ok(())
+}
+
+#[test]
+fn ephemeral_session_outlives_vm() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+    let root_dir = vm.root_dir().to_path_buf();
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let fork = session.fork()?;
+
+    drop(vm);
+    assert!(root_dir.is_dir(), "The sessions should keep the directory");
+
+    session.commit()?;
+    assert!(root_dir.is_dir(), "The fork should keep the directory");
+
+    drop(fork);
+    assert!(!root_dir.exists(), "The store directory should be removed");
+
+    Ok(())
+}
+
+#[test]
+fn ephemeral_in() -> Result<(), Error> {
+    let dir = tempfile::tempdir().expect("Creating a directory should succeed");
+
+    let vm = VM::ephemeral_in(dir.path())?;
+    let root_dir = vm.root_dir().to_path_buf();
+
+    assert!(root_dir.starts_with(dir.path()));
+    assert_ne!(
+        root_dir,
+        dir.path(),
+        "The store should be in a subdirectory"
+    );
+    assert!(root_dir.is_dir(), "The store directory should be created");
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    session.commit()?;
+
+    drop(vm);
+    assert!(!root_dir.exists(), "The store directory should be removed");
+    assert!(dir.path().is_dir(), "The given directory should be kept");
+
+    Ok(())
+}
+
+#[test]
+fn commit_unchanged() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    let entries = |vm: &VM| {
+        std::fs::read_dir(vm.root_dir())
+            .expect("Reading the root directory should succeed")
+            .count()
+    };
+    let n_entries = entries(&vm);
+
+    let session = vm.session(SessionData::builder().base(base))?;
+    let root = session.commit()?;
+
+    assert_eq!(root, base, "The root should be the same as the base");
+    assert_eq!(entries(&vm), n_entries, "No directory should be created");
+    assert_eq!(vm.commits(), [base]);
+
+    Ok(())
+}
+
+#[test]
+fn commit_grown_memory() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let id = session.deploy(
+        GROW_CONTRACT,
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let base = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(base))?;
+    session.call_raw(id, "grow", vec![], LIMIT)?;
+    assert_eq!(session.memory_len(id)?, Some(3 * PAGE_SIZE));
+    let root = session.commit()?;
+
+    assert_ne!(root, base, "Growing the memory should change the root");
+
+    let mut session = vm.session(SessionData::builder().base(root))?;
+    assert_eq!(
+        session.memory_len(id)?,
+        Some(3 * PAGE_SIZE),
+        "The grown length should be committed"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn commit_chain() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let first_commit = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(first_commit))?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    let second_commit = session.commit()?;
+
+    let mut session = vm.session(SessionData::builder().base(second_commit))?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    let third_commit = session.commit()?;
+
+    assert_eq!(vm.commit_parents(first_commit)?, None);
+    assert_eq!(vm.commit_parents(second_commit)?, Some(first_commit));
+    assert_eq!(vm.commit_parents(third_commit)?, Some(second_commit));
+
+    assert_eq!(
+        vm.commit_chain(third_commit)?,
+        [first_commit, second_commit, third_commit]
+    );
+    assert_eq!(vm.commit_chain(first_commit)?, [first_commit]);
+    assert!(vm.commit_chain([0u8; 32])?.is_empty());
+
+    // commits written without a parent start the chain
+    let parent_path = vm
+        .root_dir()
+        .join(hex::encode(second_commit))
+        .join("parent");
+    std::fs::remove_file(&parent_path)
+        .expect("Removing the parent should succeed");
+    assert_eq!(vm.commit_parents(second_commit)?, None);
+    assert_eq!(
+        vm.commit_chain(third_commit)?,
+        [second_commit, third_commit]
+    );
+
+    // a cycle of parents is only followed until a commit repeats
+    std::fs::write(&parent_path, hex::encode(third_commit))
+        .expect("Writing the parent should succeed");
+    assert_eq!(
+        vm.commit_chain(third_commit)?,
+        [second_commit, third_commit]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn failed_commit_keeps_store_healthy() -> Result<(), Error> {
+    let tmp = tempfile::tempdir().expect("Creating a tempdir should succeed");
+    let vm = VM::new(tmp.path())?;
+    assert!(vm.is_healthy());
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let root = session.root();
+
+    // a file in the way of the commit's temporary directory makes writing it
+    // fail
+    let blocker_path =
+        vm.root_dir().join(format!(".tmp-{}", hex::encode(root)));
+    std::fs::write(&blocker_path, b"blocker")
+        .expect("Writing the blocker should succeed");
+
+    assert!(session.commit().is_err(), "The commit should fail");
+    assert!(vm.is_healthy(), "The store should survive a failed commit");
+
+    std::fs::remove_file(&blocker_path)
+        .expect("Removing the blocker should succeed");
+
+    let mut session = vm.session(SessionData::builder())?;
+    session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let commit = session.commit()?;
+
+    assert_eq!(commit, root);
+    assert_eq!(vm.commits(), vec![root]);
+    assert!(vm.is_healthy());
+
+    Ok(())
+}
+
+#[test]
+fn branch_commit() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+    let original = session.commit()?;
+
+    // committing the same state again yields the same commit
+    let session = vm.session(SessionData::builder().base(original))?;
+    assert_eq!(session.commit()?, original);
+    assert_eq!(vm.commits(), vec![original]);
+
+    // a branch off the original is mutated and committed separately
+    let mut session = vm.branch_session(original)?;
+    session.call::<_, ()>(counter_id, "increment", &(), LIMIT)?;
+    let branch = session.commit()?;
+    assert_ne!(branch, original);
+
+    vm.delete_commit(branch)?;
+
+    let mut session = vm.session(SessionData::builder().base(original))?;
+    assert_eq!(
+        session
+            .call::<_, i64>(counter_id, "read_value", &(), LIMIT)?
+            .data,
+        0xfc,
+        "The original commit should be unaffected by its branch"
+    );
+
+    Ok(())
+}
@@ -4,7 +4,9 @@
 //
 // Copyright (c) DUSK NETWORK. All rights reserved.
 
-use piecrust::{contract_bytecode, ContractData, Error, SessionData, VM};
+use piecrust::{
+    contract_bytecode, ContractData, Error, SessionData, MAX_REFUND_PCT, VM,
+};
 use piecrust_uplink::ContractError;
 
 const OWNER: [u8; 32] = [0u8; 32];
@@ -189,3 +191,403 @@ pub fn limit_and_spent() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+pub fn call_with_gas_pass_pct() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    const LIMIT: u64 = 10000;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let spender_id = session.deploy(
+        contract_bytecode!("spender"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let (_, _, _, default_called_limit, _) = session
+        .call::<_, (u64, u64, u64, u64, u64)>(
+            spender_id,
+            "get_limit_and_spent",
+            &(),
+            LIMIT,
+        )?
+        .data;
+
+    let (_, _, _, strict_called_limit, _) = session
+        .call_with_gas_pass_pct::<_, (u64, u64, u64, u64, u64)>(
+            spender_id,
+            "get_limit_and_spent",
+            &(),
+            50,
+            LIMIT,
+        )?
+        .data;
+
+    assert!(
+        strict_called_limit < default_called_limit,
+        "the nested limit should be lower with a stricter percentage"
+    );
+    assert!(
+        strict_called_limit <= LIMIT / 2,
+        "the nested limit should be at most half of the top-level limit"
+    );
+
+    // The default percentage is used again after the call
+    let (_, _, _, called_limit, _) = session
+        .call::<_, (u64, u64, u64, u64, u64)>(
+            spender_id,
+            "get_limit_and_spent",
+            &(),
+            LIMIT,
+        )?
+        .data;
+    assert_eq!(called_limit, default_called_limit);
+
+    Ok(())
+}
+
+#[test]
+pub fn block_limit() -> Result<(), Error> {
+    const CALL_LIMIT: u64 = 10_000;
+    const BLOCK_LIMIT: u64 = 25_000;
+
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let counter_id = session.deploy(
+        contract_bytecode!("counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    session.set_block_limit(BLOCK_LIMIT);
+
+    let mut total_spent = 0;
+    let mut n_calls = 0;
+
+    let err = loop {
+        match session.call::<_, ()>(counter_id, "increment", &(), CALL_LIMIT) {
+            Ok(receipt) => {
+                total_spent += receipt.gas_spent;
+                n_calls += 1;
+            }
+            Err(err) => break err,
+        }
+    };
+
+    assert!(
+        matches!(err, Error::BlockLimitExceeded { .. }),
+        "calls should stop because of the block limit"
+    );
+    assert!(n_calls > 0, "at least one call should fit in the block");
+    assert_eq!(session.block_spent(), total_spent);
+    assert!(total_spent + CALL_LIMIT > BLOCK_LIMIT);
+
+    Ok(())
+}
+
+#[test]
+pub fn call_records() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("fallible_counter"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let receipt = session.call::<_, ()>(id, "increment", &false, LIMIT)?;
+    let first_spent = receipt.gas_spent;
+
+    session
+        .call::<_, ()>(id, "increment", &true, LIMIT / 2)
+        .expect_err("the call should panic");
+
+    let receipt = session.call::<_, i64>(id, "read_value", &(), LIMIT / 4)?;
+    let third_spent = receipt.gas_spent;
+
+    let first = session.call_record(0).expect("first record should exist");
+    assert_eq!(first.gas_limit, LIMIT);
+    assert_eq!(first.gas_spent, first_spent);
+    assert!(first.success);
+
+    let second = session.call_record(1).expect("second record should exist");
+    assert_eq!(second.gas_limit, LIMIT / 2);
+    assert!(second.gas_spent > 0, "a failed call should still spend gas");
+    assert!(!second.success);
+
+    let third = session.call_record(2).expect("third record should exist");
+    assert_eq!(third.gas_limit, LIMIT / 4);
+    assert_eq!(third.gas_spent, third_spent);
+    assert!(third.success);
+
+    assert_eq!(session.call_record(3), None);
+
+    Ok(())
+}
+
+#[test]
+pub fn contract_error_round_trip() {
+    let errors = [
+        ContractError::Panic(String::from("I like spending")),
+        ContractError::OutOfGas,
+        ContractError::Initialization,
+        ContractError::Validation,
+        ContractError::Trap,
+        ContractError::Unknown,
+    ];
+
+    let mut codes = Vec::new();
+
+    for err in errors {
+        let mut buf = [0u8; 64];
+
+        let code = err.to_parts(&mut buf);
+        assert!(code < 0, "status codes should be negative");
+        assert_eq!(code, err.as_status_code());
+
+        assert_eq!(ContractError::from_parts(code, &buf), err);
+        codes.push(code);
+    }
+
+    codes.sort_unstable();
+    codes.dedup();
+    assert_eq!(codes.len(), 6, "status codes should be distinct");
+}
+
+#[test]
+pub fn contract_error_truncates_panic_message() {
+    let msg = "é".repeat(32);
+    let err = ContractError::Panic(msg.clone());
+
+    // a buffer with room for 27 bytes of message - in the middle of a
+    // two-byte character
+    let mut buf = [0u8; 31];
+    let code = err.to_parts(&mut buf);
+
+    match ContractError::from_parts(code, &buf) {
+        ContractError::Panic(truncated) => {
+            assert_eq!(truncated.len(), 26);
+            assert!(msg.starts_with(&truncated));
+        }
+        err => panic!("Expected a panic, got {err:?}"),
+    }
+}
+
+#[test]
+pub fn contract_error_from_malformed_parts() {
+    assert_eq!(ContractError::from_parts(-42, &[]), ContractError::Unknown);
+
+    // a panic message length beyond the end of the slice
+    let mut buf = [0u8; 8];
+    buf[..4].copy_from_slice(&64u32.to_le_bytes());
+    buf[4..].copy_from_slice(b"oops");
+    assert_eq!(
+        ContractError::from_parts(-1, &buf),
+        ContractError::Panic(String::from("oops"))
+    );
+    assert_eq!(
+        ContractError::from_parts(-1, &[]),
+        ContractError::Panic(String::new())
+    );
+}
+
+#[test]
+pub fn contract_error_to_short_parts() {
+    let err = ContractError::Panic(String::from("oops"));
+
+    // a slice too short to hold the message length is left untouched
+    let mut buf = [0xffu8; 3];
+    assert_eq!(err.to_parts(&mut buf), -1);
+    assert_eq!(buf, [0xff; 3]);
+
+    // a slice holding only the length gets an empty message
+    let mut buf = [0xffu8; 4];
+    assert_eq!(err.to_parts(&mut buf), -1);
+    assert_eq!(
+        ContractError::from_parts(-1, &buf),
+        ContractError::Panic(String::new())
+    );
+}
+
+#[test]
+pub fn contract_error_from_error() {
+    assert_eq!(
+        ContractError::from(Error::OutOfGas),
+        ContractError::OutOfGas
+    );
+    assert_eq!(
+        ContractError::from(Error::InitalizationError("init".into())),
+        ContractError::Initialization
+    );
+    assert_eq!(
+        ContractError::from(Error::ValidationError),
+        ContractError::Validation
+    );
+    assert_eq!(
+        ContractError::from(Error::MissingFeed),
+        ContractError::Unknown
+    );
+}
+
+#[test]
+pub fn spent_per_call() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        contract_bytecode!("fibonacci"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let mut spent = Vec::new();
+    for n in [4u32, 8, 12] {
+        let receipt = session.call::<u32, u64>(id, "nth", &n, LIMIT)?;
+        spent.push(receipt.gas_spent);
+    }
+
+    let spent_per_call = session.spent_per_call();
+    assert_eq!(spent_per_call, spent);
+    assert!(
+        spent_per_call.windows(2).all(|w| w[0] < w[1]),
+        "costlier calls should spend more gas"
+    );
+    assert_eq!(spent_per_call.iter().sum::<u64>(), session.block_spent());
+
+    Ok(())
+}
+
+#[test]
+pub fn remaining() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let spender_id = session.deploy(
+        contract_bytecode!("spender"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let mut iterations = Vec::new();
+    for threshold in [LIMIT / 2, LIMIT / 4] {
+        let receipt = session.call::<_, u64>(
+            spender_id,
+            "spend_until",
+            &threshold,
+            LIMIT,
+        )?;
+
+        assert!(receipt.data > 0, "should loop at least once");
+        assert!(
+            receipt.gas_spent > LIMIT - threshold,
+            "should spend down to the threshold"
+        );
+        iterations.push(receipt.data);
+    }
+
+    assert!(
+        iterations[0] < iterations[1],
+        "a lower threshold should allow more iterations"
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn gas_refund() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let spender_id = session.deploy(
+        contract_bytecode!("spender"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let receipt = session.call::<_, ()>(spender_id, "refund", &0u64, LIMIT)?;
+    let spent = receipt.gas_spent;
+    assert_eq!(session.gas_refund(), 0);
+
+    // a refund below the cap is subtracted in full
+    let receipt = session.call::<_, ()>(spender_id, "refund", &10u64, LIMIT)?;
+    assert_eq!(session.gas_refund(), 10);
+    assert_eq!(receipt.gas_spent, spent - 10);
+
+    // a refund above the cap is capped at a fraction of the gas spent
+    let max_refund = spent * MAX_REFUND_PCT / 100;
+    let receipt =
+        session.call::<_, ()>(spender_id, "refund", &u64::MAX, LIMIT)?;
+    assert_eq!(session.gas_refund(), max_refund);
+    assert_eq!(receipt.gas_spent, spent - max_refund);
+    assert!(receipt.gas_spent > 0, "should never spend less than zero");
+
+    Ok(())
+}
+
+#[test]
+pub fn icc_gas_fraction() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    const LIMIT: u64 = 10000;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let spender_id = session.deploy(
+        contract_bytecode!("spender"),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let (_, _, _, pct_called_limit, _) = session
+        .call_with_gas_pass_pct::<_, (u64, u64, u64, u64, u64)>(
+            spender_id,
+            "get_limit_and_spent",
+            &(),
+            50,
+            LIMIT,
+        )?
+        .data;
+
+    session.set_icc_gas_fraction(1, 2)?;
+
+    let (_, _, _, called_limit, _) = session
+        .call::<_, (u64, u64, u64, u64, u64)>(
+            spender_id,
+            "get_limit_and_spent",
+            &(),
+            LIMIT,
+        )?
+        .data;
+
+    assert_eq!(
+        called_limit, pct_called_limit,
+        "a fraction of 1/2 should forward exactly 50% of the remaining gas"
+    );
+
+    // the whole remaining gas is forwarded with a fraction of 1
+    session.set_icc_gas_fraction(1, 1)?;
+
+    let (_, spent_before, _, called_limit, _) = session
+        .call::<_, (u64, u64, u64, u64, u64)>(
+            spender_id,
+            "get_limit_and_spent",
+            &(),
+            LIMIT,
+        )?
+        .data;
+    assert_eq!(called_limit / 2, pct_called_limit);
+    assert!(called_limit <= LIMIT - spent_before);
+
+    assert!(session.set_icc_gas_fraction(1, 0).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright (c) DUSK NETWORK. All rights reserved.
+
+use piecrust::{contract_bytecode, Error, TestSessionBuilder, VM};
+
+const OWNER: [u8; 32] = [0u8; 32];
+const LIMIT: u64 = 1_000_000;
+
+#[test]
+fn call_through_c() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let (mut session, ids) = TestSessionBuilder::new(&vm)
+        .owner(OWNER)
+        .gas_limit(LIMIT)
+        .contract("counter", contract_bytecode!("counter"))
+        .contract("c_example", contract_bytecode!("c_example"))
+        .build()?;
+
+    assert_eq!(
+        session
+            .call::<_, i64>(
+                ids["c_example"],
+                "increment_and_read",
+                &ids["counter"],
+                LIMIT,
+            )?
+            .data,
+        0xfd
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_session_builder() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let (mut session, ids) = TestSessionBuilder::new(&vm)
+        .contract("counter", contract_bytecode!("counter"))
+        .contract("box", contract_bytecode!("box"))
+        .build()?;
+
+    assert_eq!(ids.len(), 2);
+
+    assert_eq!(
+        session
+            .call::<_, i64>(ids["counter"], "read_value", &(), LIMIT)?
+            .data,
+        0xfc
+    );
+
+    session.call::<i16, ()>(ids["box"], "set", &0x11, LIMIT)?;
+    assert_eq!(
+        session
+            .call::<_, Option<i16>>(ids["box"], "get", &(), LIMIT)?
+            .data,
+        Some(0x11)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_session_builder_deploy_error() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let result = TestSessionBuilder::new(&vm)
+        .contract("counter", contract_bytecode!("counter"))
+        .contract("counter_again", contract_bytecode!("counter"))
+        .build();
+
+    assert!(
+        matches!(result, Err(Error::InitalizationError(_))),
+        "The error of the failing deployment should be returned unchanged"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn fixed_time() -> Result<(), Error> {
+    const TIME: u64 = 1_700_000_000;
+
+    let vm = VM::ephemeral()?.with_fixed_time(TIME);
+
+    let (mut session, ids) = TestSessionBuilder::new(&vm)
+        .contract("everest", contract_bytecode!("everest"))
+        .build()?;
+
+    let time: u64 = session.call(ids["everest"], "get_time", &(), LIMIT)?.data;
+    assert_eq!(time, TIME);
+
+    Ok(())
+}
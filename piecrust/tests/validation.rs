@@ -9,6 +9,45 @@ use piecrust::{contract_bytecode, ContractData, Error, SessionData, VM};
 const OWNER: [u8; 32] = [0u8; 32];
 const LIMIT: u64 = 1_000_000;
 
+/// The offset of the data segment in [`data_module`], 8 bytes before the end
+/// of the 17th page.
+const DATA_OFFSET: usize = 17 * 0x10000 - 8;
+
+/// Returns a module with a memory of `pages` pages, and a 16 byte data segment
+/// spanning the 17th and 18th pages. Its `read` function copies the segment
+/// into the argument buffer:
+///
+/// ```wat
+/// (module
+///   (memory (export "memory") <pages>)
+///   (global (export "A") i32 (i32.const 0))
+///   (func (export "read") (param i32) (result i32)
+///     (i64.store (i32.const 0) (i64.load (i32.const 1114104)))
+///     (i64.store (i32.const 8) (i64.load (i32.const 1114112)))
+///     i32.const 16)
+///   (data (i32.const 1114104) "\01\02\03\04\05\06\07\08\09\0a\0b\0c\0d\0e\0f\10"))
+/// ```
+fn data_module(pages: u8) -> Vec<u8> {
+    let mut module = vec![
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f, // type section
+        0x03, 0x02, 0x01, 0x00, // function section
+        0x05, 0x03, 0x01, 0x00, pages, // memory section
+        0x06, 0x06, 0x01, 0x7f, 0x00, 0x41, 0x00, 0x0b, // global section
+        0x07, 0x15, 0x03, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00,
+        0x01, 0x41, 0x03, 0x00, 0x04, 0x72, 0x65, 0x61, 0x64, 0x00,
+        0x00, // export section
+        0x0a, 0x20, 0x01, 0x1e, 0x00, 0x41, 0x00, 0x41, 0xf8, 0xff, 0xc3, 0x00,
+        0x29, 0x03, 0x00, 0x37, 0x03, 0x00, 0x41, 0x08, 0x41, 0x80, 0x80, 0xc4,
+        0x00, 0x29, 0x03, 0x00, 0x37, 0x03, 0x00, 0x41, 0x10,
+        0x0b, // code section
+        0x0b, 0x19, 0x01, 0x00, 0x41, 0xf8, 0xff, 0xc3, 0x00, 0x0b,
+        0x10, // data section
+    ];
+    module.extend(1..=16);
+    module
+}
+
 #[test]
 fn out_of_bounds() -> Result<(), Error> {
     let vm = VM::ephemeral()?;
@@ -44,3 +83,52 @@ fn bad_contract() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn data_segment_across_pages() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    let id = session.deploy(
+        &data_module(20),
+        ContractData::builder().owner(OWNER),
+        LIMIT,
+    )?;
+
+    let receipt = session.call_raw(id, "read", Vec::new(), LIMIT)?;
+    assert_eq!(receipt.data, (1..=16).collect::<Vec<u8>>());
+
+    Ok(())
+}
+
+#[test]
+fn data_segment_out_of_bounds() -> Result<(), Error> {
+    let vm = VM::ephemeral()?;
+
+    let mut session = vm.session(SessionData::builder())?;
+
+    // the segment ends 8 bytes past the end of the 17 page memory
+    let err = session
+        .deploy(
+            &data_module(17),
+            ContractData::builder().owner(OWNER),
+            LIMIT,
+        )
+        .expect_err("Deploying a data segment out of bounds should error");
+
+    match err {
+        Error::MemoryAccessOutOfBounds {
+            offset,
+            len,
+            mem_len,
+        } => {
+            assert_eq!(offset, DATA_OFFSET);
+            assert_eq!(len, 16);
+            assert_eq!(mem_len, 17 * 0x10000);
+        }
+        err => panic!("Unexpected error: {err}"),
+    }
+
+    Ok(())
+}
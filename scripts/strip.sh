@@ -51,4 +51,8 @@ if [ ! -d "$EXTRACTED_DIR" ]; then
     tar -xzf "$ARTIFACT_PATH" -C "$EXTRACTED_DIR" --strip-components=1
 fi
 
-"$EXTRACTED_DIR"/wasm-tools strip -a "$1" -o "$2"
+# Only the sections emitted by the toolchain are removed, keeping any sections
+# embedded by the contract itself - such as the `piecrust-abi` section.
+STRIPPED_SECTIONS='^(\.debug_.*|name|producers|target_features|linking|reloc\..*|sourceMappingURL|external_debug_info)$'
+
+"$EXTRACTED_DIR"/wasm-tools strip -d "$STRIPPED_SECTIONS" "$1" -o "$2"